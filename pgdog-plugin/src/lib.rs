@@ -7,6 +7,7 @@ pub mod c_api;
 pub mod config;
 pub mod copy;
 pub mod input;
+pub mod logging;
 pub mod order_by;
 pub mod output;
 pub mod parameter;
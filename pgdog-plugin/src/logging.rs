@@ -0,0 +1,10 @@
+//! Logging settings inherited from pgDog.
+
+/// Environment variable set to pgDog's log format, `text` or `json`,
+/// before plugins are initialized.
+pub const LOG_FORMAT: &str = "PGDOG_LOG_FORMAT";
+
+/// pgDog writes logs as JSON, so the plugin should too.
+pub fn json() -> bool {
+    std::env::var(LOG_FORMAT).is_ok_and(|format| format == "json")
+}
@@ -11,12 +11,16 @@ pub mod pause;
 pub mod prelude;
 pub mod reconnect;
 pub mod reload;
+pub mod reset_queries;
 pub mod reset_query_cache;
 pub mod setup_schema;
 pub mod show_clients;
 pub mod show_config;
+pub mod show_latency;
+pub mod show_limits;
 pub mod show_peers;
 pub mod show_pools;
+pub mod show_queries;
 pub mod show_query_cache;
 pub mod show_servers;
 pub mod show_stats;
@@ -2,10 +2,11 @@
 
 use super::{
     pause::Pause, prelude::Message, reconnect::Reconnect, reload::Reload,
-    reset_query_cache::ResetQueryCache, setup_schema::SetupSchema, show_clients::ShowClients,
-    show_config::ShowConfig, show_peers::ShowPeers, show_pools::ShowPools,
-    show_query_cache::ShowQueryCache, show_servers::ShowServers, show_stats::ShowStats,
-    show_version::ShowVersion, Command, Error,
+    reset_queries::ResetQueries, reset_query_cache::ResetQueryCache, setup_schema::SetupSchema,
+    show_clients::ShowClients, show_config::ShowConfig, show_latency::ShowLatency,
+    show_limits::ShowLimits, show_peers::ShowPeers, show_pools::ShowPools,
+    show_queries::ShowQueries, show_query_cache::ShowQueryCache, show_servers::ShowServers,
+    show_stats::ShowStats, show_version::ShowVersion, Command, Error,
 };
 
 use tracing::debug;
@@ -18,10 +19,14 @@ pub enum ParseResult {
     Reload(Reload),
     ShowPools(ShowPools),
     ShowConfig(ShowConfig),
+    ShowLatency(ShowLatency),
+    ShowLimits(ShowLimits),
     ShowServers(ShowServers),
     ShowPeers(ShowPeers),
     ShowQueryCache(ShowQueryCache),
     ResetQueryCache(ResetQueryCache),
+    ShowQueries(ShowQueries),
+    ResetQueries(ResetQueries),
     ShowStats(ShowStats),
     ShowVersion(ShowVersion),
     SetupSchema(SetupSchema),
@@ -39,10 +44,14 @@ impl ParseResult {
             Reload(reload) => reload.execute().await,
             ShowPools(show_pools) => show_pools.execute().await,
             ShowConfig(show_config) => show_config.execute().await,
+            ShowLatency(show_latency) => show_latency.execute().await,
+            ShowLimits(show_limits) => show_limits.execute().await,
             ShowServers(show_servers) => show_servers.execute().await,
             ShowPeers(show_peers) => show_peers.execute().await,
             ShowQueryCache(show_query_cache) => show_query_cache.execute().await,
             ResetQueryCache(reset_query_cache) => reset_query_cache.execute().await,
+            ShowQueries(show_queries) => show_queries.execute().await,
+            ResetQueries(reset_queries) => reset_queries.execute().await,
             ShowStats(show_stats) => show_stats.execute().await,
             ShowVersion(show_version) => show_version.execute().await,
             SetupSchema(setup_schema) => setup_schema.execute().await,
@@ -60,10 +69,14 @@ impl ParseResult {
             Reload(reload) => reload.name(),
             ShowPools(show_pools) => show_pools.name(),
             ShowConfig(show_config) => show_config.name(),
+            ShowLatency(show_latency) => show_latency.name(),
+            ShowLimits(show_limits) => show_limits.name(),
             ShowServers(show_servers) => show_servers.name(),
             ShowPeers(show_peers) => show_peers.name(),
             ShowQueryCache(show_query_cache) => show_query_cache.name(),
             ResetQueryCache(reset_query_cache) => reset_query_cache.name(),
+            ShowQueries(show_queries) => show_queries.name(),
+            ResetQueries(reset_queries) => reset_queries.name(),
             ShowStats(show_stats) => show_stats.name(),
             ShowVersion(show_version) => show_version.name(),
             SetupSchema(setup_schema) => setup_schema.name(),
@@ -88,9 +101,12 @@ impl Parser {
                 "clients" => ParseResult::ShowClients(ShowClients::parse(&sql)?),
                 "pools" => ParseResult::ShowPools(ShowPools::parse(&sql)?),
                 "config" => ParseResult::ShowConfig(ShowConfig::parse(&sql)?),
+                "latency" => ParseResult::ShowLatency(ShowLatency::parse(&sql)?),
+                "limits" => ParseResult::ShowLimits(ShowLimits::parse(&sql)?),
                 "servers" => ParseResult::ShowServers(ShowServers::parse(&sql)?),
                 "peers" => ParseResult::ShowPeers(ShowPeers::parse(&sql)?),
                 "query_cache" => ParseResult::ShowQueryCache(ShowQueryCache::parse(&sql)?),
+                "queries" => ParseResult::ShowQueries(ShowQueries::parse(&sql)?),
                 "stats" => ParseResult::ShowStats(ShowStats::parse(&sql)?),
                 "version" => ParseResult::ShowVersion(ShowVersion::parse(&sql)?),
                 command => {
@@ -100,6 +116,7 @@ impl Parser {
             },
             "reset" => match iter.next().ok_or(Error::Syntax)?.trim() {
                 "query_cache" => ParseResult::ResetQueryCache(ResetQueryCache::parse(&sql)?),
+                "queries" => ParseResult::ResetQueries(ResetQueries::parse(&sql)?),
                 command => {
                     debug!("unknown admin show command: '{}'", command);
                     return Err(Error::Syntax);
@@ -16,7 +16,7 @@ impl Command for Reload {
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        let _ = reload(); // TODO: error check.
+        reload().map_err(|err| Error::Backend(Box::new(err)))?;
         Ok(vec![])
     }
 }
@@ -0,0 +1,22 @@
+//! RESET QUERIES.
+use crate::stats::queries::reset;
+
+use super::prelude::*;
+
+pub struct ResetQueries;
+
+#[async_trait]
+impl Command for ResetQueries {
+    fn name(&self) -> String {
+        "RESET QUERIES".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        reset();
+        Ok(vec![])
+    }
+}
@@ -0,0 +1,72 @@
+//! SHOW LATENCY.
+use std::time::Duration;
+
+use crate::backend::databases::databases;
+
+use super::prelude::*;
+
+/// Reported percentiles.
+const PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p95", 0.95), ("p99", 0.99)];
+
+pub struct ShowLatency;
+
+#[async_trait]
+impl Command for ShowLatency {
+    fn name(&self) -> String {
+        "SHOW LATENCY".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut fields = vec![
+            Field::text("database"),
+            Field::text("user"),
+            Field::numeric("shard"),
+            Field::text("host"),
+            Field::numeric("port"),
+        ];
+        for metric in ["query", "xact", "wait"] {
+            fields.push(Field::numeric(&format!("{}_count", metric)));
+            for (percentile, _) in PERCENTILES {
+                fields.push(Field::numeric(&format!("{}_{}", metric, percentile)));
+            }
+        }
+
+        let mut messages = vec![RowDescription::new(&fields).message()?];
+
+        for (user, cluster) in databases().all() {
+            for (shard_num, shard) in cluster.shards().iter().enumerate() {
+                for pool in shard.pools() {
+                    let addr = pool.addr();
+                    let histograms = pool.state().stats.histograms;
+
+                    let mut dr = DataRow::new();
+                    dr.add(user.database.as_str())
+                        .add(user.user.as_str())
+                        .add(shard_num)
+                        .add(addr.host.as_str())
+                        .add(addr.port.to_string().as_str());
+
+                    for histogram in [histograms.query, histograms.xact, histograms.wait] {
+                        dr.add(histogram.count());
+                        for (_, quantile) in PERCENTILES {
+                            dr.add(ms(histogram.quantile(quantile)));
+                        }
+                    }
+
+                    messages.push(dr.message()?);
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+/// Milliseconds, rounded to microseconds.
+fn ms(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
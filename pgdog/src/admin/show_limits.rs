@@ -0,0 +1,63 @@
+//! SHOW LIMITS.
+use crate::frontend::rate_limit::{limits, RateLimit};
+
+use super::prelude::*;
+
+pub struct ShowLimits;
+
+#[async_trait]
+impl Command for ShowLimits {
+    fn name(&self) -> String {
+        "SHOW LIMITS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let fields = vec![
+            Field::text("limit"),
+            Field::text("database"),
+            Field::text("user"),
+            Field::text("fingerprint"),
+            Field::numeric("rate"),
+            Field::numeric("tokens"),
+            Field::numeric("allowed"),
+            Field::numeric("delayed"),
+            Field::numeric("rejected"),
+        ];
+
+        let mut messages = vec![RowDescription::new(&fields).message()?];
+
+        for stats in limits() {
+            let (limit, database, user, fingerprint) = match &stats.limit {
+                RateLimit::Query(user) => ("query", user.database.as_str(), user.user.as_str(), ""),
+                RateLimit::Transaction(user) => (
+                    "transaction",
+                    user.database.as_str(),
+                    user.user.as_str(),
+                    "",
+                ),
+                RateLimit::Fingerprint(fingerprint) => {
+                    ("fingerprint", "", "", fingerprint.as_str())
+                }
+            };
+
+            let mut dr = DataRow::new();
+            dr.add(limit)
+                .add(database)
+                .add(user)
+                .add(fingerprint)
+                .add(stats.rate as u64)
+                .add((stats.tokens * 100.0).round() / 100.0)
+                .add(stats.allowed)
+                .add(stats.delayed)
+                .add(stats.rejected);
+
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
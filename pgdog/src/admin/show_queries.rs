@@ -0,0 +1,69 @@
+//! SHOW QUERIES.
+
+use crate::stats::queries::queries;
+
+use super::prelude::*;
+
+pub struct ShowQueries {
+    filter: String,
+}
+
+#[async_trait]
+impl Command for ShowQueries {
+    fn name(&self) -> String {
+        "SHOW QUERIES".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        Ok(Self {
+            filter: sql
+                .split(" ")
+                .skip(2)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_lowercase())
+                .collect::<Vec<String>>()
+                .join(" "),
+        })
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("fingerprint"),
+            Field::text("query"),
+            Field::numeric("calls"),
+            Field::numeric("total_time"),
+            Field::numeric("mean_time"),
+            Field::numeric("rows"),
+            Field::numeric("errors"),
+            Field::text("shards"),
+        ])
+        .message()?];
+
+        for (fingerprint, stats) in queries() {
+            if !self.filter.is_empty() && !stats.query.to_lowercase().contains(&self.filter) {
+                continue;
+            }
+
+            let shards = stats
+                .shards
+                .iter()
+                .map(|shard| shard.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let mut data_row = DataRow::new();
+            data_row
+                .add(format!("{:016x}", fingerprint))
+                .add(stats.query.as_str())
+                .add(stats.calls)
+                .add(stats.total_time.as_secs_f64() * 1000.0)
+                .add(stats.mean_time().as_secs_f64() * 1000.0)
+                .add(stats.rows)
+                .add(stats.errors)
+                .add(shards);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
@@ -3,6 +3,7 @@ use crate::backend::{
     databases::databases,
     pool::{stats::Counts, Stats},
 };
+use crate::frontend::client::limit::rejected;
 
 use super::prelude::*;
 
@@ -43,6 +44,7 @@ impl Command for ShowStats {
                 })
                 .collect::<Vec<Field>>(),
         );
+        fields.push(Field::numeric("total_client_rejected_count"));
 
         let mut messages = vec![RowDescription::new(&fields).message()?];
 
@@ -50,6 +52,8 @@ impl Command for ShowStats {
 
         for (user, cluster) in clusters {
             let shards = cluster.shards();
+            // Clients are rejected before they use a shard.
+            let rejected = rejected(&user);
 
             for (shard_num, shard) in shards.iter().enumerate() {
                 let pools = shard.pools();
@@ -75,6 +79,7 @@ impl Command for ShowStats {
                         .add(0_i64)
                         .add(0_i64);
                 }
+                dr.add(rejected);
 
                 messages.push(dr.message()?);
             }
@@ -9,18 +9,22 @@ use rand::Rng;
 use crate::net::messages::Authentication;
 
 #[derive(Debug, Clone)]
-pub struct Client<'a> {
-    password: &'a str,
-    user: &'a str,
+pub struct Client {
+    /// MD5 hash of the password and user name, as stored by PostgreSQL.
+    hash: String,
     salt: [u8; 4],
 }
 
-impl<'a> Client<'a> {
+impl Client {
     /// Create new MD5 client.
-    pub fn new(user: &'a str, password: &'a str) -> Self {
+    pub fn new(user: &str, password: &str) -> Self {
+        Self::hashed(&hash(user, password))
+    }
+
+    /// Create new MD5 client using the hash stored by PostgreSQL, e.g. `md5<hex>`.
+    pub fn hashed(hash: &str) -> Self {
         Self {
-            password,
-            user,
+            hash: hash.to_owned(),
             salt: rand::thread_rng().gen(),
         }
     }
@@ -32,16 +36,30 @@ impl<'a> Client<'a> {
 
     /// Check encrypted password against what we have.
     pub fn check(&self, encrypted: &str) -> bool {
-        let mut md5 = Context::new();
-        md5.consume(self.password);
-        md5.consume(self.user);
-        let first_pass = md5.compute();
+        encrypted == encrypt(&self.hash, &self.salt)
+    }
+}
 
-        let mut md5 = Context::new();
-        md5.consume(format!("{:x}", first_pass));
-        md5.consume(self.salt);
-        let password = format!("md5{:x}", md5.compute());
+/// MD5 hash of the password, as stored by PostgreSQL.
+pub fn hash(user: &str, password: &str) -> String {
+    let mut md5 = Context::new();
+    md5.consume(password);
+    md5.consume(user);
+    format!("md5{:x}", md5.compute())
+}
 
-        encrypted == password
-    }
+/// Response to the MD5 challenge, given the stored hash and the salt.
+pub fn encrypt(hash: &str, salt: &[u8]) -> String {
+    let mut md5 = Context::new();
+    md5.consume(hash.strip_prefix("md5").unwrap_or(hash));
+    md5.consume(salt);
+    format!("md5{:x}", md5.compute())
+}
+
+/// This looks like a password hashed with MD5.
+pub fn is_hash(value: &str) -> bool {
+    value
+        .strip_prefix("md5")
+        .map(|hash| hash.len() == 32 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false)
 }
@@ -1,4 +1,65 @@
 //! PostgreSQL authentication mechanisms.
 
+use serde::{Deserialize, Serialize};
+
 pub mod md5;
 pub mod scram;
+
+use scram::Verifier;
+
+/// User's password, as stored in PostgreSQL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Secret {
+    /// Plain text password.
+    Plain(String),
+    /// SCRAM-SHA-256 verifier.
+    Scram(Verifier),
+    /// MD5 hash of the password and user name.
+    Md5(String),
+}
+
+impl Secret {
+    /// Parse the password, detecting if it's hashed.
+    pub fn parse(value: &str) -> Self {
+        if let Some(verifier) = Verifier::parse(value) {
+            Self::Scram(verifier)
+        } else if md5::is_hash(value) {
+            Self::Md5(value.to_owned())
+        } else {
+            Self::Plain(value.to_owned())
+        }
+    }
+}
+
+/// Credentials used to connect to PostgreSQL on behalf of a client
+/// authenticated with passthrough authentication.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Passthrough {
+    /// Plain text password.
+    Password(String),
+    /// Keys obtained from the client's SCRAM-SHA-256 proof.
+    Scram {
+        client_key: Vec<u8>,
+        server_key: Vec<u8>,
+    },
+    /// MD5 hash of the password and user name.
+    Md5(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let verifier = Verifier::new("pgdog");
+        assert_eq!(
+            Secret::parse(&verifier.to_string()),
+            Secret::Scram(verifier)
+        );
+        let hash = md5::hash("pgdog", "pgdog");
+        assert_eq!(Secret::parse(&hash), Secret::Md5(hash.clone()));
+        assert_eq!(Secret::parse("pgdog"), Secret::Plain("pgdog".into()));
+        assert_eq!(Secret::parse("md5short"), Secret::Plain("md5short".into()));
+    }
+}
@@ -1,7 +1,12 @@
 //! SCRAM-SHA-256 client.
 
-use super::Error;
+use super::{
+    verifier::{hmac, sha256, xor},
+    Error,
+};
 
+use base64::prelude::*;
+use rand::Rng;
 use scram::{
     client::{ClientFinal, ServerFinal, ServerFirst},
     ScramClient,
@@ -14,9 +19,20 @@ enum State<'a> {
     ServerFinal(ServerFinal),
 }
 
+/// Exchange using keys instead of the password.
+#[derive(Default)]
+struct Keys {
+    client_key: Vec<u8>,
+    server_key: Vec<u8>,
+    client_first: String,
+    nonce: String,
+    auth_message: String,
+}
+
 /// SASL SCRAM client.
 pub struct Client<'a> {
     state: Option<State<'a>>,
+    keys: Option<Keys>,
 }
 
 impl<'a> Client<'a> {
@@ -24,11 +40,32 @@ impl<'a> Client<'a> {
     pub fn new(user: &'a str, password: &'a str) -> Self {
         Self {
             state: Some(State::Initial(ScramClient::new(user, password, None))),
+            keys: None,
+        }
+    }
+
+    /// Create new SCRAM client using the client and server keys,
+    /// obtained by authenticating a client with passthrough authentication.
+    pub fn passthrough(client_key: &[u8], server_key: &[u8]) -> Self {
+        Self {
+            state: None,
+            keys: Some(Keys {
+                client_key: client_key.to_vec(),
+                server_key: server_key.to_vec(),
+                ..Default::default()
+            }),
         }
     }
 
     /// Client first message.
     pub fn first(&mut self) -> Result<String, Error> {
+        if let Some(ref mut keys) = self.keys {
+            keys.nonce = BASE64_STANDARD.encode(rand::thread_rng().gen::<[u8; 18]>());
+            // PostgreSQL uses the user from the startup message.
+            keys.client_first = format!("n=,r={}", keys.nonce);
+            return Ok(format!("n,,{}", keys.client_first));
+        }
+
         let (scram, client_first) = match self.state.take() {
             Some(State::Initial(scram)) => scram.client_first(),
             _ => return Err(Error::OutOfOrder),
@@ -39,6 +76,18 @@ impl<'a> Client<'a> {
 
     /// Handle server first message.
     pub fn server_first(&mut self, message: &str) -> Result<(), Error> {
+        if let Some(ref mut keys) = self.keys {
+            let nonce = message
+                .split(',')
+                .find_map(|attr| attr.strip_prefix("r="))
+                .filter(|nonce| nonce.starts_with(&keys.nonce))
+                .ok_or(Error::AuthenticationFailed)?;
+            let without_proof = format!("c=biws,r={}", nonce);
+            keys.auth_message = format!("{},{},{}", keys.client_first, message, without_proof);
+            keys.nonce = nonce.to_string();
+            return Ok(());
+        }
+
         let scram = match self.state.take() {
             Some(State::First(scram)) => scram.handle_server_first(message)?,
             _ => return Err(Error::OutOfOrder),
@@ -49,6 +98,16 @@ impl<'a> Client<'a> {
 
     /// Client last message.
     pub fn last(&mut self) -> Result<String, Error> {
+        if let Some(ref keys) = self.keys {
+            let signature = hmac(&sha256(&keys.client_key), keys.auth_message.as_bytes());
+            let proof = xor(&keys.client_key, &signature);
+            return Ok(format!(
+                "c=biws,r={},p={}",
+                keys.nonce,
+                BASE64_STANDARD.encode(proof)
+            ));
+        }
+
         let (scram, client_final) = match self.state.take() {
             Some(State::Final(scram)) => scram.client_final(),
             _ => return Err(Error::OutOfOrder),
@@ -59,6 +118,15 @@ impl<'a> Client<'a> {
 
     /// Verify server last message.
     pub fn server_last(&mut self, message: &str) -> Result<(), Error> {
+        if let Some(ref keys) = self.keys {
+            let signature = hmac(&keys.server_key, keys.auth_message.as_bytes());
+            return if message.strip_prefix("v=") == Some(&BASE64_STANDARD.encode(signature)) {
+                Ok(())
+            } else {
+                Err(Error::AuthenticationFailed)
+            };
+        }
+
         match self.state.take() {
             Some(State::ServerFinal(scram)) => scram.handle_server_final(message)?,
             _ => return Err(Error::OutOfOrder),
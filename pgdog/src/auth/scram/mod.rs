@@ -3,7 +3,9 @@ pub mod client;
 pub mod error;
 pub mod server;
 pub mod state;
+pub mod verifier;
 
 pub use client::Client;
 pub use error::Error;
 pub use server::Server;
+pub use verifier::Verifier;
@@ -4,139 +4,57 @@ use crate::frontend::Error;
 use crate::net::messages::*;
 use crate::net::Stream;
 
-use scram::server::ClientFinal;
-use tracing::error;
-
-use rand::Rng;
-use scram::{
-    hash_password, AuthenticationProvider, AuthenticationStatus, PasswordInfo, ScramServer,
-};
-use std::num::NonZeroU32;
-
-enum Provider {
-    Plain(UserPassword),
-    Hashed(HashedPassword),
-}
-
-/// Derive the SCRAM-SHA-256 auth
-/// from a plain text password.
-#[derive(Clone)]
-pub struct UserPassword {
-    password: String,
-}
-
-/// Used a prehashed password obtained from
-/// pg_shadow. This allows operators not to store
-/// passwords in plain text in the config.
-///
-/// TODO: Doesn't work yet. I'm not sure how to actually
-/// implement this.
-#[derive(Clone)]
-pub struct HashedPassword {
-    hash: String,
-}
-
-enum Scram {
-    Plain(ScramServer<UserPassword>),
-    Hashed(ScramServer<HashedPassword>),
-}
-
-enum ScramFinal<'a> {
-    Plain(ClientFinal<'a, UserPassword>),
-    Hashed(ClientFinal<'a, HashedPassword>),
-}
-
 use base64::prelude::*;
+use rand::Rng;
+use tracing::error;
 
-impl AuthenticationProvider for UserPassword {
-    fn get_password_for(&self, _user: &str) -> Option<PasswordInfo> {
-        let iterations = 4096;
-        let salt = rand::thread_rng().gen::<[u8; 16]>().to_vec();
-        let hash = hash_password(&self.password, NonZeroU32::new(iterations).unwrap(), &salt);
-        Some(PasswordInfo::new(hash.to_vec(), iterations as u16, salt))
-    }
-}
-
-impl AuthenticationProvider for HashedPassword {
-    fn get_password_for(&self, _user: &str) -> Option<PasswordInfo> {
-        let mut parts = self.hash.split("$");
-        if let Some(algo) = parts.next() {
-            if algo != "SCRAM-SHA-256" {
-                return None;
-            }
-        } else {
-            return None;
-        }
-
-        let (mut salt, mut iter) = (None, None);
-        if let Some(iter_salt) = parts.next() {
-            let mut split = iter_salt.split(":");
-            let maybe_iter = split.next().map(|iter| iter.parse::<u16>());
-            let maybe_salt = split.next().map(|salt| BASE64_STANDARD.decode(salt));
-
-            if let Some(Ok(num)) = maybe_iter {
-                iter = Some(num);
-            }
-
-            if let Some(Ok(s)) = maybe_salt {
-                salt = Some(s);
-            }
-        };
-
-        let hashes = parts.next().map(|hashes| hashes.split(":"));
-
-        if let Some(hashes) = hashes {
-            if let Some(last) = hashes.last() {
-                if let Ok(hash) = BASE64_STANDARD.decode(last) {
-                    if let Some(iter) = iter {
-                        if let Some(salt) = salt {
-                            return Some(PasswordInfo::new(hash, iter, salt));
-                        }
-                    }
-                }
-            }
-        }
-
-        None
-    }
+use super::Verifier;
+
+/// Messages exchanged so far.
+#[derive(Default)]
+struct Exchange {
+    /// GS2 header sent by the client.
+    header: String,
+    /// Client first message, without the GS2 header.
+    client_first: String,
+    /// Our reply to it.
+    server_first: String,
+    /// Client and server nonce.
+    nonce: String,
 }
 
 /// SCRAM-SHA-256 server that handles
 /// authenticating clients.
 pub struct Server {
-    provider: Provider,
-    client_response: String,
+    verifier: Verifier,
+    exchange: Exchange,
+    client_key: Option<Vec<u8>>,
 }
 
 impl Server {
-    /// Create new SCRAM server.
+    /// Create new SCRAM server for a plain text password.
     pub fn new(password: &str) -> Self {
-        Self {
-            provider: Provider::Plain(UserPassword {
-                password: password.to_owned(),
-            }),
-            client_response: String::new(),
-        }
+        Self::verifier(Verifier::new(password))
     }
 
-    pub fn hashed(hash: &str) -> Self {
+    /// Create new SCRAM server using a verifier, e.g. obtained from `pg_shadow`.
+    pub fn verifier(verifier: Verifier) -> Self {
         Self {
-            provider: Provider::Hashed(HashedPassword {
-                hash: hash.to_owned(),
-            }),
-            client_response: String::new(),
+            verifier,
+            exchange: Exchange::default(),
+            client_key: None,
         }
     }
 
-    /// Handle authentication.
-    pub async fn handle(mut self, stream: &mut Stream) -> Result<bool, Error> {
-        let scram = match self.provider {
-            Provider::Plain(plain) => Scram::Plain(ScramServer::new(plain)),
-            Provider::Hashed(hashed) => Scram::Hashed(ScramServer::new(hashed)),
-        };
-
-        let mut scram_client = None;
+    /// Client key recovered from the client's proof, once authenticated.
+    ///
+    /// It can be used to authenticate with PostgreSQL on behalf of the client.
+    pub fn client_key(&self) -> Option<&[u8]> {
+        self.client_key.as_deref()
+    }
 
+    /// Handle authentication.
+    pub async fn handle(&mut self, stream: &mut Stream) -> Result<bool, Error> {
         loop {
             let message = stream.read().await?;
             match message.code() {
@@ -144,49 +62,27 @@ impl Server {
                     let password = Password::from_bytes(message.to_bytes()?)?;
 
                     match password {
-                        Password::SASLInitialResponse { response, .. } => {
-                            self.client_response = response;
-                            let reply = match scram {
-                                Scram::Plain(ref plain) => {
-                                    let server =
-                                        plain.handle_client_first(&self.client_response)?;
-                                    let (client, reply) = server.server_first();
-                                    scram_client = Some(ScramFinal::Plain(client));
-                                    reply
-                                }
-                                Scram::Hashed(ref hashed) => {
-                                    let server =
-                                        hashed.handle_client_first(&self.client_response)?;
-                                    let (client, reply) = server.server_first();
-                                    scram_client = Some(ScramFinal::Hashed(client));
-                                    reply
-                                }
+                        Password::SASLInitialResponse { name, response } => {
+                            if name != "SCRAM-SHA-256" {
+                                return Ok(false);
+                            }
+
+                            let Some(reply) = self.server_first(&response) else {
+                                return Ok(false);
                             };
-                            let reply = Authentication::SaslContinue(reply);
-                            stream.send_flush(reply).await?;
+                            stream
+                                .send_flush(Authentication::SaslContinue(reply))
+                                .await?;
                         }
 
                         Password::PasswordMessage { response } => {
-                            if let Some(scram_client) = scram_client {
-                                let server_final = match scram_client {
-                                    ScramFinal::Plain(plain) => {
-                                        plain.handle_client_final(&response)?
-                                    }
-                                    ScramFinal::Hashed(hashed) => {
-                                        hashed.handle_client_final(&response)?
-                                    }
-                                };
-                                let (status, reply) = server_final.server_final();
-
-                                match status {
-                                    AuthenticationStatus::Authenticated => {
-                                        stream.send(Authentication::SaslFinal(reply)).await?;
-                                        return Ok(true);
-                                    }
-
-                                    _ => return Ok(false),
+                            return match self.server_final(&response) {
+                                Some(reply) => {
+                                    stream.send(Authentication::SaslFinal(reply)).await?;
+                                    Ok(true)
                                 }
-                            }
+                                None => Ok(false),
+                            };
                         }
                     }
                 }
@@ -201,18 +97,116 @@ impl Server {
             }
         }
     }
+
+    /// Handle client first message and get the server first message.
+    fn server_first(&mut self, client_first: &str) -> Option<String> {
+        // Channel binding is not supported.
+        let (header, client_first) = match client_first.split_at_checked(3)? {
+            (header @ ("n,," | "y,,"), rest) => (header, rest),
+            _ => return None,
+        };
+
+        let client_nonce = client_first
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("r="))?;
+
+        let server_nonce = BASE64_STANDARD.encode(rand::thread_rng().gen::<[u8; 18]>());
+        let nonce = format!("{}{}", client_nonce, server_nonce);
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            BASE64_STANDARD.encode(&self.verifier.salt),
+            self.verifier.iterations
+        );
+
+        self.exchange = Exchange {
+            header: header.to_string(),
+            client_first: client_first.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+
+        Some(server_first)
+    }
+
+    /// Verify client final message and get the server final message.
+    fn server_final(&mut self, client_final: &str) -> Option<String> {
+        let (without_proof, proof) = client_final.rsplit_once(",p=")?;
+        let proof = BASE64_STANDARD.decode(proof).ok()?;
+
+        let mut channel_binding = None;
+        let mut nonce = None;
+        for attr in without_proof.split(',') {
+            if let Some(value) = attr.strip_prefix("c=") {
+                channel_binding = Some(value);
+            } else if let Some(value) = attr.strip_prefix("r=") {
+                nonce = Some(value);
+            }
+        }
+
+        let header = BASE64_STANDARD.encode(&self.exchange.header);
+        if channel_binding? != header || nonce? != self.exchange.nonce {
+            return None;
+        }
+
+        let auth_message = format!(
+            "{},{},{}",
+            self.exchange.client_first, self.exchange.server_first, without_proof
+        );
+        self.client_key = Some(self.verifier.verify(&auth_message, &proof)?);
+
+        Some(format!(
+            "v={}",
+            BASE64_STANDARD.encode(self.verifier.server_signature(&auth_message))
+        ))
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::Client;
     use super::*;
 
+    fn authenticate(server: &mut Server, password: &str) -> bool {
+        let mut client = Client::new("user", password);
+        let server_first = server.server_first(&client.first().unwrap()).unwrap();
+        client.server_first(&server_first).unwrap();
+        match server.server_final(&client.last().unwrap()) {
+            Some(server_final) => {
+                client.server_last(&server_final).unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
     #[test]
-    fn test_hashed_password() {
-        let hash = "SCRAM-SHA-256$4096:lApbvrTR0W7WOZLcVrbz0A==$O+AwRnblFCJwEezpaozQfC6iKmbJFHQ7+0WZBsR+hFU=:wWjPizZvFjc5jmIkdN/EsuLGz/9FMjOhJ7IHxZI8eqE="
-            .to_string();
-        let hashed = HashedPassword { hash };
-        let info = hashed.get_password_for("user");
-        assert!(info.is_some());
+    fn test_scram_server() {
+        let mut server = Server::new("pgdog");
+        assert!(authenticate(&mut server, "pgdog"));
+        assert!(server.client_key().is_some());
+        assert!(!authenticate(&mut Server::new("pgdog"), "wrong"));
+
+        let verifier = Verifier::new("secret");
+        let mut server = Server::verifier(verifier.clone());
+        assert!(authenticate(&mut server, "secret"));
+
+        // Client key is the same every time for the same verifier.
+        let client_key = server.client_key().unwrap().to_vec();
+        let mut server = Server::verifier(verifier.clone());
+        assert!(authenticate(&mut server, "secret"));
+        assert_eq!(server.client_key().unwrap(), client_key);
+
+        // Authenticate with another server using the client key.
+        let mut server = Server::verifier(verifier.clone());
+        let mut client = Client::passthrough(&client_key, &verifier.server_key);
+        let server_first = server.server_first(&client.first().unwrap()).unwrap();
+        client.server_first(&server_first).unwrap();
+        let server_final = server.server_final(&client.last().unwrap()).unwrap();
+        client.server_last(&server_final).unwrap();
+
+        let mut server = Server::verifier(verifier);
+        assert!(!authenticate(&mut server, "wrong"));
+        assert!(server.client_key().is_none());
     }
 }
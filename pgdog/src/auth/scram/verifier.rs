@@ -0,0 +1,147 @@
+//! SCRAM-SHA-256 verifier, as stored by PostgreSQL in `pg_authid`.
+
+use std::num::NonZeroU32;
+
+use base64::prelude::*;
+use hmac::{Hmac, KeyInit, Mac};
+use rand::Rng;
+use scram::hash_password;
+use sha2::{Digest, Sha256};
+
+/// Number of PBKDF2 iterations used by PostgreSQL by default.
+const ITERATIONS: u32 = 4096;
+
+/// SCRAM-SHA-256 verifier.
+///
+/// Clients can be authenticated using only the stored key and the server key,
+/// so the password itself doesn't need to be known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verifier {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+impl Verifier {
+    /// Parse verifier in the `SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>` format.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.strip_prefix("SCRAM-SHA-256$")?;
+        let (iterations_salt, keys) = value.split_once('$')?;
+        let (iterations, salt) = iterations_salt.split_once(':')?;
+        let (stored_key, server_key) = keys.split_once(':')?;
+
+        let iterations = iterations.parse().ok().filter(|i| *i > 0)?;
+        let salt = BASE64_STANDARD.decode(salt).ok()?;
+        let stored_key = BASE64_STANDARD.decode(stored_key).ok()?;
+        let server_key = BASE64_STANDARD.decode(server_key).ok()?;
+
+        if stored_key.len() != 32 || server_key.len() != 32 {
+            return None;
+        }
+
+        Some(Self {
+            iterations,
+            salt,
+            stored_key,
+            server_key,
+        })
+    }
+
+    /// Create verifier for the password using a random salt.
+    pub fn new(password: &str) -> Self {
+        let salt = rand::thread_rng().gen::<[u8; 16]>().to_vec();
+        Self::derive(password, &salt, ITERATIONS)
+    }
+
+    /// Create verifier for the password with the given salt and number of iterations.
+    pub fn derive(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+        let salted_password = hash_password(password, iterations, salt);
+        let client_key = hmac(salted_password.as_ref(), b"Client Key");
+
+        Self {
+            iterations: iterations.get(),
+            salt: salt.to_vec(),
+            stored_key: sha256(&client_key),
+            server_key: hmac(salted_password.as_ref(), b"Server Key"),
+        }
+    }
+
+    /// Check that the password matches this verifier.
+    pub fn matches(&self, password: &str) -> bool {
+        Self::derive(password, &self.salt, self.iterations) == *self
+    }
+
+    /// Verify the client's proof and return the client key if it's correct.
+    pub fn verify(&self, auth_message: &str, proof: &[u8]) -> Option<Vec<u8>> {
+        let signature = hmac(&self.stored_key, auth_message.as_bytes());
+        if proof.len() != signature.len() {
+            return None;
+        }
+
+        let client_key = xor(proof, &signature);
+        if sha256(&client_key) == self.stored_key {
+            Some(client_key)
+        } else {
+            None
+        }
+    }
+
+    /// Signature proving to the client that we know the server key.
+    pub fn server_signature(&self, auth_message: &str) -> Vec<u8> {
+        hmac(&self.server_key, auth_message.as_bytes())
+    }
+}
+
+impl std::fmt::Display for Verifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SCRAM-SHA-256${}:{}${}:{}",
+            self.iterations,
+            BASE64_STANDARD.encode(&self.salt),
+            BASE64_STANDARD.encode(&self.stored_key),
+            BASE64_STANDARD.encode(&self.server_key)
+        )
+    }
+}
+
+/// HMAC-SHA-256.
+pub fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// SHA-256.
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// XOR two byte strings of equal length.
+pub fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verifier() {
+        // Generated by PostgreSQL for password "pgdog".
+        let stored = "SCRAM-SHA-256$4096:lApbvrTR0W7WOZLcVrbz0A==$O+AwRnblFCJwEezpaozQfC6iKmbJFHQ7+0WZBsR+hFU=:wWjPizZvFjc5jmIkdN/EsuLGz/9FMjOhJ7IHxZI8eqE=";
+        let verifier = Verifier::parse(stored).unwrap();
+        assert_eq!(verifier.iterations, 4096);
+        assert_eq!(verifier.to_string(), stored);
+
+        let verifier = Verifier::new("secret");
+        assert!(verifier.matches("secret"));
+        assert!(!verifier.matches("wrong"));
+        assert_eq!(Verifier::parse(&verifier.to_string()), Some(verifier));
+
+        assert!(Verifier::parse("md5a3556571e93b0d20722ba62be61e8c2d").is_none());
+        assert!(Verifier::parse("SCRAM-SHA-256$4096:c2FsdA==$bm9wZQ==:bm9wZQ==").is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! Look up user passwords on the server for passthrough authentication.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::{auth::Secret, config::config, net::messages::DataRow};
+
+use super::{
+    databases::{databases, ToUser, User},
+    pool::Request,
+    Error,
+};
+
+/// Maximum number of cached passwords.
+const CACHE_SIZE: usize = 4096;
+/// Users that don't exist are cached for at most this long.
+const MISS_TTL: Duration = Duration::from_secs(1);
+
+static CACHE: Lazy<Mutex<Cache>> = Lazy::new(|| Mutex::new(Cache::default()));
+
+/// Passwords fetched from the server and when.
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<User, (Option<Secret>, Instant)>,
+}
+
+impl Cache {
+    /// Get a password if it hasn't expired yet.
+    fn get(&self, key: &User, ttl: Duration) -> Option<Option<Secret>> {
+        self.entries
+            .get(key)
+            .filter(|(secret, fetched_at)| fetched_at.elapsed() < Self::ttl(secret, ttl))
+            .map(|(secret, _)| secret.clone())
+    }
+
+    /// Cache a password, evicting expired entries or the oldest one if the cache is full.
+    fn insert(&mut self, key: User, secret: Option<Secret>, ttl: Duration) {
+        if self.entries.len() >= CACHE_SIZE && !self.entries.contains_key(&key) {
+            self.entries
+                .retain(|_, (secret, fetched_at)| fetched_at.elapsed() < Self::ttl(secret, ttl));
+        }
+
+        if self.entries.len() >= CACHE_SIZE && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, fetched_at))| *fetched_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(key, (secret, Instant::now()));
+    }
+
+    fn ttl(secret: &Option<Secret>, ttl: Duration) -> Duration {
+        if secret.is_some() {
+            ttl
+        } else {
+            ttl.min(MISS_TTL)
+        }
+    }
+}
+
+/// Row returned by `auth_query`.
+struct Password {
+    password: Option<String>,
+}
+
+impl From<DataRow> for Password {
+    fn from(value: DataRow) -> Self {
+        Self {
+            password: value.get_text(1).filter(|password| !password.is_empty()),
+        }
+    }
+}
+
+/// Get the password of a user, as stored by the server.
+///
+/// Returns `None` if the user doesn't exist or has no password.
+/// Results are cached for `auth_query_ttl`, missing users for at most a second.
+pub async fn secret(user: &str, database: &str) -> Result<Option<Secret>, Error> {
+    let key = (user, database).to_user();
+    let config = config();
+    let ttl = config.config.general.auth_query_ttl();
+
+    if let Some(secret) = CACHE.lock().get(&key, ttl) {
+        return Ok(secret);
+    }
+
+    let pool = databases()
+        .auth_pool(database)
+        .ok_or(Error::NoDatabase(key.clone()))?;
+    let mut server = pool.get(&Request::default()).await?;
+
+    let secret = server
+        .fetch_all_params::<Password>(&config.config.general.auth_query, &[user])
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|row| row.password)
+        .map(|password| Secret::parse(&password));
+
+    debug!(
+        "fetched password for \"{}\" [{}]: {}",
+        user,
+        database,
+        if secret.is_some() {
+            "found"
+        } else {
+            "not found"
+        }
+    );
+
+    CACHE.lock().insert(key, secret.clone(), ttl);
+
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let ttl = Duration::from_secs(300);
+        let secret = Secret::parse("md5a3556571e93b0d20722ba62be61e8c2d");
+        let mut cache = Cache::default();
+
+        cache.insert(("found", "pgdog").to_user(), Some(secret), ttl);
+        cache.insert(("missing", "pgdog").to_user(), None, ttl);
+        assert!(matches!(
+            cache.get(&("found", "pgdog").to_user(), ttl),
+            Some(Some(_))
+        ));
+        assert!(matches!(
+            cache.get(&("missing", "pgdog").to_user(), ttl),
+            Some(None)
+        ));
+        assert!(cache
+            .get(&("missing", "pgdog").to_user(), Duration::ZERO)
+            .is_none());
+
+        for user in 0..CACHE_SIZE + 10 {
+            cache.insert((user.to_string().as_str(), "pgdog").to_user(), None, ttl);
+        }
+        assert_eq!(cache.entries.len(), CACHE_SIZE);
+    }
+}
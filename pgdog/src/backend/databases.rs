@@ -2,24 +2,33 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tracing::{info, warn};
 
 use crate::{
-    backend::pool::PoolConfig,
-    config::{config, load, ConfigAndUsers, ManualQuery, Role},
-    net::messages::BackendKeyData,
+    auth::Passthrough,
+    backend::pool::{ClusterConfig, PoolConfig},
+    config::{config, set, ConfigAndUsers, Database, ManualQuery, Role},
+    frontend::rate_limit,
+    logger,
+    net::{messages::BackendKeyData, tls},
 };
 
 use super::{
     pool::{Address, Config},
     replication::ReplicationConfig,
-    Cluster, Error, ShardedTables,
+    two_pc::two_pc,
+    Cluster, Error, Pool, ShardedTables,
 };
 
 static DATABASES: Lazy<ArcSwap<Databases>> =
     Lazy::new(|| ArcSwap::from_pointee(Databases::default()));
+/// Serializes changes to the databases, so a concurrent change isn't lost.
+static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
 /// Get databases handle.
 ///
@@ -30,6 +39,11 @@ pub fn databases() -> Arc<Databases> {
 
 /// Replace databases pooler-wide.
 pub fn replace_databases(new_databases: Databases) {
+    let _lock = LOCK.lock();
+    replace(new_databases);
+}
+
+fn replace(new_databases: Databases) {
     // Order of operations is important
     // to ensure zero downtime for clients.
     let old_databases = databases();
@@ -41,7 +55,8 @@ pub fn replace_databases(new_databases: Databases) {
 
 /// Re-create all connections.
 pub fn reconnect() {
-    replace_databases(databases().duplicate());
+    let _lock = LOCK.lock();
+    replace(databases().duplicate());
 }
 
 /// Iniitialize the databases for the first time.
@@ -57,20 +72,116 @@ pub fn shutdown() {
 
 /// Re-create pools from config.
 ///
-/// TODO: Avoid creating new pools if they haven't changed at all
-/// or the configuration between the two is compatible.
+/// Pools that haven't changed are kept, along with their connections.
+/// Current pools and configuration are kept if the new configuration is invalid.
 pub fn reload() -> Result<(), Error> {
+    let _lock = LOCK.lock();
     let old_config = config();
-    let new_config = load(&old_config.config_path, &old_config.users_path)?;
-    let databases = from_config(&new_config);
+    let mut new_config = ConfigAndUsers::load(&old_config.config_path, &old_config.users_path)?;
+
+    // The two-phase commit log is only loaded at startup.
+    let general = &mut new_config.config.general;
+    if general.two_phase_commit && !two_pc().loaded() {
+        warn!("two_phase_commit can only be enabled with a restart");
+        general.two_phase_commit = false;
+    }
+    if two_pc().loaded()
+        && general.two_phase_commit_log != old_config.config.general.two_phase_commit_log
+    {
+        warn!("two_phase_commit_log can only be changed with a restart");
+        general.two_phase_commit_log = old_config.config.general.two_phase_commit_log.clone();
+    }
+
+    tls::reload(&new_config.config)?;
+    logger::format(new_config.config.general.log_format);
+    rate_limit::reload(&new_config);
+    set(new_config.clone());
+
+    let old_databases = databases();
+    let mut new_databases = from_config(&new_config);
+
+    // Keep users authenticated with passthrough authentication,
+    // if it's still enabled.
+    for (user, cluster) in old_databases.all() {
+        if cluster.passthrough().is_some()
+            && new_databases.auth_pools.contains_key(&user.database)
+            && !new_databases.databases.contains_key(user)
+        {
+            new_databases
+                .databases
+                .insert(user.clone(), cluster.duplicate());
+        }
+    }
+
+    let removed = new_databases.reuse(&old_databases);
+    let kept = old_databases.pools().len() - removed.len();
+    let new_databases = Arc::new(new_databases);
+
+    info!(
+        "reloaded config: {} pools kept, {} created, {} removed",
+        kept,
+        new_databases.pools().len() - kept,
+        removed.len()
+    );
+
+    new_databases.launch();
+    DATABASES.store(new_databases);
 
-    replace_databases(databases);
+    for pool in removed {
+        pool.shutdown();
+    }
+
+    Ok(())
+}
+
+/// Create pools for a user authenticated with passthrough authentication.
+///
+/// Pools are kept if they exist already and use the same credentials.
+pub fn passthrough(user: &str, database: &str, credentials: Passthrough) -> Result<(), Error> {
+    let key = (user, database).to_user();
+    let exists = || {
+        databases()
+            .databases
+            .get(&key)
+            .is_some_and(|cluster| cluster.passthrough() == Some(&credentials))
+    };
+
+    if exists() {
+        return Ok(());
+    }
+
+    let _lock = LOCK.lock();
+
+    // Another client could've created them while we waited for the lock.
+    if exists() {
+        return Ok(());
+    }
+
+    let config = config();
+    let user_config = crate::config::User {
+        name: user.to_owned(),
+        database: database.to_owned(),
+        min_pool_size: Some(0),
+        ..Default::default()
+    };
+    let cluster = new_cluster(&user_config, &config, Some(credentials))
+        .ok_or(Error::NoDatabase(key.clone()))?;
+
+    cluster.launch();
+    let mut databases = (*databases()).clone();
+    let old = databases.databases.insert(key, cluster);
+    DATABASES.store(Arc::new(databases));
+
+    // Credentials changed, e.g. the password was rotated.
+    if let Some(old) = old {
+        old.shutdown();
+    }
 
     Ok(())
 }
 
 /// Database/user pair that identifies a database cluster pool.
-#[derive(Debug, PartialEq, Hash, Eq, Clone)]
+#[derive(Debug, PartialEq, Hash, Eq, Clone, PartialOrd, Ord)]
 pub struct User {
     /// User name.
     pub user: String,
@@ -109,9 +220,11 @@ impl ToUser for (&str, Option<&str>) {
 }
 
 /// Databases.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Databases {
     databases: HashMap<User, Cluster>,
+    /// Pools used for passthrough authentication, by database name.
+    auth_pools: HashMap<String, Pool>,
     manual_queries: HashMap<String, ManualQuery>,
 }
 
@@ -126,6 +239,12 @@ impl Databases {
         }
     }
 
+    /// Get the pool used to look up passwords for users of the database,
+    /// if passthrough authentication is enabled.
+    pub fn auth_pool(&self, database: &str) -> Option<Pool> {
+        self.auth_pools.get(database).cloned()
+    }
+
     /// Get replication configuration for the database.
     pub fn replication(&self, database: &str) -> Option<ReplicationConfig> {
         for (user, cluster) in &self.databases {
@@ -167,25 +286,60 @@ impl Databases {
                 .iter()
                 .map(|(k, v)| (k.clone(), v.duplicate()))
                 .collect(),
+            auth_pools: self
+                .auth_pools
+                .iter()
+                .map(|(k, v)| (k.clone(), v.duplicate()))
+                .collect(),
             manual_queries: self.manual_queries.clone(),
         }
     }
 
+    /// Get all pools.
+    fn pools(&self) -> Vec<Pool> {
+        self.databases
+            .values()
+            .flat_map(|cluster| cluster.pools())
+            .chain(self.auth_pools.values().cloned())
+            .collect()
+    }
+
+    /// Use pools from `old` that haven't changed instead of creating new ones.
+    ///
+    /// Returns pools from `old` that weren't reused.
+    fn reuse(&mut self, old: &Databases) -> Vec<Pool> {
+        let mut pools = old.pools();
+
+        for cluster in self.databases.values_mut() {
+            cluster.reuse(&mut pools);
+        }
+
+        for pool in self.auth_pools.values_mut() {
+            pool.reuse(&mut pools);
+        }
+
+        pools
+    }
+
     /// Shutdown all pools.
     fn shutdown(&self) {
         for cluster in self.all().values() {
-            for shard in cluster.shards() {
-                shard.shutdown();
-            }
+            cluster.shutdown();
+        }
+
+        for pool in self.auth_pools.values() {
+            pool.shutdown();
         }
     }
 
     /// Launch all pools.
     fn launch(&self) {
         for cluster in self.all().values() {
-            for shard in cluster.shards() {
-                shard.launch();
-            }
+            cluster.launch();
+        }
+
+        for pool in self.auth_pools.values() {
+            pool.launch();
         }
     }
 }
@@ -193,58 +347,173 @@ impl Databases {
 /// Load databases from config.
 pub fn from_config(config: &ConfigAndUsers) -> Databases {
     let mut databases = HashMap::new();
-    let config_databases = config.config.databases();
-    let sharded_tables = config.config.sharded_tables();
+    let mut auth_pools = HashMap::new();
     let general = &config.config.general;
 
     for user in &config.users.users {
-        if let Some(shards) = config_databases.get(&user.database) {
-            let mut shard_configs = vec![];
-            for user_databases in shards {
-                let primary =
-                    user_databases
-                        .iter()
-                        .find(|d| d.role == Role::Primary)
-                        .map(|primary| PoolConfig {
-                            address: Address::new(primary, user),
-                            config: Config::new(general, primary, user),
-                        });
-                let replicas = user_databases
-                    .iter()
-                    .filter(|d| d.role == Role::Replica)
-                    .map(|replica| PoolConfig {
-                        address: Address::new(replica, user),
-                        config: Config::new(general, replica, user),
-                    })
-                    .collect::<Vec<_>>();
-                shard_configs.push((primary, replicas));
-            }
-
-            let sharded_tables = sharded_tables
-                .get(&user.database)
-                .cloned()
-                .unwrap_or(vec![]);
-            let sharded_tables = ShardedTables::new(sharded_tables);
+        if let Some(cluster) = new_cluster(user, config, None) {
             databases.insert(
                 User {
                     user: user.name.clone(),
                     database: user.database.clone(),
                 },
-                Cluster::new(
-                    &user.database,
-                    &shard_configs,
-                    general.load_balancing_strategy,
-                    &user.password,
-                    user.pooler_mode.unwrap_or(general.pooler_mode),
-                    sharded_tables,
-                    user.replication_sharding.clone(),
-                ),
+                cluster,
             );
         }
+
+        if general.auth_user.as_ref() == Some(&user.name) {
+            if let Some(pool) = auth_pool(user, config) {
+                auth_pools.insert(user.database.clone(), pool);
+            }
+        }
     }
 
     Databases {
         databases,
+        auth_pools,
         manual_queries: config.config.manual_queries(),
     }
 }
+
+/// Create the cluster for a user, if the database is configured.
+///
+/// Users authenticated with passthrough authentication connect
+/// to the servers with their own credentials.
+fn new_cluster(
+    user: &crate::config::User,
+    config: &ConfigAndUsers,
+    passthrough: Option<Passthrough>,
+) -> Option<Cluster> {
+    let shards = config.config.databases().remove(&user.database)?;
+    let general = &config.config.general;
+
+    let pool_config = |database: &Database| {
+        let mut address = Address::new(database, user);
+        address.passthrough = passthrough.clone();
+        PoolConfig {
+            address,
+            config: Config::new(general, database, user),
+        }
+    };
+
+    let mut shard_configs = vec![];
+    for user_databases in &shards {
+        let primary = user_databases
+            .iter()
+            .find(|d| d.role == Role::Primary)
+            .map(pool_config);
+        let replicas = user_databases
+            .iter()
+            .filter(|d| d.role == Role::Replica)
+            .map(pool_config)
+            .collect::<Vec<_>>();
+        shard_configs.push((primary, replicas));
+    }
+
+    let sharded_tables = config
+        .config
+        .sharded_tables()
+        .remove(&user.database)
+        .unwrap_or_default();
+
+    Some(Cluster::new(ClusterConfig {
+        name: &user.database,
+        shards: &shard_configs,
+        lb_strategy: general.load_balancing_strategy,
+        password: &user.password,
+        auth_method: user.auth_method,
+        pooler_mode: user.pooler_mode.unwrap_or(general.pooler_mode),
+        sharded_tables: ShardedTables::new(sharded_tables),
+        replication_sharding: user.replication_sharding.clone(),
+        passthrough,
+        idle_in_transaction_timeout: user
+            .idle_in_transaction_timeout
+            .or(general.idle_in_transaction_timeout)
+            .map(Duration::from_millis),
+        client_idle_timeout: user
+            .client_idle_timeout
+            .or(general.client_idle_timeout)
+            .map(Duration::from_millis),
+    }))
+}
+
+/// Pool used for running `auth_query` on the database,
+/// preferably on the primary of the first shard.
+fn auth_pool(user: &crate::config::User, config: &ConfigAndUsers) -> Option<Pool> {
+    let databases = config.config.databases().remove(&user.database)?;
+    let shard = databases.first()?;
+    let database = shard
+        .iter()
+        .find(|d| d.role == Role::Primary)
+        .or(shard.first())?;
+
+    Some(Pool::new(PoolConfig {
+        address: Address::new(database, user),
+        config: Config {
+            min: 0,
+            max: 2,
+            ..Config::new(&config.config.general, database, user)
+        },
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Config, Users};
+
+    #[test]
+    fn test_reuse() {
+        let config: Config = toml::from_str(
+            r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+role = "replica"
+"#,
+        )
+        .unwrap();
+        let users: Users = toml::from_str(
+            r#"
+[[users]]
+name = "alice"
+database = "pgdog"
+password = "alice"
+
+[[users]]
+name = "bob"
+database = "pgdog"
+password = "bob"
+"#,
+        )
+        .unwrap();
+        let mut config = ConfigAndUsers {
+            config,
+            users,
+            ..Default::default()
+        };
+
+        let old = from_config(&config);
+        assert_eq!(old.pools().len(), 4);
+
+        // Nothing changed.
+        let mut new = from_config(&config);
+        assert!(new.reuse(&old).is_empty());
+
+        // Only bob's pools need to reconnect.
+        config.users.users[1].password = "hunter2".into();
+        let mut new = from_config(&config);
+        let removed = new.reuse(&old);
+        assert_eq!(removed.len(), 2);
+        assert!(removed.iter().all(|pool| pool.addr().password == "bob"));
+
+        // Same address, but the pool is configured differently.
+        config.config.general.default_pool_size = 5;
+        let mut new = from_config(&config);
+        assert_eq!(new.reuse(&old).len(), 4);
+    }
+}
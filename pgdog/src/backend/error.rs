@@ -12,6 +12,9 @@ pub enum Error {
     #[error("{0}")]
     Tls(#[from] rustls_pki_types::InvalidDnsNameError),
 
+    #[error("server doesn't support TLS")]
+    TlsRequired,
+
     #[error("net: {0}")]
     Net(#[from] crate::net::Error),
 
@@ -51,7 +54,7 @@ pub enum Error {
     #[error("scram auth failed")]
     ScramAuth(#[from] crate::auth::scram::Error),
 
-    #[error("config error")]
+    #[error("config error: {0}")]
     Config(#[from] crate::config::error::Error),
 
     #[error("{0}")]
@@ -74,9 +77,28 @@ pub enum Error {
 
     #[error("{0}")]
     ExecutionError(ErrorResponse),
+
+    #[error("{0}")]
+    TwoPc(#[from] crate::backend::two_pc::Error),
+
+    #[error("column {0} the rows are grouped by is missing")]
+    AggregateColumn(usize),
+
+    #[error("aggregates of data type {0} can't be merged across shards")]
+    AggregateDataType(i32),
 }
 
 impl Error {
+    /// SQLSTATE of the error returned by the server, if any.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::ConnectionError(err)
+            | Error::PreparedStatementError(err)
+            | Error::ExecutionError(err) => Some(&err.code),
+            _ => None,
+        }
+    }
+
     /// Checkout timeout.
     pub fn no_server(&self) -> bool {
         use crate::backend::pool::Error as PoolError;
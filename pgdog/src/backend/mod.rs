@@ -1,5 +1,6 @@
 //! pgDog backend managers connections to PostgreSQL.
 
+pub mod auth_query;
 pub mod databases;
 pub mod error;
 pub mod pool;
@@ -8,11 +9,12 @@ pub mod replication;
 pub mod schema;
 pub mod server;
 pub mod stats;
+pub mod two_pc;
 
 pub use error::Error;
 pub use pool::{Cluster, Pool, Replicas, Shard};
 pub use prepared_statements::PreparedStatements;
-pub use replication::ShardedTables;
+pub use replication::{ShardedColumn, ShardedTables};
 pub use schema::Schema;
 pub use server::Server;
 pub use stats::Stats;
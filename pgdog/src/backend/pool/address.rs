@@ -1,8 +1,14 @@
 //! Server address.
 
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
+use tracing::{field::Empty, info_span, Span};
 
-use crate::config::{Database, User};
+use crate::{
+    auth::Passthrough,
+    config::{Database, TlsMode, User},
+};
 
 /// Server address.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -17,6 +23,19 @@ pub struct Address {
     pub user: String,
     /// Password.
     pub password: String,
+    /// Credentials of a client authenticated with passthrough authentication.
+    /// Used instead of the password, if set.
+    #[serde(default)]
+    pub passthrough: Option<Passthrough>,
+    /// TLS mode.
+    #[serde(default)]
+    pub tls_mode: TlsMode,
+    /// CA bundle used to verify the server certificate.
+    #[serde(default)]
+    pub tls_ca_certificate: Option<PathBuf>,
+    /// Shard number.
+    #[serde(default)]
+    pub shard: usize,
 }
 
 impl Address {
@@ -44,6 +63,10 @@ impl Address {
             } else {
                 user.password.clone()
             },
+            passthrough: None,
+            tls_mode: database.server_tls_mode,
+            tls_ca_certificate: database.server_tls_ca_certificate.clone(),
+            shard: database.shard,
         }
     }
 
@@ -51,6 +74,20 @@ impl Address {
     pub fn addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    /// Span for log events about connections to this server.
+    /// The backend pid is recorded once it's known.
+    pub fn span(&self) -> Span {
+        info_span!(
+            "server",
+            host = %self.host,
+            port = self.port,
+            database = %self.database_name,
+            user = %self.user,
+            shard = self.shard,
+            pid = Empty,
+        )
+    }
 }
 
 impl std::fmt::Display for Address {
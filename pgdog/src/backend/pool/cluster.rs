@@ -1,15 +1,17 @@
 //! A collection of replicas and a primary.
 
 use crate::{
-    backend::{databases::databases, replication::ReplicationConfig, ShardedTables},
-    config::{PoolerMode, ShardedTable},
+    auth::Passthrough,
+    backend::{databases::databases, replication::ReplicationConfig, ShardedColumn, ShardedTables},
+    config::{AuthMethod, PoolerMode, ShardedTable},
     net::messages::BackendKeyData,
 };
 
-use super::{Address, Config, Error, Guard, Request, Shard};
+use super::{Address, Config, Error, Guard, Pool, Request, Shard};
 use crate::config::LoadBalancingStrategy;
 
 use std::ffi::CString;
+use std::time::Duration;
 
 #[derive(Clone, Debug)]
 /// Database configuration.
@@ -20,6 +22,33 @@ pub struct PoolConfig {
     pub(crate) config: Config,
 }
 
+/// Cluster settings, taken from the user and database configuration.
+#[derive(Debug)]
+pub struct ClusterConfig<'a> {
+    /// Database name.
+    pub name: &'a str,
+    /// Primary and replicas of each shard.
+    pub shards: &'a [(Option<PoolConfig>, Vec<PoolConfig>)],
+    /// Replica load balancing strategy.
+    pub lb_strategy: LoadBalancingStrategy,
+    /// Password clients should use.
+    pub password: &'a str,
+    /// How clients should authenticate.
+    pub auth_method: AuthMethod,
+    /// Pooler mode.
+    pub pooler_mode: PoolerMode,
+    /// Sharded tables.
+    pub sharded_tables: ShardedTables,
+    /// Database used for logical replication sharding.
+    pub replication_sharding: Option<String>,
+    /// Credentials of a user authenticated with passthrough authentication.
+    pub passthrough: Option<Passthrough>,
+    /// Disconnect clients idle in transaction for this long.
+    pub idle_in_transaction_timeout: Option<Duration>,
+    /// Disconnect idle clients after this long.
+    pub client_idle_timeout: Option<Duration>,
+}
+
 /// A collection of sharded replicas and primaries
 /// belonging to the same database cluster.
 #[derive(Clone, Default, Debug)]
@@ -27,32 +56,33 @@ pub struct Cluster {
     name: String,
     shards: Vec<Shard>,
     password: String,
+    auth_method: AuthMethod,
     pooler_mode: PoolerMode,
     sharded_tables: ShardedTables,
     replication_sharding: Option<String>,
+    passthrough: Option<Passthrough>,
+    idle_in_transaction_timeout: Option<Duration>,
+    client_idle_timeout: Option<Duration>,
 }
 
 impl Cluster {
     /// Create new cluster of shards.
-    pub fn new(
-        name: &str,
-        shards: &[(Option<PoolConfig>, Vec<PoolConfig>)],
-        lb_strategy: LoadBalancingStrategy,
-        password: &str,
-        pooler_mode: PoolerMode,
-        sharded_tables: ShardedTables,
-        replication_sharding: Option<String>,
-    ) -> Self {
+    pub fn new(config: ClusterConfig) -> Self {
         Self {
-            shards: shards
+            shards: config
+                .shards
                 .iter()
-                .map(|addr| Shard::new(addr.0.clone(), &addr.1, lb_strategy))
+                .map(|addr| Shard::new(addr.0.clone(), &addr.1, config.lb_strategy))
                 .collect(),
-            name: name.to_owned(),
-            password: password.to_owned(),
-            pooler_mode,
-            sharded_tables,
-            replication_sharding,
+            name: config.name.to_owned(),
+            password: config.password.to_owned(),
+            auth_method: config.auth_method,
+            pooler_mode: config.pooler_mode,
+            sharded_tables: config.sharded_tables,
+            replication_sharding: config.replication_sharding,
+            passthrough: config.passthrough,
+            idle_in_transaction_timeout: config.idle_in_transaction_timeout,
+            client_idle_timeout: config.client_idle_timeout,
         }
     }
 
@@ -77,12 +107,31 @@ impl Cluster {
             shards: self.shards.iter().map(|s| s.duplicate()).collect(),
             name: self.name.clone(),
             password: self.password.clone(),
+            auth_method: self.auth_method,
             pooler_mode: self.pooler_mode,
             sharded_tables: self.sharded_tables.clone(),
             replication_sharding: self.replication_sharding.clone(),
+            passthrough: self.passthrough.clone(),
+            idle_in_transaction_timeout: self.idle_in_transaction_timeout,
+            client_idle_timeout: self.client_idle_timeout,
+        }
+    }
+
+    /// Use pools from `pools` instead of new ones, if they are identical.
+    ///
+    /// This keeps existing server connections. Use when reloading configuration
+    /// and only some pools changed.
+    pub fn reuse(&mut self, pools: &mut Vec<Pool>) {
+        for shard in &mut self.shards {
+            shard.reuse(pools);
         }
     }
 
+    /// Get all pools.
+    pub fn pools(&self) -> Vec<Pool> {
+        self.shards.iter().flat_map(|shard| shard.pools()).collect()
+    }
+
     /// Cancel a query executed by one of the shards.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
         for shard in &self.shards {
@@ -148,21 +197,60 @@ impl Cluster {
         &self.password
     }
 
+    /// How the user should authenticate.
+    pub fn auth_method(&self) -> AuthMethod {
+        self.auth_method
+    }
+
+    /// Credentials of the user, if authenticated with passthrough authentication.
+    pub fn passthrough(&self) -> Option<&Passthrough> {
+        self.passthrough.as_ref()
+    }
+
+    /// Launch all pools.
+    pub fn launch(&self) {
+        for shard in self.shards() {
+            shard.launch();
+        }
+    }
+
+    /// Shutdown all pools.
+    pub fn shutdown(&self) {
+        for shard in self.shards() {
+            shard.shutdown();
+        }
+    }
+
     /// Get pooler mode.
     pub fn pooler_mode(&self) -> PoolerMode {
         self.pooler_mode
     }
 
+    /// How long clients can be idle in transaction.
+    pub fn idle_in_transaction_timeout(&self) -> Option<Duration> {
+        self.idle_in_transaction_timeout
+    }
+
+    /// How long clients can be idle outside a transaction.
+    pub fn client_idle_timeout(&self) -> Option<Duration> {
+        self.client_idle_timeout
+    }
+
     // Get sharded tables if any.
     pub fn sharded_tables(&self) -> &[ShardedTable] {
         self.sharded_tables.tables()
     }
 
     /// Find sharded column position, if the table and columns match the configuration.
-    pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<usize> {
+    pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<ShardedColumn<'_>> {
         self.sharded_tables.sharded_column(table, columns)
     }
 
+    /// Sharded table used when the table isn't known, e.g. for sharding keys in comments.
+    pub fn primary_sharded_table(&self) -> Option<&ShardedTable> {
+        self.sharded_tables.primary()
+    }
+
     /// This cluster is read only (no primaries).
     pub fn read_only(&self) -> bool {
         for shard in &self.shards {
@@ -210,6 +298,7 @@ mod test {
                     name: Some("sharded".into()),
                     column: "id".into(),
                     primary: true,
+                    ..Default::default()
                 }]),
                 shards: vec![Shard::default(), Shard::default()],
                 ..Default::default()
@@ -4,7 +4,7 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Database, General, User};
+use crate::config::{Database, General, Role, User};
 
 /// Pool configuration.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
@@ -45,6 +45,8 @@ pub struct Config {
     pub statement_timeout: Option<u64>,
     /// Replication mode.
     pub replication_mode: bool,
+    /// Recover two-phase commit transactions.
+    pub two_phase_commit: bool,
 }
 
 impl Config {
@@ -113,7 +115,7 @@ impl Config {
     }
 
     /// Create from database/user configuration.
-    pub fn new(general: &General, _database: &Database, user: &User) -> Self {
+    pub fn new(general: &General, database: &Database, user: &User) -> Self {
         Config {
             min: user.min_pool_size.unwrap_or(general.min_pool_size),
             max: user.pool_size.unwrap_or(general.default_pool_size),
@@ -124,6 +126,9 @@ impl Config {
             rollback_timeout: general.rollback_timeout,
             statement_timeout: user.statement_timeout,
             replication_mode: user.replication_mode,
+            two_phase_commit: general.two_phase_commit
+                && database.role == Role::Primary
+                && !user.replication_mode,
             ..Default::default()
         }
     }
@@ -150,6 +155,7 @@ impl Default for Config {
             rollback_timeout: Duration::from_secs(5).as_millis() as u64,
             statement_timeout: None,
             replication_mode: false,
+            two_phase_commit: false,
         }
     }
 }
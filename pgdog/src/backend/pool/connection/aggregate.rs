@@ -0,0 +1,455 @@
+//! Merge aggregate functions received from multiple shards.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use bytes::Bytes;
+
+use crate::{
+    frontend::router::parser::{Aggregate, AggregateFunction, AggregateTarget},
+    net::messages::{
+        DataRow, DataType, Datum, Decimal, Field, FromBytes, Message, Numeric, Protocol,
+        RowDescription, ToBytes,
+    },
+};
+
+/// Column value, decoded and as received from the shard.
+#[derive(Debug, Clone)]
+struct Value {
+    datum: Datum,
+    /// `None` is NULL.
+    data: Option<Bytes>,
+}
+
+/// Merge aggregate rows from all shards, one row per group.
+#[derive(Default, Debug)]
+pub(super) struct AggregateBuffer {
+    /// Merged rows, in the order groups were first seen.
+    rows: Vec<Vec<Value>>,
+    /// Group key to row position.
+    groups: HashMap<Vec<Option<Bytes>>, usize>,
+}
+
+impl AggregateBuffer {
+    /// Merge a row received from a shard.
+    pub(super) fn add(
+        &mut self,
+        message: Message,
+        aggregate: &Aggregate,
+        rd: &RowDescription,
+    ) -> Result<(), super::Error> {
+        let dr = DataRow::from_bytes(message.to_bytes()?)?;
+        let mut row = vec![];
+
+        for (index, field) in rd.fields.iter().enumerate() {
+            let data = dr.column(index);
+            let datum = match data {
+                Some(ref data) => Datum::new(data, field.data_type(), field.format())?,
+                None => Datum::Null,
+            };
+            row.push(Value { datum, data });
+        }
+
+        // Values we don't decode can't be merged.
+        for target in aggregate.targets() {
+            for column in Self::columns(target) {
+                if let (Some(value), Some(field)) = (row.get(column), rd.field(column)) {
+                    if value.data.is_some() && value.datum.is_null() {
+                        return Err(super::Error::AggregateDataType(field.type_oid));
+                    }
+                }
+            }
+        }
+
+        let key = aggregate
+            .group_by()
+            .iter()
+            .map(|index| {
+                row.get(*index)
+                    .map(|value| value.data.clone())
+                    .ok_or(super::Error::AggregateColumn(*index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(merged) = self.groups.get(&key).map(|index| &mut self.rows[*index]) else {
+            self.groups.insert(key, self.rows.len());
+            self.rows.push(row);
+            return Ok(());
+        };
+
+        for target in aggregate.targets() {
+            for column in Self::columns(target) {
+                let (Some(left), Some(right)) = (merged.get_mut(column), row.get(column)) else {
+                    continue;
+                };
+                Self::merge(target.function(), left, right)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Columns holding the partial result of an aggregate.
+    fn columns(target: &AggregateTarget) -> Vec<usize> {
+        let mut columns = vec![target.column()];
+        columns.extend(target.count());
+        columns
+    }
+
+    fn merge(
+        function: AggregateFunction,
+        left: &mut Value,
+        right: &Value,
+    ) -> Result<(), super::Error> {
+        if right.datum.is_null() {
+            return Ok(());
+        }
+
+        let replace = match function {
+            AggregateFunction::Count | AggregateFunction::Sum | AggregateFunction::Avg => {
+                left.datum = left.datum.clone().sum(right.datum.clone())?;
+                false
+            }
+            AggregateFunction::Min => {
+                left.datum.is_null() || right.datum.cmp(&left.datum) == Ordering::Less
+            }
+            AggregateFunction::Max => {
+                left.datum.is_null() || right.datum.cmp(&left.datum) == Ordering::Greater
+            }
+        };
+
+        if replace {
+            *left = right.clone();
+        }
+
+        Ok(())
+    }
+
+    /// Row description the client expects to receive.
+    pub(super) fn row_description(aggregate: &Aggregate, rd: &RowDescription) -> RowDescription {
+        let mut rd = rd.clone();
+        rd.fields.truncate(aggregate.columns());
+
+        for target in aggregate.targets() {
+            if target.function() != AggregateFunction::Avg {
+                continue;
+            }
+            // Shards return the sum, which can be an integer. Average of
+            // floating point numbers is double precision, all others are numeric.
+            if let Some(field) = rd.fields.get_mut(target.column()) {
+                if matches!(
+                    field.data_type(),
+                    DataType::Real | DataType::DoublePrecision
+                ) {
+                    field.type_oid = 701;
+                    field.type_size = 8;
+                } else {
+                    field.type_oid = 1700;
+                    field.type_size = -1;
+                }
+            }
+        }
+
+        rd
+    }
+
+    /// Take all merged rows, once all shards sent theirs.
+    pub(super) fn take(
+        &mut self,
+        aggregate: &Aggregate,
+        rd: &RowDescription,
+    ) -> Result<Vec<Message>, super::Error> {
+        self.groups.clear();
+
+        std::mem::take(&mut self.rows)
+            .into_iter()
+            .map(|row| Self::row(row, aggregate, rd))
+            .collect()
+    }
+
+    fn row(
+        row: Vec<Value>,
+        aggregate: &Aggregate,
+        rd: &RowDescription,
+    ) -> Result<Message, super::Error> {
+        let mut columns = row
+            .iter()
+            .map(|value| value.data.clone())
+            .collect::<Vec<_>>();
+
+        for target in aggregate.targets() {
+            let (Some(value), Some(field)) = (row.get(target.column()), rd.field(target.column()))
+            else {
+                continue;
+            };
+
+            let datum = match target.function() {
+                AggregateFunction::Count | AggregateFunction::Sum => value.datum.clone(),
+                AggregateFunction::Avg => {
+                    let count = target
+                        .count()
+                        .and_then(|count| row.get(count))
+                        .map(|count| &count.datum);
+                    Self::avg(&value.datum, count, field)?
+                }
+                AggregateFunction::Min | AggregateFunction::Max => continue,
+            };
+
+            columns[target.column()] = datum.encode(field.format())?;
+        }
+
+        columns.truncate(aggregate.columns());
+
+        let mut dr = DataRow::new();
+        for column in columns {
+            match column {
+                Some(data) => dr.add(data),
+                None => dr.add_null(),
+            };
+        }
+
+        Ok(dr.message()?)
+    }
+
+    /// Divide the sum by the count, same as PostgreSQL.
+    fn avg(sum: &Datum, count: Option<&Datum>, field: &Field) -> Result<Datum, super::Error> {
+        let count = match count {
+            Some(Datum::Bigint(count)) if *count > 0 => *count,
+            _ => return Ok(Datum::Null),
+        };
+
+        let sum = match sum {
+            Datum::Null => return Ok(Datum::Null),
+            Datum::Numeric(sum) => return Ok(Datum::Numeric(Numeric::from(**sum / count as f64))),
+            Datum::Bigint(sum) => Decimal::from(*sum),
+            Datum::Integer(sum) => Decimal::from(*sum as i64),
+            Datum::SmallInt(sum) => Decimal::from(*sum as i64),
+            Datum::Decimal(sum) => sum.clone(),
+            _ => return Err(super::Error::AggregateDataType(field.type_oid)),
+        };
+
+        Ok(sum
+            .div(&Decimal::from(count))
+            .map(Datum::Decimal)
+            .unwrap_or(Datum::Null))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::NodeEnum;
+
+    use super::*;
+    use crate::net::messages::{Format, FromDataType};
+
+    fn aggregate(query: &str) -> Aggregate {
+        let ast = pg_query::parse(query).unwrap();
+        let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match root.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => Aggregate::parse(stmt).unwrap(),
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_buffer() {
+        let aggregate = aggregate("SELECT COUNT(*), SUM(id), MIN(id), MAX(id), AVG(id) FROM t");
+        let rd = RowDescription::new(&[
+            Field::bigint("count"),
+            Field::numeric("sum"),
+            Field::bigint("min"),
+            Field::bigint("max"),
+            Field::numeric("avg"),
+            Field::bigint("count"),
+        ]);
+        let mut buf = AggregateBuffer::default();
+
+        for (count, sum, min, max) in [(2_i64, 3.0, 1_i64, 2_i64), (3, 12.0, 3, 5)] {
+            let mut dr = DataRow::new();
+            dr.add(count).add(sum).add(min).add(max).add(sum).add(count);
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        let messages = buf.take(&aggregate, &rd).unwrap();
+        assert_eq!(messages.len(), 1);
+        let dr = DataRow::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert!(dr.column(5).is_none());
+        assert_eq!(dr.get::<i64>(0, Format::Text), Some(5));
+        assert_eq!(dr.get_float(1, true), Some(15.0));
+        assert_eq!(dr.get::<i64>(2, Format::Text), Some(1));
+        assert_eq!(dr.get::<i64>(3, Format::Text), Some(5));
+        assert_eq!(dr.get_float(4, true), Some(3.0));
+        assert!(buf.take(&aggregate, &rd).unwrap().is_empty());
+
+        let rd = AggregateBuffer::row_description(&aggregate, &rd);
+        assert_eq!(rd.fields.len(), 5);
+    }
+
+    #[test]
+    fn test_aggregate_buffer_numeric() {
+        let aggregate = aggregate("SELECT SUM(price), SUM(id) FROM t");
+        let rd = RowDescription::new(&[Field::numeric("sum"), Field::numeric("sum")]);
+        let mut buf = AggregateBuffer::default();
+
+        for (price, id) in [
+            ("0.1", "9223372036854775807"),
+            ("0.2", "9223372036854775807"),
+        ] {
+            let mut dr = DataRow::new();
+            dr.add(price).add(id);
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        let messages = buf.take(&aggregate, &rd).unwrap();
+        let dr = DataRow::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.get_text(0).unwrap(), "0.3");
+        assert_eq!(dr.get_text(1).unwrap(), "18446744073709551614");
+
+        // Partial results with different types can't be merged.
+        let rd = RowDescription::new(&[Field::numeric("sum"), Field::numeric("sum")]);
+        let other = RowDescription::new(&[Field::bigint("sum"), Field::bigint("sum")]);
+        let mut dr = DataRow::new();
+        dr.add("1").add("1");
+        buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        assert!(buf.add(dr.message().unwrap(), &aggregate, &other).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_buffer_null() {
+        let aggregate = aggregate("SELECT COUNT(id), SUM(id), MIN(id), MAX(id), AVG(id) FROM t");
+        let rd = RowDescription::new(&[
+            Field::bigint("count"),
+            Field::numeric("sum"),
+            Field::bigint("min"),
+            Field::bigint("max"),
+            Field::numeric("avg"),
+            Field::bigint("count"),
+        ]);
+        let mut buf = AggregateBuffer::default();
+
+        // No rows on any shard.
+        for _ in 0..2 {
+            let mut dr = DataRow::new();
+            dr.add(0_i64)
+                .add_null()
+                .add_null()
+                .add_null()
+                .add_null()
+                .add(0_i64);
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        let messages = buf.take(&aggregate, &rd).unwrap();
+        let dr = DataRow::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.get::<i64>(0, Format::Text), Some(0));
+        for column in 1..5 {
+            assert!(dr.column(column).is_none());
+        }
+
+        // NULL and empty string are different groups.
+        let aggregate = self::aggregate("SELECT email, COUNT(*) FROM t GROUP BY email");
+        let rd = RowDescription::new(&[Field::text("email"), Field::bigint("count")]);
+        for email in [None, Some(""), None] {
+            let mut dr = DataRow::new();
+            match email {
+                Some(email) => dr.add(email),
+                None => dr.add_null(),
+            };
+            dr.add(1_i64);
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        let rows = buf
+            .take(&aggregate, &rd)
+            .unwrap()
+            .into_iter()
+            .map(|message| {
+                let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+                (dr.column(0), dr.get_int(1, true).unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(None, 2), (Some(Bytes::new()), 1)]);
+    }
+
+    #[test]
+    fn test_aggregate_buffer_avg() {
+        let aggregate = aggregate("SELECT AVG(id) FROM t");
+        let mut rd = RowDescription::new(&[Field::numeric("avg"), Field::bigint("count")]);
+        for field in rd.fields.iter_mut() {
+            field.format = 1;
+        }
+        let mut buf = AggregateBuffer::default();
+
+        for count in [1_i64, 2] {
+            let sum = Decimal::decode(b"9223372036854775807", Format::Text).unwrap();
+            let mut dr = DataRow::new();
+            dr.add(sum.encode(Format::Binary).unwrap())
+                .add(count.encode(Format::Binary).unwrap());
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        // Exact, and still numeric in binary format.
+        let messages = buf.take(&aggregate, &rd).unwrap();
+        let dr = DataRow::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        let avg = dr.get::<Decimal>(0, Format::Binary).unwrap();
+        assert_eq!(avg.encode(Format::Text).unwrap(), "6148914691236517205");
+
+        let rd = AggregateBuffer::row_description(&aggregate, &rd);
+        assert_eq!(rd.fields[0].type_oid, 1700);
+        assert_eq!(rd.fields[0].format, 1);
+    }
+
+    #[test]
+    fn test_aggregate_buffer_data_type() {
+        // MIN(date) isn't decoded, so it can't be merged.
+        let aggregate = aggregate("SELECT MIN(created_at) FROM t");
+        let rd = RowDescription::new(&[Field {
+            type_oid: 1082,
+            type_size: 4,
+            ..Field::text("min")
+        }]);
+        let mut buf = AggregateBuffer::default();
+        let mut dr = DataRow::new();
+        dr.add("2025-01-01");
+        assert!(buf.add(dr.message().unwrap(), &aggregate, &rd).is_err());
+
+        // NULLs don't need to be decoded.
+        let mut dr = DataRow::new();
+        dr.add_null();
+        assert!(buf.add(dr.message().unwrap(), &aggregate, &rd).is_ok());
+    }
+
+    #[test]
+    fn test_aggregate_buffer_group_by() {
+        let aggregate = aggregate("SELECT email, COUNT(*) AS total FROM t GROUP BY email");
+        let rd = RowDescription::new(&[Field::text("email"), Field::bigint("total")]);
+        let mut buf = AggregateBuffer::default();
+
+        for (email, count) in [("a", 1_i64), ("b", 2), ("a", 3), ("c", 4), ("b", 5)] {
+            let mut dr = DataRow::new();
+            dr.add(email).add(count);
+            buf.add(dr.message().unwrap(), &aggregate, &rd).unwrap();
+        }
+
+        let rows = buf
+            .take(&aggregate, &rd)
+            .unwrap()
+            .into_iter()
+            .map(|message| {
+                let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+                (dr.get_text(0).unwrap(), dr.get_int(1, true).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rows,
+            vec![("a".into(), 4), ("b".into(), 7), ("c".into(), 4)]
+        );
+
+        // Grouping column isn't in the result.
+        let missing = self::aggregate("SELECT COUNT(*), email FROM t GROUP BY 2");
+        let rd = RowDescription::new(&[Field::bigint("count")]);
+        let mut dr = DataRow::new();
+        dr.add(1_i64);
+        assert!(buf.add(dr.message().unwrap(), &missing, &rd).is_err());
+    }
+}
@@ -1,9 +1,17 @@
 //! Binding between frontend client and a connection on the backend.
 
+use tracing::error;
+
+use crate::{
+    backend::two_pc::{two_pc, Decision, TwoPc},
+    net::messages::{CommandComplete, FromBytes, ToBytes},
+};
+
 use super::*;
 
 /// The server(s) the client is connected to.
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub(super) enum Binding {
     Server(Option<Guard>),
     Admin(Backend),
@@ -63,13 +71,17 @@ impl Binding {
                             return Ok(message);
                         }
 
-                        let pending = shards.iter_mut().filter(|s| !s.done());
+                        let pending = shards
+                            .iter_mut()
+                            .enumerate()
+                            .filter(|(shard, server)| !server.done() && state.wants(*shard))
+                            .collect::<Vec<_>>();
                         let mut read = false;
 
-                        for shard in pending {
-                            let message = shard.read().await?;
+                        for (shard, server) in pending {
+                            let message = server.read().await?;
                             read = true;
-                            if let Some(message) = state.forward(message)? {
+                            if let Some(message) = state.forward(shard, message)? {
                                 return Ok(message);
                             }
                         }
@@ -137,6 +149,38 @@ impl Binding {
         }
     }
 
+    /// Update the route the current request is taking.
+    pub(super) fn set_route(&mut self, route: &Route) {
+        if let Binding::MultiShard(_, state) = self {
+            state.set_route(route);
+        }
+    }
+
+    /// Send different messages to some of the shards.
+    pub(super) async fn send_sharded(
+        &mut self,
+        messages: Vec<(usize, Vec<Message>)>,
+        parse_complete: bool,
+    ) -> Result<(), Error> {
+        match self {
+            Binding::MultiShard(servers, state) => {
+                state.participants(
+                    messages.iter().map(|(shard, _)| *shard).collect(),
+                    parse_complete,
+                );
+
+                for (shard, messages) in messages {
+                    let server = servers.get_mut(shard).ok_or(Error::NotConnected)?;
+                    server.send(messages).await?;
+                }
+
+                Ok(())
+            }
+
+            _ => Err(Error::NotConnected),
+        }
+    }
+
     /// Send copy messages to shards they are destined to go.
     pub(super) async fn send_copy(&mut self, rows: Vec<CopyRow>) -> Result<(), Error> {
         match self {
@@ -169,6 +213,15 @@ impl Binding {
         }
     }
 
+    /// Server(s) are idle in a transaction, waiting for the client.
+    pub(super) fn in_transaction(&self) -> bool {
+        match self {
+            Binding::Server(Some(server)) => server.in_transaction(),
+            Binding::MultiShard(servers, _state) => servers.iter().all(|s| s.in_transaction()),
+            _ => false,
+        }
+    }
+
     /// Execute a query on all servers.
     pub(super) async fn execute(&mut self, query: &str) -> Result<(), Error> {
         match self {
@@ -191,4 +244,84 @@ impl Binding {
 
         Ok(())
     }
+
+    /// Commit the transaction on all shards using two-phase commit.
+    ///
+    /// Returns `false` if the transaction was rolled back instead, because it
+    /// was aborted on one of the shards.
+    pub(super) async fn two_phase_commit(&mut self) -> Result<bool, Error> {
+        let Binding::MultiShard(servers, _) = self else {
+            return Err(Error::NotConnected);
+        };
+
+        let gid = two_pc().begin();
+
+        if let Err(err) = two_pc().prepare(&gid).await {
+            for server in servers.iter_mut() {
+                let _ = server.execute("ROLLBACK").await;
+            }
+            let _ = two_pc().finish(&gid, Decision::Rollback, true).await;
+            return Err(err.into());
+        }
+
+        let mut prepared = vec![];
+        let mut result = Ok(true);
+
+        for (shard, server) in servers.iter_mut().enumerate() {
+            let prepare = format!("PREPARE TRANSACTION '{}'", TwoPc::participant(&gid, shard));
+            match server.execute_checked(&prepare).await {
+                Ok(messages) => {
+                    // Transaction was aborted and PostgreSQL rolled it back.
+                    if Self::command(&messages)? != "PREPARE TRANSACTION" {
+                        result = Ok(false);
+                        break;
+                    }
+                    prepared.push(shard);
+                }
+
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        let decision = if matches!(result, Ok(true)) {
+            match two_pc().commit(&gid).await {
+                Ok(()) => Decision::Commit,
+                Err(err) => {
+                    result = Err(err.into());
+                    Decision::Rollback
+                }
+            }
+        } else {
+            Decision::Rollback
+        };
+
+        let mut resolved = true;
+        for (shard, server) in servers.iter_mut().enumerate() {
+            let query = if prepared.contains(&shard) {
+                decision.query(&TwoPc::participant(&gid, shard))
+            } else {
+                "ROLLBACK".into()
+            };
+
+            if let Err(err) = server.execute_checked(&query).await {
+                error!("{} failed: {} [{}]", query, err, server.addr());
+                resolved = false;
+            }
+        }
+
+        two_pc().finish(&gid, decision, resolved).await?;
+
+        result
+    }
+
+    /// Command tag of the last CommandComplete message.
+    fn command(messages: &[Message]) -> Result<String, Error> {
+        match messages.iter().rev().find(|message| message.code() == 'C') {
+            Some(message) => Ok(CommandComplete::from_bytes(message.to_bytes()?)?.command),
+            None => Err(Error::UnexpectedMessage('Z')),
+        }
+    }
 }
@@ -0,0 +1,197 @@
+//! Merge rows, already sorted by each shard, as they arrive.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use crate::{
+    frontend::router::parser::OrderBy,
+    net::messages::{DataRow, Datum, FromBytes, Message, RowDescription, ToBytes},
+};
+
+/// Row waiting to be sent to the client.
+#[derive(Debug)]
+struct Entry {
+    /// Values of the sorting columns and sort direction.
+    key: Vec<(Datum, bool)>,
+    shard: usize,
+    row: Message,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for ((left, asc), (right, _)) in self.key.iter().zip(other.key.iter()) {
+            let ordering = if *asc {
+                left.cmp(right)
+            } else {
+                right.cmp(left)
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        self.shard.cmp(&other.shard)
+    }
+}
+
+/// K-way merge of sorted rows from multiple shards.
+///
+/// Holds at most one row per shard. A row is sent to the client once all other
+/// shards either sent a row that sorts after it or have no more rows.
+#[derive(Default, Debug)]
+pub(super) struct MergeBuffer {
+    heap: BinaryHeap<Reverse<Entry>>,
+    /// Shard has a row in the heap.
+    buffered: Vec<bool>,
+    /// Shard has no more rows to send.
+    finished: Vec<bool>,
+    /// Positions of the sorting columns and sort direction.
+    columns: Option<Vec<(usize, bool)>>,
+}
+
+impl MergeBuffer {
+    /// Add a row received from a shard.
+    pub(super) fn add(
+        &mut self,
+        shard: usize,
+        message: Message,
+        order_by: &[OrderBy],
+        rd: &RowDescription,
+    ) -> Result<(), super::Error> {
+        let dr = DataRow::from_bytes(message.to_bytes()?)?;
+        let columns = self
+            .columns
+            .get_or_insert_with(|| Self::sort_columns(order_by, rd));
+
+        let mut key = vec![];
+        for (index, asc) in columns.iter() {
+            let datum = dr
+                .get_column(*index, rd)?
+                .map(|column| column.value)
+                .unwrap_or(Datum::Null);
+            key.push((datum, *asc));
+        }
+
+        Self::set(&mut self.buffered, shard);
+        self.heap.push(Reverse(Entry {
+            key,
+            shard,
+            row: message,
+        }));
+
+        Ok(())
+    }
+
+    /// Shard won't send any more rows.
+    pub(super) fn finish(&mut self, shard: usize) {
+        Self::set(&mut self.finished, shard);
+    }
+
+    /// Should we read the next message from this shard?
+    /// We can't merge more than one row per shard at a time.
+    pub(super) fn wants(&self, shard: usize) -> bool {
+        !self.buffered.get(shard).copied().unwrap_or(false)
+    }
+
+    /// Get the next row, if we know it's the next one.
+    pub(super) fn take(&mut self, shards: impl Iterator<Item = usize>) -> Option<Message> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        for shard in shards {
+            let buffered = self.buffered.get(shard).copied().unwrap_or(false);
+            let finished = self.finished.get(shard).copied().unwrap_or(false);
+            if !buffered && !finished {
+                return None;
+            }
+        }
+
+        let Reverse(entry) = self.heap.pop()?;
+        self.buffered[entry.shard] = false;
+
+        Some(entry.row)
+    }
+
+    fn set(flags: &mut Vec<bool>, shard: usize) {
+        if flags.len() <= shard {
+            flags.resize(shard + 1, false);
+        }
+        flags[shard] = true;
+    }
+
+    /// Calculate column positions once, since fetching them by name is O(n).
+    fn sort_columns(order_by: &[OrderBy], rd: &RowDescription) -> Vec<(usize, bool)> {
+        order_by
+            .iter()
+            .filter_map(|column| {
+                column
+                    .index()
+                    .or_else(|| column.name().and_then(|name| rd.field_index(name)))
+                    .map(|index| (index, column.asc()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::{Field, Format, Protocol};
+
+    #[test]
+    fn test_merge_buffer() {
+        let rd = RowDescription::new(&[Field::bigint("id"), Field::text("name")]);
+        let order_by = [OrderBy::DescColumn("id".into())];
+        let mut shards = [vec![9_i64, 6, 3], vec![8, 5, 2], vec![7, 4, 1]];
+        let mut merge = MergeBuffer::default();
+        let mut rows = vec![];
+
+        loop {
+            for (shard, rows) in shards.iter_mut().enumerate() {
+                if !merge.wants(shard) {
+                    continue;
+                }
+                if rows.is_empty() {
+                    merge.finish(shard);
+                } else {
+                    let mut dr = DataRow::new();
+                    dr.add(rows.remove(0)).add("test");
+                    merge
+                        .add(shard, dr.message().unwrap(), &order_by, &rd)
+                        .unwrap();
+                }
+            }
+
+            // Only one row per shard is ever buffered.
+            assert!(merge.heap.len() <= 3);
+
+            match merge.take(0..3) {
+                Some(message) => {
+                    let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+                    rows.push(dr.get::<i64>(0, Format::Text).unwrap());
+                }
+                None => break,
+            }
+        }
+
+        assert_eq!(rows, vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+}
@@ -18,9 +18,11 @@ use super::{
     Address, Cluster, Request,
 };
 
-use std::{mem::replace, time::Duration};
+use std::time::Duration;
 
+mod aggregate;
 mod binding;
+mod merge;
 mod multi_shard;
 mod sort_buffer;
 
@@ -111,11 +113,11 @@ impl Connection {
 
             match &mut self.binding {
                 Binding::Server(existing) => {
-                    let _ = replace(existing, Some(server));
+                    let _ = existing.replace(server);
                 }
 
                 Binding::Replication(existing, _) => {
-                    let _ = replace(existing, Some(server));
+                    let _ = existing.replace(server);
                 }
 
                 Binding::MultiShard(_, _) => {
@@ -184,6 +186,23 @@ impl Connection {
         self.binding.send(messages).await
     }
 
+    /// Update the route the current request is taking.
+    pub fn set_route(&mut self, route: &Route) {
+        self.binding.set_route(route);
+    }
+
+    /// Send different messages to each of the given shards.
+    ///
+    /// Set `parse_complete` to false if the client didn't send a Parse (F)
+    /// message and isn't expecting a reply to it.
+    pub async fn send_sharded(
+        &mut self,
+        messages: Vec<(usize, Vec<Message>)>,
+        parse_complete: bool,
+    ) -> Result<(), Error> {
+        self.binding.send_sharded(messages, parse_complete).await
+    }
+
     /// Send COPY subprotocol data to the right shards.
     pub async fn send_copy(&mut self, rows: Vec<CopyRow>) -> Result<(), Error> {
         self.binding.send_copy(rows).await
@@ -225,6 +244,11 @@ impl Connection {
         self.binding.done()
     }
 
+    /// Server(s) are idle in a transaction.
+    pub fn in_transaction(&self) -> bool {
+        self.binding.in_transaction()
+    }
+
     /// Get connected servers addresses.
     pub fn addr(&mut self) -> Result<Vec<&Address>, Error> {
         Ok(match self.binding {
@@ -252,6 +276,12 @@ impl Connection {
         self.cluster.as_ref().ok_or(Error::NotConnected)
     }
 
+    /// Connected to multiple shards.
+    #[inline]
+    pub fn multi_shard(&self) -> bool {
+        matches!(self.binding, Binding::MultiShard(_, _))
+    }
+
     /// This is an admin database connection.
     #[inline]
     pub fn admin(&self) -> bool {
@@ -272,6 +302,13 @@ impl Connection {
         !self.transaction_mode()
     }
 
+    /// Commit a multi-shard transaction using two-phase commit.
+    ///
+    /// Returns `false` if the transaction was rolled back instead.
+    pub async fn two_phase_commit(&mut self) -> Result<bool, Error> {
+        self.binding.two_phase_commit().await
+    }
+
     /// Execute a query on the binding, if it's connected.
     pub async fn execute(&mut self, query: &str) -> Result<(), Error> {
         self.binding.execute(query).await
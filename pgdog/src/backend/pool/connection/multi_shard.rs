@@ -9,7 +9,7 @@ use crate::{
     },
 };
 
-use super::sort_buffer::SortBuffer;
+use super::{aggregate::AggregateBuffer, merge::MergeBuffer, sort_buffer::SortBuffer};
 
 /// Multi-shard state.
 #[derive(Default, Debug)]
@@ -20,6 +20,8 @@ pub(super) struct MultiShard {
     route: Route,
     /// How many rows we received so far.
     rows: usize,
+    /// Number of DataRow messages received.
+    dr: usize,
     /// Number of ReadyForQuery messages.
     rfq: usize,
     /// Number of CommandComplete messages.
@@ -28,12 +30,28 @@ pub(super) struct MultiShard {
     nd: usize,
     /// Number of CopyInResponse messages.
     ci: usize,
+    /// Number of ParseComplete messages.
+    pc: usize,
+    /// Number of BindComplete messages.
+    bc: usize,
+    /// Number of ParameterDescription messages.
+    pd: usize,
+    /// Shards participating in the current request, if not all of them.
+    participants: Option<Vec<usize>>,
+    /// Client is expecting a ParseComplete message.
+    parse_complete: bool,
+    /// Shards that finished the current request with ReadyForQuery.
+    ready: Vec<usize>,
     /// First RowDescription we received from any shard.
     rd: Option<RowDescription>,
     /// Rewritten CommandComplete message.
     command_complete: Option<Message>,
     /// Sorting buffer.
     sort_buffer: SortBuffer,
+    /// Streaming merge of rows sorted by each shard.
+    merge_buffer: MergeBuffer,
+    /// Aggregate merging buffer.
+    aggregate_buffer: AggregateBuffer,
 }
 
 impl MultiShard {
@@ -43,20 +61,68 @@ impl MultiShard {
             shards,
             route: route.clone(),
             command_complete: None,
+            parse_complete: true,
             ..Default::default()
         }
     }
 
+    /// Route the current request is taking.
+    pub(super) fn set_route(&mut self, route: &Route) {
+        self.route = route.clone();
+    }
+
+    /// Only some of the shards will receive the current request.
+    ///
+    /// Statements sent to them can be different from what the client sent,
+    /// so it may not be expecting a ParseComplete message.
+    pub(super) fn participants(&mut self, shards: Vec<usize>, parse_complete: bool) {
+        self.participants = Some(shards);
+        self.parse_complete = parse_complete;
+    }
+
+    /// Is the shard participating in the current request?
+    pub(super) fn participant(&self, shard: usize) -> bool {
+        self.participants
+            .as_ref()
+            .map(|participants| participants.contains(&shard))
+            .unwrap_or(true)
+    }
+
+    /// Should we read the next message from this shard?
+    pub(super) fn wants(&self, shard: usize) -> bool {
+        self.participant(shard) && !self.ready.contains(&shard) && self.merge_buffer.wants(shard)
+    }
+
+    /// Number of shards we expect to respond to the current request.
+    fn expected(&self) -> usize {
+        self.participants
+            .as_ref()
+            .map(|participants| participants.len())
+            .unwrap_or(self.shards)
+    }
+
+    /// Request is finished, get ready for the next one.
+    fn reset(&mut self) {
+        *self = Self::new(self.shards, &self.route);
+    }
+
     /// Check if the message should be sent to the client, skipped,
     /// or modified.
-    pub(super) fn forward(&mut self, message: Message) -> Result<Option<Message>, super::Error> {
+    pub(super) fn forward(
+        &mut self,
+        shard: usize,
+        message: Message,
+    ) -> Result<Option<Message>, super::Error> {
         let mut forward = None;
         let order_by = self.route.order_by();
+        let shards = self.expected();
 
         match message.code() {
             'Z' => {
                 self.rfq += 1;
-                forward = if self.rfq == self.shards {
+                self.ready.push(shard);
+                forward = if self.rfq == shards {
+                    self.reset();
                     Some(message)
                 } else {
                     None
@@ -64,6 +130,7 @@ impl MultiShard {
             }
 
             'C' => {
+                self.merge_buffer.finish(shard);
                 let cc = CommandComplete::from_bytes(message.to_bytes()?)?;
                 let has_rows = if let Some(rows) = cc.rows()? {
                     self.rows += rows;
@@ -73,14 +140,28 @@ impl MultiShard {
                 };
                 self.cc += 1;
 
-                if self.cc == self.shards {
+                if self.cc == shards {
                     self.sort_buffer.full();
-                    if let Some(ref rd) = self.rd {
-                        self.sort_buffer.sort(order_by, rd);
+                    if let (Some(aggregate), Some(ref rd)) = (self.route.aggregate(), &self.rd) {
+                        // Merged rows are sorted just like regular ones.
+                        let rows = self.aggregate_buffer.take(aggregate, rd)?;
+                        self.rows = rows.len();
+                        for row in rows {
+                            self.sort_buffer.add(row)?;
+                        }
+                        self.sort_buffer
+                            .sort(order_by, &AggregateBuffer::row_description(aggregate, rd));
                     }
 
+                    let rows = if let Some(limit) = self.route.limit() {
+                        self.sort_buffer.limit(limit);
+                        limit.rows(self.rows)
+                    } else {
+                        self.rows
+                    };
+
                     if has_rows {
-                        self.command_complete = Some(cc.rewrite(self.rows)?.message()?);
+                        self.command_complete = Some(cc.rewrite(rows)?.message()?);
                     } else {
                         forward = Some(cc.message()?);
                     }
@@ -94,29 +175,62 @@ impl MultiShard {
                         warn!("RowDescription across shards doesn't match");
                     }
                 } else {
+                    forward = Some(match self.route.aggregate() {
+                        Some(aggregate) => {
+                            AggregateBuffer::row_description(aggregate, &rd).message()?
+                        }
+                        None => message,
+                    });
                     self.rd = Some(rd);
-                    forward = Some(message);
                 }
             }
 
             'I' => {
                 self.nd += 1;
-                if self.nd == self.shards {
+                if self.nd == shards {
                     forward = Some(message);
                 }
             }
 
-            'D' => {
-                if order_by.is_empty() {
+            '1' => {
+                self.pc += 1;
+                if self.pc == shards && self.parse_complete {
+                    forward = Some(message);
+                }
+            }
+
+            '2' => {
+                self.bc += 1;
+                if self.bc == shards {
+                    forward = Some(message);
+                }
+            }
+
+            't' => {
+                self.pd += 1;
+                if self.pd == shards {
                     forward = Some(message);
+                }
+            }
+
+            'D' => {
+                if let (Some(aggregate), Some(rd)) = (self.route.aggregate(), &self.rd) {
+                    self.aggregate_buffer.add(message, aggregate, rd)?;
+                } else if let (false, Some(rd)) = (order_by.is_empty(), &self.rd) {
+                    self.merge_buffer.add(shard, message, order_by, rd)?;
                 } else {
-                    self.sort_buffer.add(message)?;
+                    forward = self.limit(message);
                 }
             }
 
+            'E' => {
+                self.merge_buffer.finish(shard);
+                forward = Some(message);
+            }
+
             'G' => {
                 self.ci += 1;
-                if self.ci == self.shards {
+                if self.ci == shards {
                     forward = Some(message);
                 }
             }
@@ -129,10 +243,59 @@ impl MultiShard {
 
     /// Multi-shard state is ready to send messages.
     pub(super) fn message(&mut self) -> Option<Message> {
+        let shards = (0..self.shards)
+            .filter(|shard| self.participant(*shard))
+            .collect::<Vec<_>>();
+        while let Some(data_row) = self.merge_buffer.take(shards.iter().copied()) {
+            if let Some(data_row) = self.limit(data_row) {
+                return Some(data_row);
+            }
+        }
+
         if let Some(data_row) = self.sort_buffer.take() {
             Some(data_row)
         } else {
             self.command_complete.take()
         }
     }
+
+    /// Skip rows before the offset and after the limit,
+    /// for rows sent to the client as soon as they arrive.
+    fn limit(&mut self, data_row: Message) -> Option<Message> {
+        let row = self.dr;
+        self.dr += 1;
+
+        if self
+            .route
+            .limit()
+            .map(|limit| limit.includes(row))
+            .unwrap_or(true)
+        {
+            Some(data_row)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::messages::ReadyForQuery;
+
+    use super::*;
+
+    #[test]
+    fn test_ready_for_query() {
+        let mut state = MultiShard::new(2, &Route::default());
+
+        // Shard is inside a transaction, but finished the request.
+        let rfq = ReadyForQuery::in_transaction().message().unwrap();
+        assert!(state.forward(0, rfq.clone()).unwrap().is_none());
+        assert!(!state.wants(0));
+        assert!(state.wants(1));
+
+        assert!(state.forward(1, rfq).unwrap().is_some());
+        assert!(state.wants(0));
+        assert!(state.wants(1));
+    }
 }
@@ -3,7 +3,7 @@
 use std::{cmp::Ordering, collections::VecDeque};
 
 use crate::{
-    frontend::router::parser::OrderBy,
+    frontend::router::parser::{Limit, OrderBy},
     net::messages::{DataRow, FromBytes, Message, Protocol, RowDescription, ToBytes},
 };
 
@@ -79,6 +79,14 @@ impl SortBuffer {
         self.buffer.make_contiguous().sort_by(order_by);
     }
 
+    /// Apply offset and limit to sorted rows.
+    pub(super) fn limit(&mut self, limit: &Limit) {
+        self.buffer.drain(..limit.offset().min(self.buffer.len()));
+        if let Some(limit) = limit.limit() {
+            self.buffer.truncate(limit);
+        }
+    }
+
     /// Take messages from buffer.
     pub(super) fn take(&mut self) -> Option<Message> {
         if self.full {
@@ -121,4 +129,33 @@ mod test {
 
         assert_eq!(i, 26);
     }
+
+    #[test]
+    fn test_sort_buffer_limit() {
+        let mut buf = SortBuffer::default();
+        let rd = RowDescription::new(&[Field::bigint("one")]);
+        let stmt = pg_query::parse("SELECT * FROM t LIMIT 5 OFFSET 10").unwrap();
+        let limit = match stmt.protobuf.stmts[0].stmt.as_ref().unwrap().node {
+            Some(pg_query::NodeEnum::SelectStmt(ref stmt)) => Limit::parse(stmt, &None).unwrap(),
+            _ => panic!("not a select"),
+        };
+
+        for i in 0..25_i64 {
+            let mut dr = DataRow::new();
+            dr.add(i);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.sort(&[OrderBy::Desc(1)], &rd);
+        buf.limit(&limit);
+        buf.full();
+
+        let mut rows = vec![];
+        while let Some(message) = buf.take() {
+            let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+            rows.push(dr.get::<i64>(0, Format::Text).unwrap());
+        }
+
+        assert_eq!(rows, vec![14, 13, 12, 11, 10]);
+    }
 }
@@ -234,8 +234,7 @@ impl Inner {
         }
 
         // Update stats
-        let stats = server.stats_mut().reset_last_checkout();
-        self.stats.counts = self.stats.counts + stats;
+        self.stats.checkin(server.stats_mut());
 
         // Ban the pool from serving more clients.
         if server.error() {
@@ -9,6 +9,8 @@
 //! * the new connection loop which runs every time a client asks
 //!   for a new connection to be created
 //!
+//! If two-phase commit is enabled, primaries also run the recovery loop.
+//!
 //! ## Maintenance loop
 //!
 //! The maintenance loop runs every 333ms and removes connections that
@@ -31,11 +33,20 @@
 //! a connection to the server can take ~100ms even inside datacenters, other clients may have returned
 //! connections back to the idle pool in that amount of time, and new connections are no longer needed even
 //! if clients requested ones to be created ~100ms ago.
+//!
+//! ## Recovery loop
+//!
+//! The recovery loop resolves two-phase commit transactions left prepared on the server
+//! by a failure or a restart. It runs after `idle_healthcheck_delay` at startup and then every
+//! `idle_healthcheck_interval`, if there are transactions in doubt.
 
 use std::time::{Duration, Instant};
 
 use super::{Error, Guard, Healtcheck, Pool};
-use crate::backend::Server;
+use crate::backend::{
+    two_pc::{self, two_pc},
+    Server,
+};
 use crate::net::messages::BackendKeyData;
 
 use tokio::time::{interval, sleep, timeout};
@@ -77,10 +88,14 @@ impl Monitor {
         // Delay starting healthchecks to give
         // time for the pool to spin up.
         let pool = self.pool.clone();
-        let (delay, replication_mode) = {
+        let (delay, replication_mode, two_phase_commit) = {
             let lock = pool.lock();
             let config = lock.config();
-            (config.idle_healthcheck_delay(), config.replication_mode)
+            (
+                config.idle_healthcheck_delay(),
+                config.replication_mode,
+                config.two_phase_commit,
+            )
         };
 
         if !replication_mode {
@@ -90,6 +105,17 @@ impl Monitor {
             });
         }
 
+        if two_phase_commit {
+            // Register right away, so transactions in doubt
+            // aren't considered resolved before all servers are checked.
+            two_pc().register(&self.pool.addr().to_string());
+            let pool = self.pool.clone();
+            spawn(async move {
+                sleep(delay).await;
+                Self::recovery(pool).await
+            });
+        }
+
         loop {
             let comms = self.pool.comms();
 
@@ -190,6 +216,45 @@ impl Monitor {
         debug!("healthchecks stopped [{}]", pool.addr());
     }
 
+    /// The two-phase commit recovery loop.
+    async fn recovery(pool: Pool) {
+        let name = pool.addr().to_string();
+        let mut tick = interval(pool.lock().config().idle_healthcheck_interval());
+        let comms = pool.comms();
+        let mut startup = true;
+
+        debug!("two-phase commit recovery running [{}]", name);
+
+        loop {
+            select! {
+                _ = tick.tick() => {
+                    if !pool.lock().online {
+                        break;
+                    }
+
+                    if startup || two_pc().in_doubt() {
+                        match Self::recover(&pool, &name).await {
+                            Ok(_) => startup = false,
+                            Err(err) => error!("two-phase commit recovery error: {} [{}]", err, name),
+                        }
+                    }
+                }
+
+                _ = comms.shutdown.notified() => break,
+            }
+        }
+
+        two_pc().unregister(&name);
+
+        debug!("two-phase commit recovery stopped [{}]", name);
+    }
+
+    /// Resolve transactions in doubt using a dedicated connection.
+    async fn recover(pool: &Pool, name: &str) -> Result<usize, two_pc::Error> {
+        let mut server = Server::connect(pool.addr(), pool.startup_parameters()).await?;
+        two_pc().recover(name, &mut server).await
+    }
+
     /// Perform maintenance on the pool periodically.
     async fn maintenance(pool: Pool) {
         let maintenance_interval = if pool.lock().banned() {
@@ -251,12 +316,17 @@ impl Monitor {
 
             Ok(Err(err)) => {
                 self.pool.ban(Error::ServerError);
-                error!("error connecting to server: {} [{}]", err, self.pool.addr());
+                error!(
+                    parent: &self.pool.addr().span(),
+                    code = err.code(),
+                    %err,
+                    "error connecting to server"
+                );
             }
 
             Err(_) => {
                 self.pool.ban(Error::ServerError);
-                error!("server connection timeout [{}]", self.pool.addr());
+                error!(parent: &self.pool.addr().span(), "server connection timeout");
             }
         }
 
@@ -76,8 +76,7 @@ impl Pool {
                     .map(|server| Guard::new(self.clone(), server));
 
                 if conn.is_some() {
-                    guard.stats.counts.wait_time += elapsed.as_micros();
-                    guard.stats.counts.server_assignment_count += 1;
+                    guard.stats.checkout(elapsed);
                 }
 
                 (
@@ -144,6 +143,18 @@ impl Pool {
         })
     }
 
+    /// Replace this pool with an identical pool from `pools`, if there is one,
+    /// keeping its connections. The reused pool is removed from `pools`.
+    pub(crate) fn reuse(&mut self, pools: &mut Vec<Pool>) {
+        let config = *self.lock().config();
+        if let Some(position) = pools
+            .iter()
+            .position(|pool| pool.addr() == self.addr() && *pool.lock().config() == config)
+        {
+            *self = pools.swap_remove(position);
+        }
+    }
+
     /// Check the connection back into the pool.
     pub(super) fn checkin(&self, server: Server) {
         // Ask for the time before locking.
@@ -1,6 +1,9 @@
 //! A shard is a collection of replicas and a primary.
 
-use crate::{config::LoadBalancingStrategy, net::messages::BackendKeyData};
+use crate::{
+    config::{LoadBalancingStrategy, Role},
+    net::messages::BackendKeyData,
+};
 
 use super::{Error, Guard, Pool, PoolConfig, Replicas, Request};
 
@@ -54,6 +57,17 @@ impl Shard {
         }
     }
 
+    /// Reuse identical pools, keeping their connections.
+    pub(crate) fn reuse(&mut self, pools: &mut Vec<Pool>) {
+        if let Some(ref mut primary) = self.primary {
+            primary.reuse(pools);
+        }
+
+        for replica in &mut self.replicas.pools {
+            replica.reuse(pools);
+        }
+    }
+
     /// Cancel a query if one is running.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
         if let Some(ref primary) = self.primary {
@@ -75,6 +89,22 @@ impl Shard {
         pools
     }
 
+    /// Get all pools with their roles.
+    pub fn pools_with_roles(&self) -> Vec<(Role, Pool)> {
+        let mut pools = vec![];
+        if let Some(primary) = self.primary.clone() {
+            pools.push((Role::Primary, primary));
+        }
+        pools.extend(
+            self.replicas
+                .pools()
+                .iter()
+                .map(|replica| (Role::Replica, replica.clone())),
+        );
+
+        pools
+    }
+
     /// Launch the shard, bringing all pools online.
     pub fn launch(&self) {
         self.pools().iter().for_each(|pool| pool.launch());
@@ -5,6 +5,9 @@ use std::{
     ops::{Add, Div, Sub},
     time::Duration,
 };
+
+use crate::stats::Histogram;
+
 #[derive(Debug, Clone, Default, Copy)]
 pub struct Counts {
     pub xact_count: usize,
@@ -22,7 +25,7 @@ impl Sub for Counts {
 
     fn sub(self, rhs: Self) -> Self::Output {
         Self {
-            xact_count: self.xact_time.saturating_sub(rhs.xact_time),
+            xact_count: self.xact_count.saturating_sub(rhs.xact_count),
             query_count: self.query_count.saturating_sub(rhs.query_count),
             server_assignment_count: self
                 .server_assignment_count
@@ -41,7 +44,7 @@ impl Div<usize> for Counts {
 
     fn div(self, rhs: usize) -> Self::Output {
         Self {
-            xact_count: self.xact_time.saturating_div(rhs),
+            xact_count: self.xact_count.saturating_div(rhs),
             query_count: self.query_count.saturating_div(rhs),
             server_assignment_count: self.server_assignment_count.saturating_div(rhs),
             received: self.received.saturating_div(rhs),
@@ -100,6 +103,17 @@ impl Add for Counts {
     }
 }
 
+/// Latency histograms.
+#[derive(Debug, Clone, Default, Copy)]
+pub struct Histograms {
+    /// Query time.
+    pub query: Histogram,
+    /// Transaction time.
+    pub xact: Histogram,
+    /// Time clients waited for a connection.
+    pub wait: Histogram,
+}
+
 #[derive(Debug, Clone, Default, Copy)]
 pub struct Stats {
     // Total counts.
@@ -107,9 +121,29 @@ pub struct Stats {
     last_counts: Counts,
     // Average counts.
     pub averages: Counts,
+    // Latency since the pool started.
+    pub histograms: Histograms,
 }
 
 impl Stats {
+    /// Add stats of a server returned to the pool.
+    pub fn checkin(&mut self, server: &mut crate::backend::stats::Stats) {
+        self.counts = self.counts + server.reset_last_checkout();
+
+        let (query, xact) = server.reset_latency();
+        self.counts.query_time += query.sum().as_micros() as usize;
+        self.counts.xact_time += xact.sum().as_micros() as usize;
+        self.histograms.query = self.histograms.query + query;
+        self.histograms.xact = self.histograms.xact + xact;
+    }
+
+    /// A client got a connection after waiting for it.
+    pub fn checkout(&mut self, wait: Duration) {
+        self.counts.wait_time += wait.as_micros();
+        self.counts.server_assignment_count += 1;
+        self.histograms.wait.observe(wait);
+    }
+
     /// Calculate averages.
     pub fn calc_averages(&mut self, time: Duration) {
         let secs = time.as_secs() as usize;
@@ -27,6 +27,8 @@ pub fn pool() -> Pool {
             database_name: "pgdog".into(),
             user: "pgdog".into(),
             password: "pgdog".into(),
+            passthrough: None,
+            ..Default::default()
         },
         config,
     });
@@ -36,7 +38,7 @@ pub fn pool() -> Pool {
 
 #[tokio::test(flavor = "current_thread")]
 async fn test_pool_checkout() {
-    crate::logger();
+    crate::logger::init();
 
     let pool = pool();
     let conn = pool.get(&Request::default()).await.unwrap();
@@ -12,6 +12,8 @@ fn replicas() -> Replicas {
             user: "pgdog".into(),
             password: "pgdog".into(),
             database_name: "pgdog".into(),
+            passthrough: None,
+            ..Default::default()
         },
         config: Config {
             max: 1,
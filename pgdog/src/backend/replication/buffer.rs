@@ -2,7 +2,7 @@ use fnv::FnvHashMap as HashMap;
 use fnv::FnvHashSet as HashSet;
 use std::collections::VecDeque;
 
-use crate::frontend::router::sharding::shard_str;
+use crate::frontend::router::sharding::shard_value;
 use crate::net::messages::FromBytes;
 use crate::net::messages::Protocol;
 use crate::net::messages::ToBytes;
@@ -71,10 +71,14 @@ impl Buffer {
                         let column = self
                             .replication_config
                             .sharded_column(table, &columns)
-                            .and_then(|column| update.column(column))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard = shard_str(column, self.replication_config.shards());
+                            .and_then(|column| {
+                                update
+                                    .column(column.position)
+                                    .and_then(|value| value.as_str())
+                                    .map(|value| (value, column.table))
+                            });
+                        if let Some((value, table)) = column {
+                            let shard = shard_value(value, table, self.replication_config.shards());
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -89,10 +93,14 @@ impl Buffer {
                         let column = self
                             .replication_config
                             .sharded_column(table, &columns)
-                            .and_then(|column| insert.column(column))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard = shard_str(column, self.replication_config.shards());
+                            .and_then(|column| {
+                                insert
+                                    .column(column.position)
+                                    .and_then(|value| value.as_str())
+                                    .map(|value| (value, column.table))
+                            });
+                        if let Some((value, table)) = column {
+                            let shard = shard_value(value, table, self.replication_config.shards());
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -6,4 +6,4 @@ pub mod sharded_tables;
 pub use buffer::Buffer;
 pub use config::ReplicationConfig;
 pub use error::Error;
-pub use sharded_tables::ShardedTables;
+pub use sharded_tables::{ShardedColumn, ShardedTables};
@@ -1,6 +1,15 @@
 //! Tables sharded in the database.
 use crate::config::ShardedTable;
 
+/// Sharded column in a row, and the table configuration it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShardedColumn<'a> {
+    /// Position of the column in the row.
+    pub position: usize,
+    /// Sharded table configuration.
+    pub table: &'a ShardedTable,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ShardedTables {
     tables: Vec<ShardedTable>,
@@ -21,7 +30,7 @@ impl ShardedTables {
         &self.tables
     }
 
-    pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<usize> {
+    pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<ShardedColumn<'_>> {
         let table = self.tables.iter().find(|sharded_table| {
             sharded_table
                 .name
@@ -31,6 +40,20 @@ impl ShardedTables {
                 && columns.contains(&sharded_table.column.as_str())
         });
 
-        table.and_then(|t| columns.iter().position(|c| *c == t.column))
+        table.and_then(|t| {
+            columns
+                .iter()
+                .position(|c| *c == t.column)
+                .map(|position| ShardedColumn { position, table: t })
+        })
+    }
+
+    /// Table whose sharding function is used when the table isn't known,
+    /// e.g. for sharding keys in comments.
+    pub fn primary(&self) -> Option<&ShardedTable> {
+        self.tables
+            .iter()
+            .find(|table| table.primary)
+            .or(self.tables.first())
     }
 }
@@ -8,7 +8,7 @@ use tokio::{
     net::TcpStream,
     spawn,
 };
-use tracing::{debug, info, trace, warn};
+use tracing::{debug, info, trace, warn, Span};
 
 use super::{pool::Address, Error, PreparedStatements, Stats};
 use crate::net::{
@@ -19,10 +19,12 @@ use crate::net::{
 };
 use crate::state::State;
 use crate::{
-    auth::scram::Client,
+    auth::{md5, scram::Client, Passthrough},
+    config::TlsMode,
     net::messages::{
-        hello::SslReply, Authentication, BackendKeyData, ErrorResponse, FromBytes, Message,
-        ParameterStatus, Password, Protocol, Query, ReadyForQuery, Startup, Terminate, ToBytes,
+        hello::SslReply, Authentication, BackendKeyData, Bind, ErrorResponse, Execute, FromBytes,
+        Message, ParameterStatus, Parse, Password, Protocol, Query, ReadyForQuery, Startup, Sync,
+        Terminate, ToBytes,
     },
 };
 
@@ -38,6 +40,7 @@ pub struct Server {
     dirty: bool,
     streaming: bool,
     schema_changed: bool,
+    span: Span,
 }
 
 impl Server {
@@ -51,24 +54,28 @@ impl Server {
 
         let mut stream = Stream::plain(stream);
 
-        // Request TLS.
-        stream.write_all(&Startup::tls().to_bytes()?).await?;
-        stream.flush().await?;
+        if addr.tls_mode != TlsMode::Disable {
+            // Request TLS.
+            stream.write_all(&Startup::tls().to_bytes()?).await?;
+            stream.flush().await?;
 
-        let mut ssl = BytesMut::new();
-        ssl.put_u8(stream.read_u8().await?);
-        let ssl = SslReply::from_bytes(ssl.freeze())?;
+            let mut ssl = BytesMut::new();
+            ssl.put_u8(stream.read_u8().await?);
+            let ssl = SslReply::from_bytes(ssl.freeze())?;
 
-        if ssl == SslReply::Yes {
-            let connector = connector()?;
-            let plain = stream.take()?;
+            if ssl == SslReply::Yes {
+                let connector = connector(addr.tls_mode, addr.tls_ca_certificate.as_ref())?;
+                let plain = stream.take()?;
 
-            let server_name = ServerName::try_from(addr.host.clone())?;
+                let server_name = ServerName::try_from(addr.host.clone())?;
 
-            let cipher =
-                tokio_rustls::TlsStream::Client(connector.connect(server_name, plain).await?);
+                let cipher =
+                    tokio_rustls::TlsStream::Client(connector.connect(server_name, plain).await?);
 
-            stream = Stream::tls(cipher);
+                stream = Stream::tls(cipher);
+            } else if addr.tls_mode != TlsMode::Prefer {
+                return Err(Error::TlsRequired);
+            }
         }
 
         stream
@@ -77,7 +84,19 @@ impl Server {
         stream.flush().await?;
 
         // Perform authentication.
-        let mut scram = Client::new(&addr.user, &addr.password);
+        let password = match addr.passthrough {
+            Some(Passthrough::Password(ref password)) => Some(password.as_str()),
+            Some(_) => None,
+            None => Some(addr.password.as_str()),
+        };
+        let mut scram = match addr.passthrough {
+            Some(Passthrough::Scram {
+                ref client_key,
+                ref server_key,
+            }) => Some(Client::passthrough(client_key, server_key)),
+            _ => password.map(|password| Client::new(&addr.user, password)),
+        };
+
         loop {
             let message = stream.read().await?;
 
@@ -92,10 +111,12 @@ impl Server {
                     match auth {
                         Authentication::Ok => break,
                         Authentication::Sasl(_) => {
+                            let scram = scram.as_mut().ok_or(Error::UnsupportedAuth)?;
                             let initial = Password::sasl_initial(&scram.first()?);
                             stream.send_flush(initial).await?;
                         }
                         Authentication::SaslContinue(data) => {
+                            let scram = scram.as_mut().ok_or(Error::UnsupportedAuth)?;
                             scram.server_first(&data)?;
                             let response = Password::PasswordMessage {
                                 response: scram.last()?,
@@ -103,9 +124,22 @@ impl Server {
                             stream.send_flush(response).await?;
                         }
                         Authentication::SaslFinal(data) => {
+                            let scram = scram.as_mut().ok_or(Error::UnsupportedAuth)?;
                             scram.server_last(&data)?;
                         }
-                        Authentication::Md5(_) => return Err(Error::UnsupportedAuth),
+                        Authentication::Md5(salt) => {
+                            let hash = match (&addr.passthrough, password) {
+                                (Some(Passthrough::Md5(hash)), _) => hash.clone(),
+                                (_, Some(password)) => md5::hash(&addr.user, password),
+                                _ => return Err(Error::UnsupportedAuth),
+                            };
+                            let response = Password::cleartext(&md5::encrypt(&hash, &salt));
+                            stream.send_flush(response).await?;
+                        }
+                        Authentication::ClearTextPassword => {
+                            let password = password.ok_or(Error::UnsupportedAuth)?;
+                            stream.send_flush(Password::cleartext(password)).await?;
+                        }
                     }
                 }
 
@@ -152,7 +186,9 @@ impl Server {
 
         let id = key_data.ok_or(Error::NoBackendKeyData)?;
 
-        info!("new server connection [{}]", addr);
+        let span = addr.span();
+        span.record("pid", id.pid);
+        info!(parent: &span, "new server connection");
 
         Ok(Server {
             addr: addr.clone(),
@@ -164,6 +200,7 @@ impl Server {
             dirty: false,
             streaming: false,
             schema_changed: false,
+            span,
         })
     }
 
@@ -349,7 +386,7 @@ impl Server {
         let error = messages.iter().find(|m| m.code() == 'E');
         if let Some(error) = error {
             let error = ErrorResponse::from_bytes(error.to_bytes()?)?;
-            return Err(Error::ExecutionError(error));
+            Err(Error::ExecutionError(error))
         } else {
             Ok(messages)
         }
@@ -369,6 +406,57 @@ impl Server {
             .collect())
     }
 
+    /// Execute a query with text parameters and return all rows.
+    ///
+    /// Parameters are sent separately from the query, using the extended protocol.
+    pub async fn fetch_all_params<T: From<DataRow>>(
+        &mut self,
+        query: &str,
+        params: &[&str],
+    ) -> Result<Vec<T>, Error> {
+        if !self.in_sync() {
+            return Err(Error::NotInSync);
+        }
+
+        debug!("[{}] {} ", self.addr(), query);
+
+        let bind = Bind {
+            params: params
+                .iter()
+                .map(|param| crate::net::messages::Parameter {
+                    len: param.len() as i32,
+                    data: param.as_bytes().to_vec(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        self.send(vec![
+            Parse::new_anonymous(query).message()?,
+            bind.message()?,
+            Execute::default().message()?,
+            Sync.message()?,
+        ])
+        .await?;
+
+        let mut rows = vec![];
+        let mut error = None;
+        loop {
+            let message = self.read().await?;
+            match message.code() {
+                'D' => rows.push(DataRow::from_bytes(message.to_bytes()?)?),
+                'E' => error = Some(ErrorResponse::from_bytes(message.to_bytes()?)?),
+                'Z' => break,
+                _ => (),
+            }
+        }
+
+        if let Some(error) = error {
+            Err(Error::ExecutionError(error))
+        } else {
+            Ok(rows.into_iter().map(T::from).collect())
+        }
+    }
+
     /// Perform a healthcheck on this connection using the provided query.
     pub async fn healthcheck(&mut self, query: &str) -> Result<(), Error> {
         debug!("running healthcheck \"{}\" [{}]", query, self.addr);
@@ -510,8 +598,8 @@ impl Drop for Server {
             // If you see a lot of these, tell your clients
             // to not send queries unless they are willing to stick
             // around for results.
-            let out_of_sync = if self.done() { " " } else { " out of sync " };
-            info!("closing{}server connection [{}]", out_of_sync, self.addr,);
+            let out_of_sync = !self.done();
+            info!(parent: &self.span, out_of_sync, "closing server connection");
 
             spawn(async move {
                 stream.write_all(&Terminate.to_bytes()?).await?;
@@ -541,6 +629,7 @@ mod test {
                 dirty: false,
                 streaming: false,
                 schema_changed: false,
+                span: Span::none(),
             }
         }
     }
@@ -6,7 +6,7 @@ use fnv::FnvHashMap as HashMap;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 
-use crate::{net::messages::BackendKeyData, state::State};
+use crate::{net::messages::BackendKeyData, state::State, stats::Histogram};
 
 use super::pool::Address;
 
@@ -80,6 +80,12 @@ pub struct Stats {
     pub created_at: Instant,
     pub total: Counts,
     pub last_checkout: Counts,
+    /// Query times since checkout.
+    pub query_latency: Histogram,
+    /// Transaction times since checkout.
+    pub xact_latency: Histogram,
+    query_timer: Option<Instant>,
+    xact_timer: Option<Instant>,
 }
 
 impl Stats {
@@ -95,6 +101,10 @@ impl Stats {
             created_at: now,
             total: Counts::default(),
             last_checkout: Counts::default(),
+            query_latency: Histogram::default(),
+            xact_latency: Histogram::default(),
+            query_timer: None,
+            xact_timer: None,
         };
 
         STATS.lock().insert(
@@ -110,6 +120,7 @@ impl Stats {
 
     /// A transaction has been completed.
     pub fn transaction(&mut self) {
+        self.transaction_finished();
         self.total.transactions += 1;
         self.last_checkout.transactions += 1;
         self.state = State::Idle;
@@ -119,6 +130,7 @@ impl Stats {
 
     /// Error occured in a transaction.
     pub fn transaction_error(&mut self) {
+        self.transaction_finished();
         self.total.transactions += 1;
         self.last_checkout.transactions += 1;
         self.state = State::TransactionError;
@@ -135,10 +147,24 @@ impl Stats {
     pub fn query(&mut self) {
         self.total.queries += 1;
         self.last_checkout.queries += 1;
+        if let Some(timer) = self.query_timer.take() {
+            self.query_latency.observe(timer.elapsed());
+        }
+    }
+
+    fn transaction_finished(&mut self) {
+        if let Some(timer) = self.xact_timer.take() {
+            self.xact_latency.observe(timer.elapsed());
+        }
     }
 
     /// Manual state change.
     pub fn state(&mut self, state: State) {
+        if state == State::Active {
+            let now = Instant::now();
+            self.query_timer.get_or_insert(now);
+            self.xact_timer.get_or_insert(now);
+        }
         let update = self.state != state;
         self.state = state;
         if update {
@@ -196,4 +222,12 @@ impl Stats {
         self.last_checkout = Counts::default();
         counts
     }
+
+    /// Reset query and transaction times since checkout.
+    pub fn reset_latency(&mut self) -> (Histogram, Histogram) {
+        let latency = (self.query_latency, self.xact_latency);
+        self.query_latency = Histogram::default();
+        self.xact_latency = Histogram::default();
+        latency
+    }
 }
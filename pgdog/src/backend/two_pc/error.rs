@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("two-phase commit log is corrupted: \"{0}\"")]
+    Corrupted(String),
+
+    #[error("two-phase commit log is not loaded")]
+    NotLoaded,
+
+    #[error("{0}")]
+    Backend(Box<crate::backend::Error>),
+}
+
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        Self::Backend(Box::new(value))
+    }
+}
@@ -0,0 +1,129 @@
+//! Durable log of two-phase commit transactions.
+
+use std::{collections::HashMap, path::Path};
+
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use super::{Decision, Error};
+
+/// Append-only log of two-phase commit transactions that haven't finished.
+///
+/// Each line is `<state> <transaction id>`, where state is `prepare`, `commit`, `rollback`
+/// or `done`. Transactions that were prepared but not committed are rolled back.
+/// Every write is flushed to disk before returning.
+#[derive(Debug)]
+pub struct TransactionLog {
+    file: File,
+}
+
+impl TransactionLog {
+    /// Open the log and get the decisions for transactions that haven't finished.
+    ///
+    /// The log is rewritten to contain only those transactions.
+    pub async fn open(path: &Path) -> Result<(Self, HashMap<String, Decision>), Error> {
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let decisions = Self::parse(&contents)?;
+
+        // Compact the log, keeping only transactions in doubt.
+        let mut compacted = String::new();
+        for (gid, decision) in &decisions {
+            compacted.push_str(&Self::line(gid, decision.state()));
+        }
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, compacted).await?;
+        File::open(&tmp).await?.sync_all().await?;
+        tokio::fs::rename(&tmp, path).await?;
+
+        let file = OpenOptions::new().append(true).open(path).await?;
+
+        Ok((Self { file }, decisions))
+    }
+
+    /// Durably record the state of a transaction.
+    pub async fn write(&mut self, gid: &str, state: &str) -> Result<(), Error> {
+        self.file
+            .write_all(Self::line(gid, state).as_bytes())
+            .await?;
+        self.file.sync_data().await?;
+        Ok(())
+    }
+
+    fn line(gid: &str, state: &str) -> String {
+        format!("{} {}\n", state, gid)
+    }
+
+    fn parse(contents: &str) -> Result<HashMap<String, Decision>, Error> {
+        let mut decisions = HashMap::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (state, gid) = line
+                .split_once(' ')
+                .ok_or_else(|| Error::Corrupted(line.to_string()))?;
+
+            match state {
+                "prepare" | "rollback" => {
+                    decisions.insert(gid.to_string(), Decision::Rollback);
+                }
+                "commit" => {
+                    decisions.insert(gid.to_string(), Decision::Commit);
+                }
+                "done" => {
+                    decisions.remove(gid);
+                }
+                _ => return Err(Error::Corrupted(line.to_string())),
+            }
+        }
+
+        Ok(decisions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_transaction_log() {
+        let path =
+            std::env::temp_dir().join(format!("pgdog_2pc_{}.log", crate::util::random_string(8)));
+
+        let (mut log, decisions) = TransactionLog::open(&path).await.unwrap();
+        assert!(decisions.is_empty());
+
+        log.write("one", "prepare").await.unwrap();
+        log.write("two", "prepare").await.unwrap();
+        log.write("two", "commit").await.unwrap();
+        log.write("three", "prepare").await.unwrap();
+        log.write("three", "commit").await.unwrap();
+        log.write("three", "done").await.unwrap();
+        log.write("four", "prepare").await.unwrap();
+        log.write("four", "rollback").await.unwrap();
+        drop(log);
+
+        let (_log, decisions) = TransactionLog::open(&path).await.unwrap();
+        assert_eq!(decisions.len(), 3);
+        assert_eq!(decisions["one"], Decision::Rollback);
+        assert_eq!(decisions["two"], Decision::Commit);
+        assert_eq!(decisions["four"], Decision::Rollback);
+
+        // Compacted.
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.contains("rollback one\n"));
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! Two-phase commit for transactions that wrote to multiple shards.
+//!
+//! Each shard prepares the transaction with `PREPARE TRANSACTION` before any of them
+//! commits it with `COMMIT PREPARED`. The decision to commit is written to a durable
+//! log first, so transactions left prepared by a failure or a restart can be resolved
+//! by the pool monitor. See [`TwoPc::recover`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tracing::{info, warn};
+
+use crate::{backend::Server, util::random_string};
+
+pub mod error;
+pub mod log;
+
+pub use error::Error;
+pub use log::TransactionLog;
+
+/// Prefix of prepared transaction ids created by us.
+static PREFIX: &str = "__pgdog_2pc_";
+
+static TWO_PC: Lazy<TwoPc> = Lazy::new(TwoPc::default);
+
+/// Get the two-phase commit manager.
+pub fn two_pc() -> &'static TwoPc {
+    &TWO_PC
+}
+
+/// What should happen to a prepared transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Commit,
+    Rollback,
+}
+
+impl Decision {
+    /// State recorded in the transaction log.
+    pub(super) fn state(&self) -> &'static str {
+        match self {
+            Self::Commit => "commit",
+            Self::Rollback => "rollback",
+        }
+    }
+
+    /// Query that resolves the prepared transaction.
+    pub fn query(&self, gid: &str) -> String {
+        match self {
+            Self::Commit => format!("COMMIT PREPARED '{}'", gid),
+            Self::Rollback => format!("ROLLBACK PREPARED '{}'", gid),
+        }
+    }
+}
+
+/// Transaction that may still be prepared on some of the servers.
+#[derive(Debug)]
+struct InDoubt {
+    decision: Decision,
+    /// Servers checked by recovery where the transaction is no longer prepared.
+    resolved: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Transactions that aren't known to be finished on all servers.
+    in_doubt: HashMap<String, InDoubt>,
+    /// Transactions currently being committed by clients.
+    active: HashSet<String>,
+    /// Servers running recovery and the number of pools connected to them.
+    servers: HashMap<String, usize>,
+    /// Unique to this process, so transaction ids don't repeat after a restart.
+    instance: String,
+    /// Transaction log was loaded at startup.
+    loaded: bool,
+    counter: usize,
+}
+
+/// Two-phase commit manager.
+#[derive(Debug, Default)]
+pub struct TwoPc {
+    log: tokio::sync::Mutex<Option<TransactionLog>>,
+    state: Mutex<State>,
+}
+
+impl TwoPc {
+    /// Load the transaction log. Transactions left in doubt will be resolved by recovery.
+    pub async fn init(&self, path: &Path) -> Result<(), Error> {
+        let (log, decisions) = TransactionLog::open(path).await?;
+
+        if !decisions.is_empty() {
+            info!(
+                "{} two-phase commit transactions need recovery",
+                decisions.len()
+            );
+        }
+
+        *self.log.lock().await = Some(log);
+
+        let mut state = self.state.lock();
+        state.loaded = true;
+        state.instance = random_string(8).to_lowercase();
+        state.in_doubt = decisions
+            .into_iter()
+            .map(|(gid, decision)| {
+                (
+                    gid,
+                    InDoubt {
+                        decision,
+                        resolved: HashSet::new(),
+                    },
+                )
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Transaction log is loaded. It's only loaded at startup,
+    /// so enabling two-phase commit requires a restart.
+    pub fn loaded(&self) -> bool {
+        self.state.lock().loaded
+    }
+
+    /// Start a new two-phase commit transaction and get its id.
+    pub fn begin(&self) -> String {
+        let mut state = self.state.lock();
+        state.counter += 1;
+        let gid = format!("{}{}_{}", PREFIX, state.instance, state.counter);
+        state.active.insert(gid.clone());
+        gid
+    }
+
+    /// Transaction id used on a shard.
+    ///
+    /// Prepared transaction ids are unique per PostgreSQL server,
+    /// and multiple shards can be hosted on the same one.
+    pub fn participant(gid: &str, shard: usize) -> String {
+        format!("{}_{}", gid, shard)
+    }
+
+    /// Transaction id logged for the shard's transaction id.
+    fn transaction(participant: &str) -> Option<&str> {
+        participant
+            .strip_prefix(PREFIX)
+            .and_then(|_| participant.rsplit_once('_'))
+            .map(|(gid, _)| gid)
+    }
+
+    /// Record that the transaction is about to be prepared.
+    /// Until it's committed, recovery will roll it back.
+    pub async fn prepare(&self, gid: &str) -> Result<(), Error> {
+        self.write(gid, "prepare").await
+    }
+
+    /// Record the decision to commit the transaction.
+    pub async fn commit(&self, gid: &str) -> Result<(), Error> {
+        self.write(gid, Decision::Commit.state()).await
+    }
+
+    /// Transaction is no longer being handled by the client.
+    ///
+    /// If it was resolved on all servers, it's removed from the log.
+    /// Otherwise, the decision is logged and recovery will finish it.
+    pub async fn finish(&self, gid: &str, decision: Decision, resolved: bool) -> Result<(), Error> {
+        if resolved {
+            self.write(gid, "done").await?;
+        } else if let Err(err) = self.write(gid, decision.state()).await {
+            // Transactions that weren't committed are rolled back anyway.
+            warn!("two-phase commit log write failed: {}", err);
+        }
+
+        let mut state = self.state.lock();
+        state.active.remove(gid);
+        if !resolved {
+            warn!("two-phase commit transaction \"{}\" left for recovery", gid);
+            state.in_doubt.insert(
+                gid.to_string(),
+                InDoubt {
+                    decision,
+                    resolved: HashSet::new(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Register a server for recovery.
+    ///
+    /// Transactions in doubt are removed from the log only once
+    /// they are resolved on all registered servers.
+    pub fn register(&self, server: &str) {
+        *self
+            .state
+            .lock()
+            .servers
+            .entry(server.to_string())
+            .or_default() += 1;
+    }
+
+    /// Server no longer runs recovery.
+    pub fn unregister(&self, server: &str) {
+        let mut state = self.state.lock();
+        if let Some(pools) = state.servers.get_mut(server) {
+            *pools -= 1;
+            if *pools == 0 {
+                state.servers.remove(server);
+            }
+        }
+    }
+
+    /// There are transactions waiting for recovery.
+    pub fn in_doubt(&self) -> bool {
+        let state = self.state.lock();
+        state.in_doubt.keys().any(|gid| !state.active.contains(gid))
+    }
+
+    /// Resolve transactions we left prepared on the server.
+    ///
+    /// Prepared transactions not found in the log belong to another instance
+    /// or are in progress, and are not touched. Returns the number of transactions resolved.
+    pub async fn recover(&self, name: &str, server: &mut Server) -> Result<usize, Error> {
+        let prepared: Vec<String> = server
+            .fetch_all("SELECT gid FROM pg_prepared_xacts WHERE database = current_database()")
+            .await?;
+
+        let mut recovered = 0;
+        let mut remaining = HashSet::new();
+
+        for participant in prepared {
+            let Some(gid) = Self::transaction(&participant) else {
+                continue;
+            };
+
+            let decision = {
+                let state = self.state.lock();
+                if state.active.contains(gid) {
+                    None
+                } else {
+                    state.in_doubt.get(gid).map(|in_doubt| in_doubt.decision)
+                }
+            };
+
+            let Some(decision) = decision else {
+                continue;
+            };
+
+            match server.execute_checked(&decision.query(&participant)).await {
+                Ok(_) => {
+                    info!(
+                        "recovered two-phase commit transaction \"{}\" ({:?}) [{}]",
+                        participant, decision, name
+                    );
+                    recovered += 1;
+                }
+                Err(err) => {
+                    warn!(
+                        "failed to recover two-phase commit transaction \"{}\": {} [{}]",
+                        participant, err, name
+                    );
+                    remaining.insert(gid.to_string());
+                }
+            }
+        }
+
+        for gid in self.resolved(name, &remaining) {
+            self.write(&gid, "done").await?;
+        }
+
+        Ok(recovered)
+    }
+
+    /// Mark transactions as resolved on the server and
+    /// get the ones resolved on all servers.
+    fn resolved(&self, name: &str, remaining: &HashSet<String>) -> Vec<String> {
+        let mut state = self.state.lock();
+        let State {
+            in_doubt,
+            active,
+            servers,
+            ..
+        } = &mut *state;
+
+        let mut done = vec![];
+        for (gid, transaction) in in_doubt.iter_mut() {
+            if active.contains(gid) || remaining.contains(gid) {
+                continue;
+            }
+            transaction.resolved.insert(name.to_string());
+            if servers
+                .keys()
+                .all(|server| transaction.resolved.contains(server))
+            {
+                done.push(gid.clone());
+            }
+        }
+
+        for gid in &done {
+            in_doubt.remove(gid);
+        }
+
+        done
+    }
+
+    async fn write(&self, gid: &str, state: &str) -> Result<(), Error> {
+        self.log
+            .lock()
+            .await
+            .as_mut()
+            .ok_or(Error::NotLoaded)?
+            .write(gid, state)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_participant() {
+        let gid = format!("{}abcd_1", PREFIX);
+        let participant = TwoPc::participant(&gid, 12);
+        assert_eq!(TwoPc::transaction(&participant), Some(gid.as_str()));
+        assert_eq!(TwoPc::transaction("other_transaction_1"), None);
+    }
+}
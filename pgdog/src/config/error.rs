@@ -16,6 +16,15 @@ pub enum Error {
 
     #[error("{0}")]
     Url(#[from] url::ParseError),
+
+    #[error("user \"{0}\" has a hashed password, server_password is required")]
+    ServerPassword(String),
+
+    #[error("tls_client_required requires tls_client_ca_certificate")]
+    TlsClientCa,
+
+    #[error("{0}")]
+    Watch(#[from] notify::Error),
 }
 
 impl Error {
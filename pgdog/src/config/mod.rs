@@ -3,6 +3,7 @@
 pub mod error;
 pub mod overrides;
 pub mod url;
+pub mod watcher;
 
 use error::Error;
 pub use overrides::Overrides;
@@ -19,6 +20,7 @@ use serde::{Deserialize, Serialize};
 use tracing::info;
 use tracing::warn;
 
+use crate::auth::Secret;
 use crate::util::random_string;
 
 static CONFIG: Lazy<ArcSwap<ConfigAndUsers>> =
@@ -36,6 +38,11 @@ pub fn load(config: &PathBuf, users: &PathBuf) -> Result<ConfigAndUsers, Error>
     Ok(config)
 }
 
+/// Replace the configuration pooler-wide.
+pub fn set(config: ConfigAndUsers) {
+    CONFIG.store(Arc::new(config));
+}
+
 /// Load configuration from a list of database URLs.
 pub fn from_urls(urls: &[String]) -> Result<ConfigAndUsers, Error> {
     let config = ConfigAndUsers::from_urls(urls)?;
@@ -117,6 +124,9 @@ impl ConfigAndUsers {
             Users::default()
         };
 
+        config.check()?;
+        users.check(&config)?;
+
         Ok(ConfigAndUsers {
             config,
             users,
@@ -149,6 +159,15 @@ pub struct Config {
 }
 
 impl Config {
+    /// Check settings that depend on each other.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.general.tls_client_required && self.general.tls_client_ca_certificate.is_none() {
+            return Err(Error::TlsClientCa);
+        }
+
+        Ok(())
+    }
+
     /// Organize all databases by name for quicker retrival.
     pub fn databases(&self) -> HashMap<String, Vec<Vec<Database>>> {
         let mut databases = HashMap::new();
@@ -228,6 +247,18 @@ pub struct General {
     /// Rollback timeout.
     #[serde(default = "General::rollback_timeout")]
     pub rollback_timeout: u64,
+    /// Maximum number of clients connected to pgDog.
+    #[serde(default)]
+    pub max_client_connections: Option<usize>,
+    /// How long clients over a rate limit wait before their query is rejected (ms).
+    #[serde(default)]
+    pub rate_limit_timeout: u64,
+    /// Disconnect clients idle in transaction for longer than this (ms).
+    #[serde(default)]
+    pub idle_in_transaction_timeout: Option<u64>,
+    /// Disconnect clients idle outside a transaction for longer than this (ms).
+    #[serde(default)]
+    pub client_idle_timeout: Option<u64>,
     /// Load balancing strategy.
     #[serde(default = "General::load_balancing_strategy")]
     pub load_balancing_strategy: LoadBalancingStrategy,
@@ -235,6 +266,11 @@ pub struct General {
     pub tls_certificate: Option<PathBuf>,
     /// TLS private key.
     pub tls_private_key: Option<PathBuf>,
+    /// CA bundle used to verify client certificates.
+    pub tls_client_ca_certificate: Option<PathBuf>,
+    /// Require clients to connect with TLS and present a certificate.
+    #[serde(default)]
+    pub tls_client_required: bool,
     /// Shutdown timeout.
     #[serde(default = "General::default_shutdown_timeout")]
     pub shutdown_timeout: u64,
@@ -243,9 +279,54 @@ pub struct General {
     /// Broadcast port.
     #[serde(default = "General::broadcast_port")]
     pub broadcast_port: u16,
-    /// Load queries to file (warning: slow, don't use in production).
+    /// Log queries to this file, one JSON object per line.
     #[serde(default)]
     pub query_log: Option<PathBuf>,
+    /// Fraction of queries to log, between 0 and 1.
+    #[serde(default = "General::query_log_sample_rate")]
+    pub query_log_sample_rate: f64,
+    /// Always log queries that take at least this long (ms).
+    #[serde(default)]
+    pub query_log_slow_threshold: Option<u64>,
+    /// Rotate the query log when it reaches this size (bytes).
+    #[serde(default = "General::query_log_max_size")]
+    pub query_log_max_size: u64,
+    /// Number of rotated query logs to keep.
+    #[serde(default = "General::query_log_max_files")]
+    pub query_log_max_files: usize,
+    /// Collect statistics for each query fingerprint, shown with `SHOW QUERIES`.
+    #[serde(default)]
+    pub query_stats: bool,
+    /// Maximum number of query fingerprints to keep statistics for.
+    /// The least executed ones are removed to make room for new ones.
+    #[serde(default = "General::query_stats_max")]
+    pub query_stats_max: usize,
+    /// Commit transactions that wrote to multiple shards using two-phase commit.
+    /// Can only be enabled at startup.
+    #[serde(default)]
+    pub two_phase_commit: bool,
+    /// Durable log of two-phase commit transactions, used for recovery.
+    /// Can only be changed at startup.
+    #[serde(default = "General::two_phase_commit_log")]
+    pub two_phase_commit_log: PathBuf,
+    /// User from users.toml that runs `auth_query` to authenticate users not in users.toml.
+    pub auth_user: Option<String>,
+    /// Query returning the user's password hash. The user name is bound to `$1`.
+    #[serde(default = "General::auth_query")]
+    pub auth_query: String,
+    /// How long to cache passwords returned by `auth_query`.
+    #[serde(default = "General::default_auth_query_ttl")]
+    pub auth_query_ttl: u64,
+    /// Reload configuration when pgdog.toml or users.toml change.
+    /// Can be enabled and disabled on reload.
+    #[serde(default)]
+    pub watch_config: bool,
+    /// Reload after the files haven't changed for this long.
+    #[serde(default = "General::default_watch_config_delay")]
+    pub watch_config_delay: u64,
+    /// Log output format.
+    #[serde(default)]
+    pub log_format: LogFormat,
 }
 
 impl Default for General {
@@ -262,13 +343,33 @@ impl Default for General {
             idle_healthcheck_delay: Self::idle_healthcheck_delay(),
             ban_timeout: Self::ban_timeout(),
             rollback_timeout: Self::rollback_timeout(),
+            max_client_connections: None,
+            rate_limit_timeout: 0,
+            idle_in_transaction_timeout: None,
+            client_idle_timeout: None,
             load_balancing_strategy: Self::load_balancing_strategy(),
             tls_certificate: None,
             tls_private_key: None,
+            tls_client_ca_certificate: None,
+            tls_client_required: false,
             shutdown_timeout: Self::default_shutdown_timeout(),
             broadcast_address: None,
             broadcast_port: Self::broadcast_port(),
             query_log: None,
+            query_log_sample_rate: Self::query_log_sample_rate(),
+            query_log_slow_threshold: None,
+            query_log_max_size: Self::query_log_max_size(),
+            query_log_max_files: Self::query_log_max_files(),
+            query_stats: false,
+            query_stats_max: Self::query_stats_max(),
+            two_phase_commit: false,
+            two_phase_commit_log: Self::two_phase_commit_log(),
+            auth_user: None,
+            auth_query: Self::auth_query(),
+            auth_query_ttl: Self::default_auth_query_ttl(),
+            watch_config: false,
+            watch_config_delay: Self::default_watch_config_delay(),
+            log_format: LogFormat::default(),
         }
     }
 }
@@ -294,6 +395,38 @@ impl General {
         1
     }
 
+    fn two_phase_commit_log() -> PathBuf {
+        PathBuf::from("pgdog_2pc.log")
+    }
+
+    fn auth_query() -> String {
+        "SELECT usename, passwd FROM pg_shadow WHERE usename = $1".into()
+    }
+
+    fn default_auth_query_ttl() -> u64 {
+        60_000
+    }
+
+    fn default_watch_config_delay() -> u64 {
+        1_000
+    }
+
+    fn query_log_sample_rate() -> f64 {
+        1.0
+    }
+
+    fn query_log_max_size() -> u64 {
+        100 * 1024 * 1024
+    }
+
+    fn query_log_max_files() -> usize {
+        5
+    }
+
+    fn query_stats_max() -> usize {
+        5_000
+    }
+
     fn healthcheck_interval() -> u64 {
         30_000
     }
@@ -331,6 +464,26 @@ impl General {
         Duration::from_millis(self.shutdown_timeout)
     }
 
+    /// How long to cache passwords returned by `auth_query`.
+    pub fn auth_query_ttl(&self) -> Duration {
+        Duration::from_millis(self.auth_query_ttl)
+    }
+
+    /// How long to wait for config files to stop changing before reloading.
+    pub fn watch_config_delay(&self) -> Duration {
+        Duration::from_millis(self.watch_config_delay)
+    }
+
+    /// Rate limit timeout.
+    pub fn rate_limit_timeout(&self) -> Duration {
+        Duration::from_millis(self.rate_limit_timeout)
+    }
+
+    /// Queries taking at least this long are always logged.
+    pub fn query_log_slow_threshold(&self) -> Option<Duration> {
+        self.query_log_slow_threshold.map(Duration::from_millis)
+    }
+
     /// Get TLS config, if any.
     pub fn tls(&self) -> Option<(&PathBuf, &PathBuf)> {
         if let Some(cert) = &self.tls_certificate {
@@ -344,7 +497,12 @@ impl General {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct Stats {}
+pub struct Stats {
+    /// Serve metrics in OpenMetrics format on this port.
+    pub openmetrics_port: Option<u16>,
+    /// Address for the metrics endpoint, defaults to `general.host`.
+    pub openmetrics_host: Option<String>,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy, Eq, Ord, PartialOrd)]
 #[serde(rename_all = "snake_case")]
@@ -385,6 +543,14 @@ pub struct Database {
     pub user: Option<String>,
     /// Use this password to login, overriding the userlist.
     pub password: Option<String>,
+    /// TLS mode used to connect to the database.
+    #[serde(default)]
+    pub server_tls_mode: TlsMode,
+    /// CA bundle used to verify the server certificate.
+    /// The system's root certificates are used if not set.
+    pub server_tls_ca_certificate: Option<PathBuf>,
+    /// Maximum number of clients connected to this database.
+    pub max_client_connections: Option<usize>,
     // Maximum number of connections to this database from this pooler.
     // #[serde(default = "Database::max_connections")]
     // pub max_connections: usize,
@@ -401,6 +567,45 @@ impl Database {
     }
 }
 
+/// TLS mode used to connect to the server, same as `sslmode` in libpq.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Ord, PartialOrd,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TlsMode {
+    /// Don't use TLS.
+    Disable,
+    /// Use TLS if the server supports it, without verifying the certificate.
+    #[default]
+    Prefer,
+    /// Require TLS, without verifying the certificate.
+    Require,
+    /// Require TLS and verify that the certificate is signed by a trusted CA.
+    VerifyCa,
+    /// Same as `VerifyCa` and verify that the certificate matches the host name.
+    VerifyFull,
+}
+
+/// Log output format.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Ord, PartialOrd, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
@@ -436,6 +641,25 @@ impl Users {
 
         users
     }
+
+    /// Check that users with hashed passwords can connect to the servers.
+    pub fn check(&self, config: &Config) -> Result<(), Error> {
+        for user in &self.users {
+            let hashed = !matches!(Secret::parse(&user.password), Secret::Plain(_));
+            let server_password = user.server_password.is_some()
+                || config
+                    .databases
+                    .iter()
+                    .filter(|database| database.name == user.database)
+                    .all(|database| database.password.is_some());
+
+            if hashed && !server_password {
+                return Err(Error::ServerPassword(user.name.clone()));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// User allowed to connect to pgDog.
@@ -445,7 +669,8 @@ pub struct User {
     pub name: String,
     /// Database name, from pgdog.toml.
     pub database: String,
-    /// User's password.
+    /// User's password, in plain text or hashed like PostgreSQL does,
+    /// i.e. a SCRAM-SHA-256 verifier or an MD5 hash.
     pub password: String,
     /// Pool size for this user pool, overriding `default_pool_size`.
     pub pool_size: Option<usize>,
@@ -459,11 +684,44 @@ pub struct User {
     pub server_password: Option<String>,
     /// Statement timeout.
     pub statement_timeout: Option<u64>,
+    /// Idle in transaction timeout, overriding `idle_in_transaction_timeout`.
+    pub idle_in_transaction_timeout: Option<u64>,
+    /// Client idle timeout, overriding `client_idle_timeout`.
+    pub client_idle_timeout: Option<u64>,
+    /// Maximum number of clients connected as this user.
+    pub max_client_connections: Option<usize>,
+    /// Maximum number of queries per second, from all clients of this user.
+    pub query_rate_limit: Option<u32>,
+    /// Maximum number of transactions per second, from all clients of this user.
+    pub transaction_rate_limit: Option<u32>,
     /// Relication mode.
     #[serde(default)]
     pub replication_mode: bool,
     /// Sharding into this database.
     pub replication_sharding: Option<String>,
+    /// How the client should authenticate.
+    #[serde(default)]
+    pub auth_method: AuthMethod,
+    /// Common name (CN) of the client certificate, if using `cert` authentication.
+    /// Defaults to the user name.
+    pub tls_common_name: Option<String>,
+}
+
+/// Client authentication method.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// SCRAM-SHA-256.
+    #[default]
+    Scram,
+    /// MD5 hash of the password, salted.
+    Md5,
+    /// Password in clear text. Use with TLS only.
+    Plain,
+    /// No password required.
+    Trust,
+    /// TLS client certificate, verified with `tls_client_ca_certificate`.
+    Cert,
 }
 
 /// Admin database settings.
@@ -516,7 +774,7 @@ fn admin_password() -> String {
 }
 
 /// Sharded table.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct ShardedTable {
     /// Database this table belongs to.
     pub database: String,
@@ -528,18 +786,128 @@ pub struct ShardedTable {
     /// This table is the primary sharding anchor (e.g. "users").
     #[serde(default)]
     pub primary: bool,
+    /// Data type of the sharding column.
+    #[serde(default)]
+    pub data_type: DataType,
+    /// Function used to map sharding keys to shards.
+    #[serde(default)]
+    pub sharding_function: ShardingFunction,
+    /// Integer ranges mapped to shards, used by the `range` function.
+    #[serde(default)]
+    pub ranges: Vec<ShardRange>,
+    /// Values mapped to shards, used by the `list` function.
+    #[serde(default)]
+    pub lists: Vec<ShardList>,
+}
+
+/// Data type of the sharding column.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    /// `BIGINT`, or any other integer. UUIDs are detected automatically.
+    #[default]
+    Bigint,
+    /// `UUID`.
+    Uuid,
+    /// `VARCHAR` or `TEXT`, hashed the same way as `hashtext`.
+    #[serde(alias = "text")]
+    Varchar,
+}
+
+/// Function used to map sharding keys to shards.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardingFunction {
+    /// PostgreSQL hash functions, compatible with hash partitioning.
+    #[default]
+    Hash,
+    /// Integer ranges, see [`ShardRange`].
+    Range,
+    /// Explicit values, see [`ShardList`].
+    List,
+    /// Integer modulo number of shards.
+    Modulo,
+}
+
+/// Integer range mapped to a shard.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardRange {
+    /// Start of the range, inclusive. Unbounded if not set.
+    pub start: Option<i64>,
+    /// End of the range, exclusive. Unbounded if not set.
+    pub end: Option<i64>,
+    /// Shard number.
+    pub shard: usize,
+}
+
+impl ShardRange {
+    /// Value is in this range.
+    pub fn contains(&self, value: i64) -> bool {
+        self.start.map(|start| value >= start).unwrap_or(true)
+            && self.end.map(|end| value < end).unwrap_or(true)
+    }
+}
+
+/// Values mapped to a shard.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardList {
+    /// Sharding key values.
+    pub values: Vec<ShardListValue>,
+    /// Shard number.
+    pub shard: usize,
+}
+
+/// Value in a sharding list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ShardListValue {
+    Integer(i64),
+    String(String),
 }
 
 /// Queries with manual routing rules.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ManualQuery {
     pub fingerprint: String,
+    /// Maximum number of these queries per second, from all clients.
+    #[serde(default)]
+    pub query_rate_limit: Option<u32>,
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_hashed_password() {
+        let config: Config = toml::from_str(
+            r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+"#,
+        )
+        .unwrap();
+
+        let mut users: Users = toml::from_str(
+            r#"
+[[users]]
+name = "pgdog"
+database = "pgdog"
+password = "SCRAM-SHA-256$4096:pMgsDoE5T1NAZabp4LxtSw==$I2dN5urqnaiw8lsHv5EZ0wL9tABTeeGdhwE7T9lmnis=:/1vwrzDpGLMqVYEIpXew+O0PqdpQAnQVUjpA7P/OAPw="
+"#,
+        )
+        .unwrap();
+        assert!(users.check(&config).is_err());
+
+        users.users[0].server_password = Some("pgdog".into());
+        assert!(users.check(&config).is_ok());
+
+        users.users[0].password = "pgdog".into();
+        users.users[0].server_password = None;
+        assert!(users.check(&config).is_ok());
+    }
+
     #[test]
     fn test_basic() {
         let source = r#"
@@ -564,4 +932,108 @@ name = "pgdog_routing"
         assert_eq!(config.databases[0].name, "production");
         assert_eq!(config.plugins[0].name, "pgdog_routing");
     }
+
+    #[test]
+    fn test_sharding_function() {
+        let source = r#"
+[[databases]]
+name = "sharded"
+host = "127.0.0.1"
+
+[[sharded_tables]]
+database = "sharded"
+name = "events"
+column = "created_at"
+sharding_function = "range"
+ranges = [
+    { end = 100, shard = 0 },
+    { start = 100, shard = 1 },
+]
+
+[[sharded_tables]]
+database = "sharded"
+name = "tenants"
+column = "tenant_id"
+sharding_function = "list"
+lists = [
+    { values = [1, "acme"], shard = 1 },
+]
+"#;
+
+        let config: Config = toml::from_str(source).unwrap();
+        let range = &config.sharded_tables[0];
+        assert_eq!(range.sharding_function, ShardingFunction::Range);
+        assert!(range.ranges[0].contains(-5));
+        assert!(!range.ranges[0].contains(100));
+        assert!(range.ranges[1].contains(100));
+
+        let list = &config.sharded_tables[1];
+        assert_eq!(list.sharding_function, ShardingFunction::List);
+        assert_eq!(
+            list.lists[0].values,
+            vec![
+                ShardListValue::Integer(1),
+                ShardListValue::String("acme".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auth_method() {
+        let source = r#"
+[[users]]
+name = "legacy"
+database = "pgdog"
+password = "pgdog"
+auth_method = "md5"
+
+[[users]]
+name = "pgdog"
+database = "pgdog"
+password = "pgdog"
+"#;
+
+        let users: Users = toml::from_str(source).unwrap();
+        assert_eq!(users.users[0].auth_method, AuthMethod::Md5);
+        assert_eq!(users.users[1].auth_method, AuthMethod::Scram);
+    }
+
+    #[test]
+    fn test_server_tls_mode() {
+        let source = r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+
+[[databases]]
+name = "pgdog"
+host = "db.example.com"
+role = "replica"
+server_tls_mode = "verify-full"
+server_tls_ca_certificate = "/etc/ssl/ca.pem"
+"#;
+
+        let config: Config = toml::from_str(source).unwrap();
+        assert_eq!(config.databases[0].server_tls_mode, TlsMode::Prefer);
+        assert_eq!(config.databases[1].server_tls_mode, TlsMode::VerifyFull);
+        assert_eq!(
+            config.databases[1].server_tls_ca_certificate,
+            Some(PathBuf::from("/etc/ssl/ca.pem"))
+        );
+    }
+
+    #[test]
+    fn test_tls_client_required() {
+        let mut config: Config = toml::from_str(
+            r#"
+[general]
+tls_client_required = true
+"#,
+        )
+        .unwrap();
+        assert!(config.check().is_err());
+
+        config.general.tls_client_ca_certificate = Some(PathBuf::from("/etc/ssl/ca.pem"));
+        assert!(config.check().is_ok());
+    }
 }
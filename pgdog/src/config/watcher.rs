@@ -0,0 +1,113 @@
+//! Watch configuration files for changes.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{absolute, PathBuf};
+use std::time::Duration;
+
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use super::Error;
+
+/// Watches files for changes.
+#[derive(Debug)]
+pub struct Watcher {
+    events: UnboundedReceiver<()>,
+    /// Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+}
+
+impl Watcher {
+    /// Start watching the files.
+    ///
+    /// Directories containing the files are watched instead of the files themselves,
+    /// so files replaced with a rename, like editors and Kubernetes do, are noticed too.
+    pub fn new(paths: &[&PathBuf]) -> Result<Self, Error> {
+        let mut names = HashSet::new();
+        let mut directories = HashSet::new();
+
+        for path in paths {
+            let path = absolute(path)?;
+            if let (Some(directory), Some(name)) = (path.parent(), path.file_name()) {
+                directories.insert(directory.to_owned());
+                names.insert(name.to_owned());
+            }
+        }
+
+        // Kubernetes updates mounted ConfigMaps by swapping this symlink.
+        names.insert(OsString::from("..data"));
+
+        let (tx, events) = unbounded_channel();
+
+        let mut watcher = recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                let changed = event
+                    .paths
+                    .iter()
+                    .filter_map(|path| path.file_name())
+                    .filter(|name| names.contains(*name))
+                    .inspect(|name| debug!("{:?} changed", name))
+                    .count()
+                    > 0;
+
+                if changed && !event.kind.is_access() {
+                    let _ = tx.send(());
+                }
+            }
+            Err(err) => warn!("config watcher error: {}", err),
+        })?;
+
+        for directory in &directories {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            debug!("watching \"{}\" for config changes", directory.display());
+        }
+
+        Ok(Self {
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Wait until the files change and then stop changing for `delay`.
+    ///
+    /// Returns `false` if the watcher stopped.
+    pub async fn changed(&mut self, delay: Duration) -> bool {
+        if self.events.recv().await.is_none() {
+            return false;
+        }
+
+        while let Ok(Some(())) = timeout(delay, self.events.recv()).await {}
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watcher() {
+        let dir = std::env::temp_dir().join(format!("pgdog_watcher_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pgdog.toml");
+        std::fs::write(&path, "").unwrap();
+
+        let mut watcher = Watcher::new(&[&path]).unwrap();
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        std::fs::write(&path, "[general]").unwrap();
+        std::fs::write(&path, "[general]\nport = 6432").unwrap();
+
+        let delay = Duration::from_millis(50);
+        assert!(timeout(Duration::from_secs(5), watcher.changed(delay))
+            .await
+            .unwrap());
+        // Both writes are reported once.
+        assert!(timeout(delay * 4, watcher.changed(delay)).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
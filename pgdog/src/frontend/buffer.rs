@@ -121,6 +121,35 @@ impl Buffer {
         Ok(None)
     }
 
+    /// Parameter data types declared by the client in Parse (F), if any.
+    ///
+    /// If the statement was prepared earlier, its Parse is fetched from the global cache.
+    pub fn data_types(&self) -> Result<Vec<i32>, Error> {
+        for message in &self.buffer {
+            match message.code() {
+                'P' => {
+                    let parse = Parse::from_bytes(message.to_bytes()?)?;
+                    return Ok(parse.data_types);
+                }
+
+                'B' => {
+                    let bind = Bind::from_bytes(message.to_bytes()?)?;
+                    if !bind.anonymous() {
+                        return Ok(PreparedStatements::global()
+                            .lock()
+                            .parse(&bind.statement)
+                            .map(|parse| parse.data_types)
+                            .unwrap_or_default());
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        Ok(vec![])
+    }
+
     /// Get all CopyData (F & B) messages.
     pub fn copy_data(&self) -> Result<Vec<CopyData>, Error> {
         let mut rows = vec![];
@@ -148,6 +177,11 @@ impl Buffer {
         Self { buffer }
     }
 
+    /// The buffer runs a query, i.e. has a Query or Execute message.
+    pub fn executes(&self) -> bool {
+        self.buffer.iter().any(|m| matches!(m.code(), 'Q' | 'E'))
+    }
+
     /// The buffer has CopyData messages.
     pub fn copy(&self) -> bool {
         self.buffer
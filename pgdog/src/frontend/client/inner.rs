@@ -1,14 +1,28 @@
 use crate::{
     backend::{
+        databases::{databases, ToUser, User},
         pool::{Connection, Request},
         Error as BackendError,
     },
-    frontend::{router::Error as RouterError, Buffer, Command, Comms, Router, Stats},
+    config::config,
+    frontend::{
+        query_logger::Entry,
+        rate_limit::{acquire, RateLimit},
+        router::{parser::Cache, Error as RouterError, ShardedMessages},
+        Buffer, Command, Comms, Router, Stats,
+    },
+    net::messages::{
+        BindComplete, CommandComplete, Describe, ErrorResponse, FromBytes, Message, NoData,
+        ParameterDescription, ParseComplete, Protocol, ReadyForQuery, ToBytes,
+    },
+    stats::{latency, queries::Query, Latency},
 };
 
-use tracing::debug;
+use std::sync::Arc;
+
+use tracing::{debug, warn};
 
-use super::{Client, Error};
+use super::{timeout::IdleTimeout, Client, Error};
 
 /// Mutable internals used by both client and server message handlers.
 ///
@@ -28,6 +42,14 @@ pub(super) struct Inner {
     pub(super) start_transaction: Option<String>,
     /// Client-wide comms.
     pub(super) comms: Comms,
+    /// Latency histograms for the user/database.
+    pub(super) latency: Arc<Latency>,
+    /// User and database the client is connected to.
+    pub(super) user: User,
+    /// Statistics of the query being executed.
+    pub(super) query: Option<Query>,
+    /// Query log entry of the query being executed.
+    pub(super) log: Option<Entry>,
 }
 
 impl Inner {
@@ -47,6 +69,13 @@ impl Inner {
             }
         }
 
+        // Admin database isn't a pool, so don't publish its latency.
+        let latency = if client.admin {
+            Arc::default()
+        } else {
+            latency((user, database))
+        };
+
         Ok(Self {
             backend,
             router,
@@ -54,6 +83,10 @@ impl Inner {
             async_: false,
             start_transaction: None,
             comms: client.comms.clone(),
+            latency,
+            user: (user, database).to_user(),
+            query: None,
+            log: None,
         })
     }
 
@@ -66,16 +99,246 @@ impl Inner {
             .transpose()
     }
 
+    /// Messages to send to each shard, if the statement had to be rewritten.
+    ///
+    /// This also makes sure the connection knows about the current route.
+    pub(super) fn rewrite(
+        &mut self,
+        buffer: &Buffer,
+    ) -> Result<Option<ShardedMessages>, RouterError> {
+        if !self.backend.multi_shard() {
+            return Ok(None);
+        }
+
+        self.backend.set_route(&self.router.route());
+        let shards = self.backend.cluster()?.shards().len();
+        self.router.rewrite(buffer, shards)
+    }
+
     /// Client is connected to server(s).
     pub(super) fn connected(&self) -> bool {
         self.backend.connected()
     }
 
+    /// Commit the transaction using two-phase commit.
+    ///
+    /// Only used for transactions on multiple shards. COMMIT can be sent with the simple
+    /// or the extended protocol, see [`Inner::commit_replies`].
+    pub(super) fn two_phase_commit(&self, buffer: &Buffer) -> bool {
+        if !self.backend.multi_shard() || !config().config.general.two_phase_commit {
+            return false;
+        }
+
+        let supported = buffer
+            .iter()
+            .all(|message| matches!(message.code(), 'Q' | 'P' | 'B' | 'D' | 'E' | 'H' | 'S'));
+        if !supported {
+            warn!("COMMIT sent with unsupported messages, not using two-phase commit");
+        }
+
+        supported
+    }
+
+    /// Replies to the messages containing COMMIT, when pgdog commits the transaction itself.
+    ///
+    /// `reply` is CommandComplete or ErrorResponse. After an error, extended protocol
+    /// messages are skipped until Sync, same as PostgreSQL does.
+    pub(super) fn commit_replies(buffer: &Buffer, reply: Message) -> Result<Vec<Message>, Error> {
+        let mut replies = vec![];
+        let mut failed = false;
+
+        for message in buffer.iter() {
+            match message.code() {
+                'Q' => {
+                    replies.push(reply.clone());
+                    replies.push(ReadyForQuery::idle().message()?);
+                }
+                'S' => {
+                    failed = false;
+                    replies.push(ReadyForQuery::idle().message()?);
+                }
+                _ if failed => (),
+                'P' => replies.push(ParseComplete.message()?),
+                'B' => replies.push(BindComplete.message()?),
+                'D' => {
+                    let describe = Describe::from_bytes(message.to_bytes()?)?;
+                    if describe.kind == 'S' {
+                        replies.push(ParameterDescription::default().message()?);
+                    }
+                    replies.push(NoData.message()?);
+                }
+                'E' => {
+                    failed = reply.code() == 'E';
+                    replies.push(reply.clone());
+                }
+                _ => (),
+            }
+        }
+
+        Ok(replies)
+    }
+
     /// Server(s) are done talking.
     pub(super) fn done(&self) -> bool {
         self.backend.done()
     }
 
+    /// Start collecting statistics and logging the query in the buffer,
+    /// sent to the shards, or to all of them if not specified.
+    pub(super) fn start_query(
+        &mut self,
+        buffer: &Buffer,
+        shards: Option<Vec<usize>>,
+        read: bool,
+    ) -> Result<(), Error> {
+        let general = &config().config.general;
+        if !general.query_stats && general.query_log.is_none() {
+            return Ok(());
+        }
+
+        let Ok(cluster) = self.backend.cluster() else {
+            return Ok(());
+        };
+
+        if let Some(query) = buffer.query()? {
+            // Queries the parser can't handle are passed through without statistics.
+            if let Ok(fingerprint) = Cache::get().fingerprint(&query) {
+                let shards = shards.unwrap_or_else(|| (0..cluster.shards().len()).collect());
+                if general.query_log.is_some() {
+                    self.log = Some(Entry::new(&query, fingerprint, &self.user, &shards, read));
+                }
+                if general.query_stats {
+                    self.query = Some(Query::start(
+                        fingerprint,
+                        &query,
+                        shards,
+                        general.query_stats_max,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count rows and errors returned by the server(s).
+    pub(super) fn query_reply(&mut self, message: &Message) -> Result<(), Error> {
+        if self.query.is_none() && self.log.is_none() {
+            return Ok(());
+        }
+
+        match message.code() {
+            'C' => {
+                if let Some(ref mut query) = self.query {
+                    let complete = CommandComplete::from_bytes(message.to_bytes()?)?;
+                    query.rows(complete.rows()?.unwrap_or(0));
+                }
+            }
+            'E' => {
+                if let Some(ref mut query) = self.query {
+                    query.error();
+                }
+                if let Some(ref mut log) = self.log {
+                    let error = ErrorResponse::from_bytes(message.to_bytes()?)?;
+                    log.error(&error.code, &error.message);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Record a finished query.
+    pub(super) fn query_finished(&mut self) {
+        self.comms.stats(self.stats.query());
+        self.latency.query.observe(self.stats.last_query_time);
+        if let Some(query) = self.query.take() {
+            query.finish(self.stats.last_query_time);
+        }
+        if let Some(log) = self.log.take() {
+            log.finish(self.stats.last_query_time);
+        }
+    }
+
+    /// Record a finished transaction.
+    pub(super) fn transaction_finished(&mut self) {
+        self.comms.stats(self.stats.transaction());
+        self.latency
+            .transaction
+            .observe(self.stats.last_transaction_time);
+    }
+
+    /// Wait for the query in the buffer to fit within rate limits.
+    ///
+    /// Queries inside transactions are counted, but never delayed or rejected,
+    /// because the client is already holding server connections.
+    /// Returns the limit that was exceeded if the query should be rejected.
+    pub(super) async fn rate_limit(&self, buffer: &Buffer) -> Result<Option<RateLimit>, Error> {
+        // Parse, Describe, Flush, etc. on their own don't run anything.
+        if self.backend.admin() || !buffer.executes() {
+            return Ok(None);
+        }
+
+        let config = config();
+        let Some(query) = buffer.query()? else {
+            return Ok(None);
+        };
+
+        let transaction = !self.backend.in_transaction() && self.start_transaction.is_none();
+        let mut limits = vec![];
+
+        if let Some(user) = config
+            .users
+            .users
+            .iter()
+            .find(|u| u.name == self.user.user && u.database == self.user.database)
+        {
+            if let Some(rate) = user.query_rate_limit {
+                limits.push((RateLimit::Query(self.user.clone()), rate));
+            }
+            if let Some(rate) = user.transaction_rate_limit.filter(|_| transaction) {
+                limits.push((RateLimit::Transaction(self.user.clone()), rate));
+            }
+        }
+
+        // Don't fingerprint queries unless we have to.
+        if config
+            .config
+            .manual_queries
+            .iter()
+            .any(|q| q.query_rate_limit.is_some())
+        {
+            if let Ok(fingerprint) = Cache::get().fingerprint(&query) {
+                let fingerprint = format!("{:016x}", fingerprint);
+                if let Some(rate) = databases()
+                    .manual_query(&fingerprint)
+                    .and_then(|q| q.query_rate_limit)
+                {
+                    limits.push((RateLimit::Fingerprint(fingerprint), rate));
+                }
+            }
+        }
+
+        let timeout = config.config.general.rate_limit_timeout();
+        Ok(acquire(&limits, transaction, timeout).await.err())
+    }
+
+    /// Idle timeout that applies while waiting for the client, if any.
+    pub(super) fn idle_timeout(&self) -> Option<IdleTimeout> {
+        let cluster = self.backend.cluster().ok()?;
+
+        if self.backend.in_transaction() || self.start_transaction.is_some() {
+            cluster
+                .idle_in_transaction_timeout()
+                .map(IdleTimeout::Transaction)
+        } else if self.done() {
+            cluster.client_idle_timeout().map(IdleTimeout::Client)
+        } else {
+            None
+        }
+    }
+
     /// Server(s) are in transaction mode pooling.
     pub(super) fn transaction_mode(&self) -> bool {
         self.backend.transaction_mode()
@@ -96,6 +359,7 @@ impl Inner {
 
         if result.is_ok() {
             self.comms.stats(self.stats.connected());
+            self.latency.wait.observe(self.stats.wait_time);
             if let Ok(addr) = self.backend.addr() {
                 let addrs = addr
                     .into_iter()
@@ -115,3 +379,38 @@ impl Inner {
         result
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::{Bind, Execute, Parse, Query, Sync};
+
+    #[test]
+    fn test_commit_replies() {
+        let codes = |replies: Vec<Message>| replies.iter().map(|m| m.code()).collect::<String>();
+        let commit = CommandComplete::new_commit().message().unwrap();
+
+        let buffer = Buffer::from(vec![Query::new("COMMIT").message().unwrap()]);
+        let replies = Inner::commit_replies(&buffer, commit.clone()).unwrap();
+        assert_eq!(codes(replies), "CZ");
+
+        let buffer = Buffer::from(vec![
+            Parse::new_anonymous("COMMIT").message().unwrap(),
+            Bind::default().message().unwrap(),
+            Describe {
+                kind: 'P',
+                statement: "".into(),
+            }
+            .message()
+            .unwrap(),
+            Execute::default().message().unwrap(),
+            Sync.message().unwrap(),
+        ]);
+        let replies = Inner::commit_replies(&buffer, commit).unwrap();
+        assert_eq!(codes(replies), "12nCZ");
+
+        let error = ErrorResponse::default().message().unwrap();
+        let replies = Inner::commit_replies(&buffer, error).unwrap();
+        assert_eq!(codes(replies), "12nEZ");
+    }
+}
@@ -0,0 +1,249 @@
+//! Client connection limits.
+//!
+//! Clients reserve a connection slot right after sending the startup packet,
+//! before authentication, and release it when they disconnect. Checking the limits
+//! and reserving the slot happens under one lock, so a burst of new clients can't
+//! get past them.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::backend::databases::User;
+use crate::config::ConfigAndUsers;
+use crate::net::messages::ErrorResponse;
+
+static CONNECTIONS: Lazy<Mutex<Connections>> = Lazy::new(|| Mutex::new(Connections::default()));
+
+/// Connection limit reached by a new client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Limit {
+    /// `max_client_connections` in `[general]`.
+    Global,
+    /// `max_client_connections` of the database.
+    Database,
+    /// `max_client_connections` of the user.
+    User,
+}
+
+impl Limit {
+    /// Reserve a connection slot for a new client, unless that would exceed a limit.
+    pub(super) fn reserve(config: &ConfigAndUsers, user: &User) -> Result<Reservation, Self> {
+        CONNECTIONS.lock().reserve(config, user).map(Reservation)
+    }
+
+    /// Error sent to the client, same as PostgreSQL.
+    pub(super) fn error(&self, user: &User) -> ErrorResponse {
+        match self {
+            Self::Global => ErrorResponse::too_many_connections("sorry, too many clients already"),
+            Self::Database => ErrorResponse::too_many_connections(&format!(
+                "too many connections for database \"{}\"",
+                user.database
+            )),
+            Self::User => ErrorResponse::too_many_connections(&format!(
+                "too many connections for role \"{}\"",
+                user.user
+            )),
+        }
+    }
+}
+
+/// Connection slot held by a client, released when dropped.
+#[derive(Debug)]
+pub(super) struct Reservation(Slot);
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        CONNECTIONS.lock().release(&self.0);
+    }
+}
+
+/// Counters a client is included in, besides the total.
+#[derive(Debug, Clone)]
+struct Slot {
+    /// Database from pgdog.toml.
+    database: Option<String>,
+    /// User from users.toml.
+    user: Option<User>,
+}
+
+/// Number of clients rejected because of connection limits,
+/// if the user is in users.toml.
+pub fn rejected(user: &User) -> usize {
+    CONNECTIONS
+        .lock()
+        .rejected
+        .get(user)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Number of clients rejected because of connection limits.
+pub fn rejected_total() -> usize {
+    CONNECTIONS.lock().rejected_total
+}
+
+/// Clients holding a connection slot.
+///
+/// Only databases and users from the configuration are tracked,
+/// so clients can't grow these maps by sending made up names.
+#[derive(Debug, Default)]
+struct Connections {
+    total: usize,
+    databases: HashMap<String, usize>,
+    users: HashMap<User, usize>,
+    rejected: HashMap<User, usize>,
+    rejected_total: usize,
+}
+
+impl Connections {
+    fn reserve(&mut self, config: &ConfigAndUsers, user: &User) -> Result<Slot, Limit> {
+        // Databases have an entry for each shard and replica, use the lowest limit.
+        let databases = config
+            .config
+            .databases
+            .iter()
+            .filter(|d| d.name == user.database)
+            .collect::<Vec<_>>();
+        let database_limit = databases
+            .iter()
+            .filter_map(|d| d.max_client_connections)
+            .min();
+        let user_config = config
+            .users
+            .users
+            .iter()
+            .find(|u| u.name == user.user && u.database == user.database);
+        let user_limit = user_config.and_then(|u| u.max_client_connections);
+
+        let reached = |count: Option<&usize>, limit: Option<usize>| {
+            limit
+                .map(|limit| count.copied().unwrap_or_default() >= limit)
+                .unwrap_or(false)
+        };
+
+        let limit = if reached(
+            Some(&self.total),
+            config.config.general.max_client_connections,
+        ) {
+            Some(Limit::Global)
+        } else if reached(self.databases.get(&user.database), database_limit) {
+            Some(Limit::Database)
+        } else if reached(self.users.get(user), user_limit) {
+            Some(Limit::User)
+        } else {
+            None
+        };
+
+        if let Some(limit) = limit {
+            self.rejected_total += 1;
+            if user_config.is_some() {
+                *self.rejected.entry(user.clone()).or_default() += 1;
+            }
+            return Err(limit);
+        }
+
+        let slot = Slot {
+            database: (!databases.is_empty()).then(|| user.database.clone()),
+            user: user_config.map(|_| user.clone()),
+        };
+
+        self.total += 1;
+        if let Some(ref database) = slot.database {
+            *self.databases.entry(database.clone()).or_default() += 1;
+        }
+        if let Some(ref user) = slot.user {
+            *self.users.entry(user.clone()).or_default() += 1;
+        }
+
+        Ok(slot)
+    }
+
+    fn release(&mut self, slot: &Slot) {
+        self.total = self.total.saturating_sub(1);
+        if let Some(ref database) = slot.database {
+            release(&mut self.databases, database);
+        }
+        if let Some(ref user) = slot.user {
+            release(&mut self.users, user);
+        }
+    }
+}
+
+/// Decrement a counter, removing it when it reaches zero.
+fn release<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: &K) {
+    if let Some(count) = counts.get_mut(key) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::databases::ToUser;
+    use crate::config::{Database, User as UserConfig};
+
+    #[test]
+    fn test_limit() {
+        let mut config = ConfigAndUsers::default();
+        config.users.users.push(UserConfig {
+            name: "pgdog".into(),
+            database: "pgdog".into(),
+            max_client_connections: Some(2),
+            ..Default::default()
+        });
+        config.config.databases = (0..2)
+            .map(|shard| Database {
+                name: "pgdog".into(),
+                shard,
+                max_client_connections: Some(3 + shard),
+                ..Default::default()
+            })
+            .collect();
+        config.config.general.max_client_connections = Some(4);
+
+        let user = ("pgdog", "pgdog").to_user();
+        let other = ("other", "pgdog").to_user();
+        let unknown = ("unknown", "unknown").to_user();
+        let mut connections = Connections::default();
+        let mut slots = vec![];
+
+        for _ in 0..2 {
+            slots.push(connections.reserve(&config, &user).unwrap());
+        }
+        assert_eq!(connections.reserve(&config, &user).err(), Some(Limit::User));
+
+        slots.push(connections.reserve(&config, &other).unwrap());
+        assert_eq!(
+            connections.reserve(&config, &other).err(),
+            Some(Limit::Database)
+        );
+
+        slots.push(connections.reserve(&config, &unknown).unwrap());
+        assert_eq!(
+            connections.reserve(&config, &unknown).err(),
+            Some(Limit::Global)
+        );
+
+        // Only configured users and databases are tracked.
+        assert_eq!(connections.total, 4);
+        assert_eq!(connections.databases.len(), 1);
+        assert_eq!(connections.users.len(), 1);
+        assert_eq!(connections.rejected.get(&user), Some(&1));
+        assert_eq!(connections.rejected.len(), 1);
+        assert_eq!(connections.rejected_total, 3);
+
+        for slot in slots {
+            connections.release(&slot);
+        }
+        assert_eq!(connections.total, 0);
+        assert!(connections.databases.is_empty());
+        assert!(connections.users.is_empty());
+
+        assert_eq!(Limit::User.error(&user).code, "53300");
+    }
+}
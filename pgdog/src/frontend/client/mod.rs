@@ -4,22 +4,36 @@ use std::net::SocketAddr;
 use std::time::Instant;
 
 use tokio::{select, spawn};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, field::Empty, info, info_span, trace, warn, Instrument};
 
 use super::{Buffer, Command, Comms, Error, PreparedStatements};
-use crate::auth::scram::Server;
-use crate::backend::pool::{Connection, Request};
-use crate::config::config;
-#[cfg(debug_assertions)]
-use crate::frontend::QueryLogger;
+use crate::auth::{md5, scram::Server, Passthrough, Secret};
+use crate::backend::{
+    auth_query,
+    databases::{self, databases, ToUser},
+    pool::{Connection, Request},
+    Error as BackendError,
+};
+use crate::config::{config, AuthMethod};
 use crate::net::messages::{
-    Authentication, BackendKeyData, CommandComplete, ErrorResponse, Message, ParseComplete,
-    Protocol, ReadyForQuery,
+    Authentication, BackendKeyData, CommandComplete, ErrorResponse, FromBytes, Message,
+    ParseComplete, Password, Protocol, ReadyForQuery, ToBytes,
 };
 use crate::net::{parameter::Parameters, Stream};
 
 pub mod inner;
+pub mod limit;
+pub mod timeout;
+
 use inner::Inner;
+use limit::Limit;
+use timeout::IdleTimeout;
+
+/// Client passed authentication.
+struct Authenticated {
+    /// Credentials that can be used to connect to PostgreSQL on behalf of the client.
+    passthrough: Option<Passthrough>,
+}
 
 /// Frontend client.
 #[allow(dead_code)]
@@ -37,7 +51,28 @@ pub struct Client {
 
 impl Client {
     /// Create new frontend client from the given TCP stream.
+    ///
+    /// Everything the client logs is tagged with its address, user and database.
     pub async fn spawn(
+        stream: Stream,
+        params: Parameters,
+        addr: SocketAddr,
+        comms: Comms,
+    ) -> Result<(), Error> {
+        let user = params.get_default("user", "postgres");
+        let database = params.get_default("database", user);
+        let span = info_span!("client", %addr, user, database, shard = Empty);
+        if let Some(shard) = params.shard() {
+            span.record("shard", shard);
+        }
+
+        Self::start(stream, params, addr, comms)
+            .instrument(span)
+            .await
+    }
+
+    /// Authenticate the client and serve its queries.
+    async fn start(
         mut stream: Stream,
         params: Parameters,
         addr: SocketAddr,
@@ -52,31 +87,107 @@ impl Client {
 
         let id = BackendKeyData::new();
 
-        // Get server parameters and send them to the client.
-        let mut conn = match Connection::new(user, database, admin) {
-            Ok(conn) => conn,
-            Err(_) => {
-                stream.fatal(ErrorResponse::auth(user, database)).await?;
-                return Ok(());
+        // Reserve a connection slot before doing any work for this client,
+        // held until it disconnects. The admin database is exempt,
+        // so it's always possible to connect to it.
+        let _reservation = if admin {
+            None
+        } else {
+            let user = (user, database).to_user();
+            match Limit::reserve(&config, &user) {
+                Ok(reservation) => Some(reservation),
+                Err(limit) => {
+                    warn!("client connection limit reached [{:?}]", limit);
+                    stream.fatal(limit.error(&user)).await?;
+                    return Ok(());
+                }
             }
         };
 
-        let password = if admin {
-            admin_password
+        // Certificates are verified during the TLS handshake.
+        if config.config.general.tls_client_required && stream.peer_common_name().is_none() {
+            stream.fatal(ErrorResponse::tls_required()).await?;
+            return Ok(());
+        }
+
+        // Users not in users.toml are authenticated with the password
+        // stored by the server, if passthrough authentication is enabled.
+        let passthrough = !admin
+            && databases().auth_pool(database).is_some()
+            && databases()
+                .cluster((user, database))
+                .map(|cluster| cluster.passthrough().is_some())
+                .unwrap_or(true);
+
+        let (secret, auth_method) = if admin {
+            (Secret::Plain(admin_password.clone()), AuthMethod::Scram)
+        } else if passthrough {
+            match auth_query::secret(user, database).await {
+                Ok(Some(secret)) => (secret, AuthMethod::Scram),
+                Ok(None) => {
+                    stream.fatal(ErrorResponse::auth(user, database)).await?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    error!("auth query failed: {}", err);
+                    stream.fatal(ErrorResponse::connection()).await?;
+                    return Ok(());
+                }
+            }
         } else {
-            conn.cluster()?.password()
+            match databases().cluster((user, database)) {
+                Ok(cluster) => (Secret::parse(cluster.password()), cluster.auth_method()),
+                Err(_) => {
+                    stream.fatal(ErrorResponse::auth(user, database)).await?;
+                    return Ok(());
+                }
+            }
         };
 
-        stream.send_flush(Authentication::scram()).await?;
+        // Hashed passwords only work with the method they were hashed for.
+        let auth_method = match (auth_method, &secret) {
+            (AuthMethod::Scram, Secret::Md5(_)) => AuthMethod::Md5,
+            (AuthMethod::Md5, Secret::Scram(_)) => AuthMethod::Scram,
+            (auth_method, _) => auth_method,
+        };
 
-        let scram = Server::new(password);
-        if let Ok(true) = scram.handle(&mut stream).await {
-            stream.send(Authentication::Ok).await?;
-        } else {
+        // Certificate CN the client must present for cert authentication.
+        let common_name = config
+            .users
+            .users
+            .iter()
+            .find(|u| u.name == user && u.database == database)
+            .and_then(|u| u.tls_common_name.as_deref())
+            .unwrap_or(user);
+
+        let Some(authenticated) =
+            Self::authenticate(&mut stream, auth_method, user, common_name, &secret).await?
+        else {
             stream.fatal(ErrorResponse::auth(user, database)).await?;
             return Ok(());
+        };
+
+        if passthrough {
+            if let Some(credentials) = authenticated.passthrough {
+                if let Err(err) = databases::passthrough(user, database, credentials) {
+                    error!("passthrough authentication failed: {}", err);
+                    stream.fatal(ErrorResponse::auth(user, database)).await?;
+                    return Ok(());
+                }
+            }
         }
 
+        // Get server parameters and send them to the client.
+        let mut conn = match Connection::new(user, database, admin) {
+            Ok(conn) => conn,
+            Err(_) => {
+                stream.fatal(ErrorResponse::auth(user, database)).await?;
+                return Ok(());
+            }
+        };
+
+        stream.send(Authentication::Ok).await?;
+
         // Check if the pooler is shutting down.
         if comms.offline() && !admin {
             stream.fatal(ErrorResponse::shutting_down()).await?;
@@ -105,15 +216,7 @@ impl Client {
         comms.connect(&id, addr);
         let shard = params.shard();
 
-        info!(
-            "client connected [{}]{}",
-            addr,
-            if let Some(ref shard) = shard {
-                format!(" (replication, shard {})", shard)
-            } else {
-                "".into()
-            }
-        );
+        info!(replication = shard.is_some(), "client connected");
 
         let mut client = Self {
             addr,
@@ -129,9 +232,12 @@ impl Client {
 
         if client.admin {
             // Admin clients are not waited on during shutdown.
-            spawn(async move {
-                client.spawn_internal().await;
-            });
+            spawn(
+                async move {
+                    client.spawn_internal().await;
+                }
+                .in_current_span(),
+            );
         } else {
             client.spawn_internal().await;
         }
@@ -139,6 +245,104 @@ impl Client {
         Ok(())
     }
 
+    /// Authenticate the client using the configured method.
+    ///
+    /// Returns `None` if authentication failed.
+    async fn authenticate(
+        stream: &mut Stream,
+        auth_method: AuthMethod,
+        user: &str,
+        common_name: &str,
+        secret: &Secret,
+    ) -> Result<Option<Authenticated>, Error> {
+        let passthrough = match secret {
+            Secret::Plain(password) => Some(Passthrough::Password(password.clone())),
+            Secret::Md5(hash) => Some(Passthrough::Md5(hash.clone())),
+            Secret::Scram(_) => None,
+        };
+
+        match auth_method {
+            AuthMethod::Scram => {
+                let mut scram = match secret {
+                    Secret::Plain(password) => Server::new(password),
+                    Secret::Scram(verifier) => Server::verifier(verifier.clone()),
+                    Secret::Md5(_) => {
+                        error!("md5 password can't be used with scram authentication");
+                        return Ok(None);
+                    }
+                };
+                stream.send_flush(Authentication::scram()).await?;
+                if !matches!(scram.handle(stream).await, Ok(true)) {
+                    return Ok(None);
+                }
+
+                let passthrough = match (secret, scram.client_key()) {
+                    (Secret::Scram(verifier), Some(client_key)) => Some(Passthrough::Scram {
+                        client_key: client_key.to_vec(),
+                        server_key: verifier.server_key.clone(),
+                    }),
+                    _ => passthrough,
+                };
+                Ok(Some(Authenticated { passthrough }))
+            }
+
+            AuthMethod::Md5 => {
+                let md5 = match secret {
+                    Secret::Plain(password) => md5::Client::new(user, password),
+                    Secret::Md5(hash) => md5::Client::hashed(hash),
+                    Secret::Scram(_) => {
+                        error!("scram password can't be used with md5 authentication");
+                        return Ok(None);
+                    }
+                };
+                stream.send_flush(md5.challenge()).await?;
+                let authenticated = Self::password(stream)
+                    .await?
+                    .map(|response| md5.check(&response))
+                    .unwrap_or(false);
+                Ok(authenticated.then_some(Authenticated { passthrough }))
+            }
+
+            AuthMethod::Plain => {
+                stream.send_flush(Authentication::ClearTextPassword).await?;
+                let Some(response) = Self::password(stream).await? else {
+                    return Ok(None);
+                };
+                let authenticated = match secret {
+                    Secret::Plain(password) => &response == password,
+                    Secret::Scram(verifier) => verifier.matches(&response),
+                    Secret::Md5(hash) => &md5::hash(user, &response) == hash,
+                };
+                Ok(authenticated.then_some(Authenticated {
+                    passthrough: Some(Passthrough::Password(response)),
+                }))
+            }
+
+            AuthMethod::Trust => Ok(Some(Authenticated { passthrough })),
+
+            AuthMethod::Cert => {
+                let authenticated = stream.peer_common_name().as_deref() == Some(common_name);
+                if !authenticated {
+                    error!("client certificate doesn't match \"{}\"", common_name);
+                }
+                Ok(authenticated.then_some(Authenticated { passthrough }))
+            }
+        }
+    }
+
+    /// Read PasswordMessage (F) sent by the client.
+    async fn password(stream: &mut Stream) -> Result<Option<String>, Error> {
+        let message = stream.read().await?;
+        if message.code() != 'p' {
+            return Ok(None);
+        }
+
+        match Password::from_bytes(message.to_bytes()?)? {
+            Password::PasswordMessage { response } => Ok(Some(response)),
+            _ => Ok(None),
+        }
+    }
+
     /// Get client's identifier.
     pub fn id(&self) -> BackendKeyData {
         self.id
@@ -147,10 +351,12 @@ impl Client {
     /// Run the client and log disconnect.
     async fn spawn_internal(&mut self) {
         match self.run().await {
-            Ok(_) => info!("client disconnected [{}]", self.addr),
+            Ok(_) => info!("client disconnected"),
             Err(err) => {
-                let _ = self.stream.error(ErrorResponse::from_err(&err)).await;
-                error!("client disconnected with error [{}]: {}", self.addr, err)
+                let error = ErrorResponse::from_err(&err);
+                let code = error.code.clone();
+                let _ = self.stream.error(error).await;
+                error!(code, %err, "client disconnected with error")
             }
         }
     }
@@ -160,6 +366,8 @@ impl Client {
         let mut inner = Inner::new(self)?;
 
         loop {
+            let idle_timeout = inner.idle_timeout();
+
             select! {
                 _ = inner.comms.shutting_down() => {
                     if !inner.backend.connected() {
@@ -186,6 +394,15 @@ impl Client {
                         break;
                     }
                 }
+
+                timeout = IdleTimeout::wait(idle_timeout) => {
+                    // Return the server(s) to the pool, rolling back the transaction.
+                    inner.disconnect();
+                    let error = timeout.error();
+                    warn!("{}", error.message);
+                    self.stream.send_flush(error).await?;
+                    break;
+                }
             }
         }
 
@@ -210,7 +427,14 @@ impl Client {
         #[cfg(debug_assertions)]
         if let Some(query) = buffer.query()? {
             debug!("{} [{}]", query, self.addr);
-            QueryLogger::new(&buffer).log().await?;
+        }
+
+        // Rate limits are enforced before checking out a connection.
+        if let Some(limit) = inner.rate_limit(&buffer).await? {
+            self.stream
+                .error(ErrorResponse::rate_limit(&format!("{} exceeded", limit)))
+                .await?;
+            return Ok(false);
         }
 
         let connected = inner.connected();
@@ -225,6 +449,9 @@ impl Client {
         };
 
         self.streaming = matches!(command, Some(Command::StartReplication));
+        let commit = matches!(command, Some(Command::CommitTransaction));
+        let shards = command.and_then(|command| command.shards());
+        let read = matches!(command, Some(Command::Query(route)) if route.is_read());
 
         if !connected {
             match command {
@@ -269,6 +496,8 @@ impl Client {
             }
         }
 
+        inner.start_query(&buffer, shards, read)?;
+
         // Handle any prepared statements.
         for request in self.prepared_statements.requests() {
             if let Err(err) = inner.backend.prepare(&request.name).await {
@@ -282,6 +511,31 @@ impl Client {
             }
         }
 
+        // Commit a transaction that wrote to multiple shards using two-phase commit.
+        if commit && inner.two_phase_commit(&buffer) {
+            let reply = match inner.backend.two_phase_commit().await {
+                Ok(true) => CommandComplete::new_commit().message()?,
+                Ok(false) => CommandComplete::new_rollback().message()?,
+                Err(err) => {
+                    error!("two-phase commit failed: {} [{}]", err, self.addr);
+                    match err {
+                        // Transaction was rolled back, the client can keep going.
+                        BackendError::ExecutionError(mut error) => {
+                            error.severity = "ERROR".into();
+                            error.message()?
+                        }
+                        err => ErrorResponse::from_err(&err).message()?,
+                    }
+                }
+            };
+            let replies = Inner::commit_replies(&buffer, reply)?;
+            let len = replies.iter().map(|reply| reply.len()).sum();
+            self.stream.send_many(replies).await?;
+            inner.comms.stats(inner.stats.sent(len));
+            inner.query_finished();
+            return Ok(self.transaction_finished(inner));
+        }
+
         // Handle COPY subprotocol in a potentially sharded context.
         if buffer.copy() && !self.streaming {
             let rows = inner.router.copy_data(&buffer)?;
@@ -294,6 +548,10 @@ impl Client {
             } else {
                 inner.backend.send(buffer.into()).await?;
             }
+        } else if let Some(messages) = inner.rewrite(&buffer)? {
+            // Statement was rewritten, e.g. split between shards.
+            let parse = buffer.iter().any(|message| message.code() == 'P');
+            inner.backend.send_sharded(messages, parse).await?;
         } else {
             // Send query to server.
             inner.backend.send(buffer.into()).await?;
@@ -313,6 +571,8 @@ impl Client {
         let async_flush = matches!(code, 'T' | 'E') && inner.async_;
         let streaming = message.streaming();
 
+        inner.query_reply(&message)?;
+
         if flush || async_flush || streaming {
             self.stream.send_flush(message).await?;
             if async_flush {
@@ -325,24 +585,31 @@ impl Client {
         inner.comms.stats(inner.stats.sent(len));
 
         if code == 'Z' {
-            inner.comms.stats(inner.stats.query());
+            inner.query_finished();
         }
 
+        Ok(self.transaction_finished(inner))
+    }
+
+    /// Release server(s) if the transaction is finished.
+    ///
+    /// Returns true if the client should disconnect because we are shutting down.
+    fn transaction_finished(&self, inner: &mut Inner) -> bool {
         if inner.done() {
             if inner.transaction_mode() {
                 inner.disconnect();
             }
-            inner.comms.stats(inner.stats.transaction());
+            inner.transaction_finished();
             trace!(
                 "transaction finished [{}ms]",
                 inner.stats.last_transaction_time.as_secs_f64() * 1000.0
             );
             if inner.comms.offline() && !self.admin {
-                return Ok(true);
+                return true;
             }
         }
 
-        Ok(false)
+        false
     }
 
     /// Buffer extended protocol messages until client requests a sync.
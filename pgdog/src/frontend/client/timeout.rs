@@ -0,0 +1,61 @@
+//! Client idle timeouts.
+
+use std::future::pending;
+use std::time::Duration;
+
+use tokio::time::sleep;
+
+use crate::net::messages::ErrorResponse;
+
+/// Client is idle for too long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum IdleTimeout {
+    /// Client is idle in transaction.
+    Transaction(Duration),
+    /// Client is idle outside a transaction.
+    Client(Duration),
+}
+
+impl IdleTimeout {
+    /// Wait for the timeout to expire. Never returns if there is no timeout.
+    pub(super) async fn wait(timeout: Option<Self>) -> Self {
+        match timeout {
+            Some(timeout) => {
+                sleep(timeout.duration()).await;
+                timeout
+            }
+            None => pending().await,
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            Self::Transaction(duration) | Self::Client(duration) => *duration,
+        }
+    }
+
+    /// Error sent to the client before disconnecting it.
+    pub(super) fn error(&self) -> ErrorResponse {
+        match self {
+            Self::Transaction(_) => ErrorResponse::idle_in_transaction_timeout(),
+            Self::Client(_) => ErrorResponse::client_idle_timeout(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let idle = IdleTimeout::Transaction(Duration::from_millis(10));
+        assert_eq!(IdleTimeout::wait(Some(idle)).await, idle);
+        assert_eq!(idle.error().code, "25P03");
+
+        let none = timeout(Duration::from_millis(50), IdleTimeout::wait(None)).await;
+        assert!(none.is_err());
+    }
+}
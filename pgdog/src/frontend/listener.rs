@@ -4,13 +4,16 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use tokio::net::{TcpListener, TcpStream};
-use tokio::signal::ctrl_c;
+use tokio::signal::{
+    ctrl_c,
+    unix::{signal, SignalKind},
+};
 use tokio::sync::Notify;
 use tokio::time::timeout;
 use tokio::{select, spawn};
 use tokio_util::task::TaskTracker;
 
-use crate::backend::databases::{databases, shutdown};
+use crate::backend::databases::{databases, reload, shutdown};
 use crate::config::config;
 use crate::net::messages::BackendKeyData;
 use crate::net::messages::{hello::SslReply, Startup};
@@ -47,6 +50,8 @@ impl Listener {
         let listener = TcpListener::bind(&self.addr).await?;
         info!("🐕 PgDog listening on {}", self.addr);
         let comms = comms();
+        let mut sighup = signal(SignalKind::hangup())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
 
         loop {
             let comms = comms.clone();
@@ -76,14 +81,19 @@ impl Listener {
                 }
 
                 _ = ctrl_c() => {
-                    self.clients.close();
-                    comms.shutdown();
-                    shutdown();
-
-                    let listener = self.clone();
-                    spawn(async move {
-                        listener.shutdown().await;
-                    });
+                    self.start_shutdown(&comms);
+                }
+
+                _ = sigterm.recv() => {
+                    info!("received SIGTERM, shutting down");
+                    self.start_shutdown(&comms);
+                }
+
+                _ = sighup.recv() => {
+                    info!("received SIGHUP, reloading configuration");
+                    if let Err(err) = reload() {
+                        error!("reload failed: {}", err);
+                    }
                 }
 
                 _ = self.shutdown.notified() => {
@@ -95,6 +105,18 @@ impl Listener {
         Ok(())
     }
 
+    /// Stop accepting clients and shutdown gracefully in the background.
+    fn start_shutdown(&self, comms: &Comms) {
+        self.clients.close();
+        comms.shutdown();
+        shutdown();
+
+        let listener = self.clone();
+        spawn(async move {
+            listener.shutdown().await;
+        });
+    }
+
     async fn shutdown(&self) {
         let shutdown_timeout = config().config.general.shutdown_timeout();
 
@@ -125,7 +147,7 @@ impl Listener {
 
             match startup {
                 Startup::Ssl => {
-                    if let Some(tls) = tls {
+                    if let Some(ref tls) = tls {
                         stream.send_flush(SslReply::Yes).await?;
                         let plain = stream.take()?;
                         let cipher = tls.accept(plain).await?;
@@ -7,8 +7,8 @@ pub mod connected_client;
 pub mod error;
 pub mod listener;
 pub mod prepared_statements;
-#[cfg(debug_assertions)]
 pub mod query_logger;
+pub mod rate_limit;
 pub mod router;
 pub mod stats;
 
@@ -18,7 +18,5 @@ pub use comms::Comms;
 pub use connected_client::ConnectedClient;
 pub use error::Error;
 pub use prepared_statements::{PreparedStatements, Rewrite};
-#[cfg(debug_assertions)]
-pub use query_logger::QueryLogger;
 pub use router::{Command, Router};
 pub use stats::Stats;
@@ -5,22 +5,34 @@ fn global_name(counter: usize) -> String {
     format!("__pgdog_{}", counter)
 }
 
+/// Statements are the same if both the query and the parameter data types match.
+type Key = (String, Vec<i32>);
+
 #[derive(Default, Debug)]
 pub struct GlobalCache {
-    statements: HashMap<String, usize>,
-    names: HashMap<String, String>, // Ideally this holds an entry to `statements`. Maybe an Arc?
+    statements: HashMap<Key, usize>,
+    names: HashMap<String, Parse>, // Ideally this holds an entry to `statements`. Maybe an Arc?
     counter: usize,
 }
 
 impl GlobalCache {
     pub(super) fn insert(&mut self, parse: &Parse) -> (bool, String) {
-        match self.statements.entry(parse.query.clone()) {
+        match self
+            .statements
+            .entry((parse.query.clone(), parse.data_types.clone()))
+        {
             Entry::Occupied(entry) => (false, global_name(*entry.get())),
             Entry::Vacant(entry) => {
                 self.counter += 1;
                 entry.insert(self.counter);
-                self.names
-                    .insert(global_name(self.counter), parse.query.clone());
+                let name = global_name(self.counter);
+                self.names.insert(
+                    name.clone(),
+                    Parse {
+                        name,
+                        ..parse.clone()
+                    },
+                );
 
                 (true, global_name(self.counter))
             }
@@ -30,12 +42,13 @@ impl GlobalCache {
     /// Get query stored in the global cache.
     #[inline]
     pub fn query(&self, name: &str) -> Option<&String> {
-        self.names.get(name)
+        self.names.get(name).map(|parse| &parse.query)
     }
 
-    /// Construct a Parse message from a query stored in the global cache.
+    /// Get the Parse message stored in the global cache,
+    /// including the parameter data types declared by the client.
     pub fn parse(&self, name: &str) -> Option<Parse> {
-        self.query(name).map(|query| Parse::named(name, query))
+        self.names.get(name).cloned()
     }
 
     pub fn len(&self) -> usize {
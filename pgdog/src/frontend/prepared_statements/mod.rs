@@ -56,7 +56,7 @@ impl PreparedStatements {
         let (_new, name) = guard.insert(&parse);
         self.local.insert(parse.name.clone(), name.clone());
 
-        Parse::named(name, parse.query)
+        Parse { name, ..parse }
     }
 
     /// Get global statement counter.
@@ -110,4 +110,26 @@ mod test {
         assert_eq!(request.name, "__pgdog_1");
         assert!(request.new);
     }
+
+    #[test]
+    fn test_data_types() {
+        let mut statements = PreparedStatements::default();
+
+        let int = statements.insert(Parse {
+            data_types: vec![23],
+            ..Parse::named("a", "SELECT $1")
+        });
+        let text = statements.insert(Parse {
+            data_types: vec![25],
+            ..Parse::named("b", "SELECT $1")
+        });
+        let same = statements.insert(Parse {
+            data_types: vec![23],
+            ..Parse::named("c", "SELECT $1")
+        });
+
+        assert_ne!(int.name, text.name);
+        assert_eq!(int.name, same.name);
+        assert_eq!(text.data_types, vec![25]);
+    }
 }
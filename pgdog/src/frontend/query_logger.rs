@@ -1,40 +1,307 @@
 //! Log queries to a file.
 //!
-//! DO NOT USE IN PRODUCTION. This is very slow.
-//!
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+//! Queries are sent over a bounded channel to a background task,
+//! which writes them as JSON, one per line, and rotates the file
+//! when it gets too big. If the task can't keep up, queries are dropped
+//! instead of slowing down clients.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::Serialize;
+use tokio::fs::{rename, File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::spawn;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+use tracing::{error, warn};
+
+use crate::backend::databases::User;
+use crate::config::{config, General};
+
+/// Queries waiting to be written.
+const QUEUE_SIZE: usize = 8192;
+/// Wait this long before opening the file again after an error.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+static LOGGER: Lazy<QueryLogger> = Lazy::new(QueryLogger::new);
+
+/// Query log entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    /// When the query finished, in seconds since the epoch.
+    timestamp: f64,
+    /// Query text.
+    query: String,
+    /// Query fingerprint.
+    fingerprint: String,
+    user: String,
+    database: String,
+    /// Shards the query was sent to.
+    shards: Vec<usize>,
+    /// "read" or "write".
+    role: &'static str,
+    /// Query time (ms).
+    duration: f64,
+    /// Error returned by the server(s), if any.
+    error: Option<String>,
+}
+
+impl Entry {
+    /// Start logging a query.
+    pub fn new(query: &str, fingerprint: u64, user: &User, shards: &[usize], read: bool) -> Self {
+        Self {
+            timestamp: 0.0,
+            query: query.trim().to_string(),
+            fingerprint: format!("{:016x}", fingerprint),
+            user: user.user.clone(),
+            database: user.database.clone(),
+            shards: shards.to_vec(),
+            role: if read { "read" } else { "write" },
+            duration: 0.0,
+            error: None,
+        }
+    }
+
+    /// Query returned an error.
+    pub fn error(&mut self, code: &str, message: &str) {
+        // Keep the first error if more than one shard failed.
+        if self.error.is_none() {
+            self.error = Some(format!("{}: {}", code, message));
+        }
+    }
+
+    /// Query finished, log it if needed.
+    pub fn finish(mut self, duration: Duration) {
+        let general = &config().config.general;
+        if general.query_log.is_none() || !Self::sample(general, duration, self.error.is_some()) {
+            return;
+        }
+
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.duration = duration.as_secs_f64() * 1000.0;
+
+        LOGGER.send(self);
+    }
+
+    /// Slow queries and errors are always logged, others are sampled.
+    fn sample(general: &General, duration: Duration, error: bool) -> bool {
+        let slow = general
+            .query_log_slow_threshold()
+            .map(|threshold| duration >= threshold)
+            .unwrap_or(false);
+
+        slow || error || rand::thread_rng().gen_bool(general.query_log_sample_rate.clamp(0.0, 1.0))
+    }
+}
+
+/// Background query log writer.
+struct QueryLogger {
+    tx: Sender<Entry>,
+    dropped: AtomicUsize,
+}
+
+impl QueryLogger {
+    fn new() -> Self {
+        let (tx, rx) = channel(QUEUE_SIZE);
+        spawn(Self::writer(rx));
+
+        Self {
+            tx,
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    fn send(&self, entry: Entry) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(entry) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn writer(mut rx: Receiver<Entry>) {
+        let mut file: Option<LogFile> = None;
+        let mut retry_at = Instant::now();
+
+        while let Some(entry) = rx.recv().await {
+            let general = config().config.general.clone();
+            let Some(path) = general.query_log else {
+                file = None;
+                continue;
+            };
 
-use crate::config::config;
+            // Path changed on reload.
+            if file.as_ref().map(|file| file.path != path).unwrap_or(false) {
+                file = None;
+            }
+
+            if file.is_none() {
+                if Instant::now() < retry_at {
+                    continue;
+                }
+                match LogFile::open(&path).await {
+                    Ok(log_file) => file = Some(log_file),
+                    Err(err) => {
+                        error!("query log \"{}\" error: {}", path.display(), err);
+                        retry_at = Instant::now() + RETRY_DELAY;
+                        continue;
+                    }
+                }
+            }
 
-use super::{Buffer, Error};
+            let Some(log_file) = file.as_mut() else {
+                continue;
+            };
+
+            let dropped = LOGGER.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("query log is falling behind, dropped {} queries", dropped);
+            }
 
-/// Log queries.
-pub struct QueryLogger<'a> {
-    buffer: &'a Buffer,
+            let result = log_file
+                .write(
+                    &entry,
+                    general.query_log_max_size,
+                    general.query_log_max_files,
+                )
+                .await;
+            let result = match result {
+                // Flush once there is nothing else to write.
+                Ok(()) if rx.is_empty() => log_file.flush().await,
+                result => result,
+            };
+
+            if let Err(err) = result {
+                error!("query log \"{}\" error: {}", path.display(), err);
+                file = None;
+                retry_at = Instant::now() + RETRY_DELAY;
+            }
+        }
+    }
 }
 
-impl<'a> QueryLogger<'a> {
-    /// Create new query logger.
-    pub fn new(buffer: &'a Buffer) -> Self {
-        Self { buffer }
+/// Query log file.
+struct LogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+impl LogFile {
+    async fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await?;
+        let size = file.metadata().await?.len();
+
+        Ok(Self {
+            path: path.to_owned(),
+            writer: BufWriter::new(file),
+            size,
+        })
+    }
+
+    async fn write(
+        &mut self,
+        entry: &Entry,
+        max_size: u64,
+        max_files: usize,
+    ) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > max_size {
+            self.rotate(max_files).await?;
+        }
+
+        self.writer.write_all(&line).await?;
+        self.size += line.len() as u64;
+
+        Ok(())
     }
 
-    /// Log queries
-    pub async fn log(&self) -> Result<(), Error> {
-        let path = &config().config.general.query_log;
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush().await
+    }
 
-        if let Some(path) = path {
-            if let Some(query) = self.buffer.query()? {
-                let mut file = OpenOptions::new()
-                    .append(true)
-                    .create(true)
-                    .open(path)
-                    .await?;
-                let line = format!("{}\n", query.trim());
-                file.write_all(line.as_bytes()).await?;
+    /// Rename query.log to query.log.1, query.log.1 to query.log.2, etc.,
+    /// and start a new file.
+    async fn rotate(&mut self, max_files: usize) -> std::io::Result<()> {
+        self.flush().await?;
+
+        let max_files = max_files.max(1);
+        for n in (1..max_files).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                rename(&from, rotated(&self.path, n + 1)).await?;
             }
         }
+        rename(&self.path, rotated(&self.path, 1)).await?;
+
+        *self = Self::open(&self.path).await?;
 
         Ok(())
     }
 }
+
+/// Path of a rotated log file.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("pgdog_query_log_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("queries.log");
+
+        let user = User {
+            user: "pgdog".into(),
+            database: "pgdog".into(),
+        };
+        let entry = Entry::new("SELECT 1", 1, &user, &[0], true);
+        let size = serde_json::to_vec(&entry).unwrap().len() as u64 + 1;
+
+        // Three entries per file, two rotated files.
+        let mut file = LogFile::open(&path).await.unwrap();
+        for _ in 0..10 {
+            file.write(&entry, size * 3, 2).await.unwrap();
+        }
+        file.flush().await.unwrap();
+
+        let lines = |n: usize| {
+            let path = if n == 0 {
+                path.clone()
+            } else {
+                rotated(&path, n)
+            };
+            std::fs::read_to_string(path).unwrap().lines().count()
+        };
+        assert_eq!(lines(0), 1);
+        assert_eq!(lines(1), 3);
+        assert_eq!(lines(2), 3);
+        assert!(!rotated(&path, 3).exists());
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(json["query"], "SELECT 1");
+        assert_eq!(json["fingerprint"], "0000000000000001");
+        assert_eq!(json["role"], "read");
+        assert_eq!(json["shards"], serde_json::json!([0]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
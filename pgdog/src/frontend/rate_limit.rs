@@ -0,0 +1,269 @@
+//! Query and transaction rate limits.
+//!
+//! Each limit is a token bucket shared by all clients, refilled at the configured
+//! rate per second and holding up to one second worth of tokens.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::time::sleep;
+
+use crate::backend::databases::User;
+use crate::config::ConfigAndUsers;
+
+static BUCKETS: Lazy<Mutex<HashMap<RateLimit, Bucket>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Rate limit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RateLimit {
+    /// Queries per second for a user.
+    Query(User),
+    /// Transactions per second for a user.
+    Transaction(User),
+    /// Queries per second for a query fingerprint.
+    Fingerprint(String),
+}
+
+impl RateLimit {
+    /// Limit is still in the configuration.
+    fn configured(&self, config: &ConfigAndUsers) -> bool {
+        let user = |user: &User| {
+            config
+                .users
+                .users
+                .iter()
+                .find(|u| u.name == user.user && u.database == user.database)
+        };
+
+        match self {
+            Self::Query(u) => user(u).and_then(|u| u.query_rate_limit).is_some(),
+            Self::Transaction(u) => user(u).and_then(|u| u.transaction_rate_limit).is_some(),
+            Self::Fingerprint(fingerprint) => config
+                .config
+                .manual_queries
+                .iter()
+                .any(|q| q.fingerprint == *fingerprint && q.query_rate_limit.is_some()),
+        }
+    }
+}
+
+impl Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Query(user) => write!(
+                f,
+                "query rate limit for user \"{}\" and database \"{}\"",
+                user.user, user.database
+            ),
+            Self::Transaction(user) => write!(
+                f,
+                "transaction rate limit for user \"{}\" and database \"{}\"",
+                user.user, user.database
+            ),
+            Self::Fingerprint(fingerprint) => {
+                write!(f, "rate limit for query fingerprint \"{}\"", fingerprint)
+            }
+        }
+    }
+}
+
+/// Token bucket.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Tokens added per second.
+    rate: u32,
+    /// Available tokens. Can go below zero, see [`acquire`].
+    tokens: f64,
+    updated: Instant,
+    allowed: usize,
+    delayed: usize,
+    rejected: usize,
+}
+
+impl Bucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            updated: now,
+            allowed: 0,
+            delayed: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Add tokens accumulated since the last refill.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.updated = now;
+    }
+
+    /// How long until a token is available.
+    fn wait(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.rate as f64)
+        }
+    }
+}
+
+/// Rate limit state, shown in `SHOW LIMITS`.
+#[derive(Debug, Clone)]
+pub struct LimitStats {
+    pub limit: RateLimit,
+    /// Configured rate per second.
+    pub rate: u32,
+    /// Tokens available right now.
+    pub tokens: f64,
+    /// Requests allowed, including delayed ones.
+    pub allowed: usize,
+    /// Requests that had to wait for a token.
+    pub delayed: usize,
+    /// Requests rejected.
+    pub rejected: usize,
+}
+
+/// Take a token from each of the limits, waiting up to `timeout` for them.
+///
+/// If `enforce` is false, tokens are taken without waiting, even if that puts
+/// the bucket in debt. This is used for queries inside transactions, which already
+/// hold a server connection, so they are counted against clients that don't.
+///
+/// Returns the limit that was exceeded if tokens aren't available in time.
+pub async fn acquire(
+    limits: &[(RateLimit, u32)],
+    enforce: bool,
+    timeout: Duration,
+) -> Result<(), RateLimit> {
+    // A rate of zero would never refill.
+    let limits = limits
+        .iter()
+        .filter(|(_, rate)| *rate > 0)
+        .collect::<Vec<_>>();
+    if limits.is_empty() {
+        return Ok(());
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut delayed = false;
+
+    loop {
+        let wait = {
+            let now = Instant::now();
+            let mut buckets = BUCKETS.lock();
+
+            let mut wait = Duration::ZERO;
+            let mut exceeded = None;
+            for (limit, rate) in &limits {
+                let bucket = buckets
+                    .entry(limit.clone())
+                    .or_insert_with(|| Bucket::new(*rate, now));
+                // Rate changed on reload.
+                bucket.rate = *rate;
+                bucket.refill(now);
+                if bucket.wait() > wait {
+                    wait = bucket.wait();
+                    exceeded = Some(limit);
+                }
+            }
+
+            match exceeded {
+                Some(limit) if enforce && now + wait > deadline => {
+                    if let Some(bucket) = buckets.get_mut(limit) {
+                        bucket.rejected += 1;
+                    }
+                    return Err(limit.clone());
+                }
+
+                Some(_) if enforce => {
+                    delayed = true;
+                    wait
+                }
+
+                _ => {
+                    for (limit, _) in &limits {
+                        if let Some(bucket) = buckets.get_mut(limit) {
+                            bucket.tokens -= 1.0;
+                            bucket.allowed += 1;
+                            if delayed {
+                                bucket.delayed += 1;
+                            }
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        };
+
+        sleep(wait).await;
+    }
+}
+
+/// State of all rate limits.
+pub fn limits() -> Vec<LimitStats> {
+    let now = Instant::now();
+    let mut limits = BUCKETS
+        .lock()
+        .iter()
+        .map(|(limit, bucket)| {
+            let mut bucket = *bucket;
+            bucket.refill(now);
+            LimitStats {
+                limit: limit.clone(),
+                rate: bucket.rate,
+                tokens: bucket.tokens,
+                allowed: bucket.allowed,
+                delayed: bucket.delayed,
+                rejected: bucket.rejected,
+            }
+        })
+        .collect::<Vec<_>>();
+    limits.sort_by(|a, b| a.limit.cmp(&b.limit));
+    limits
+}
+
+/// Forget limits removed from the configuration.
+pub fn reload(config: &ConfigAndUsers) {
+    BUCKETS.lock().retain(|limit, _| limit.configured(config));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let limit = RateLimit::Fingerprint("test_rate_limit".into());
+        let rates = [(limit.clone(), 10)];
+
+        // Burst of one second worth of queries.
+        for _ in 0..10 {
+            acquire(&rates, true, Duration::ZERO).await.unwrap();
+        }
+        assert_eq!(
+            acquire(&rates, true, Duration::ZERO).await,
+            Err(limit.clone())
+        );
+
+        // Not enforced, goes into debt.
+        acquire(&rates, false, Duration::ZERO).await.unwrap();
+
+        // Wait for the debt to be repaid.
+        let start = Instant::now();
+        acquire(&rates, true, Duration::from_secs(1)).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        let stats = limits()
+            .into_iter()
+            .find(|stats| stats.limit == limit)
+            .unwrap();
+        assert_eq!(stats.allowed, 12);
+        assert_eq!(stats.delayed, 1);
+        assert_eq!(stats.rejected, 1);
+    }
+}
@@ -11,7 +11,7 @@ pub mod sharding;
 
 pub use copy::CopyRow;
 pub use error::Error;
-pub use parser::{Command, QueryParser, Route};
+pub use parser::{Command, QueryParser, Route, ShardedMessages};
 
 use super::Buffer;
 
@@ -54,6 +54,16 @@ impl Router {
         Ok(self.query_parser.copy_data(buffer.copy_data()?)?)
     }
 
+    /// Messages to send to each shard instead of the ones in the buffer,
+    /// if the statement had to be rewritten.
+    pub fn rewrite(
+        &self,
+        buffer: &Buffer,
+        shards: usize,
+    ) -> Result<Option<ShardedMessages>, Error> {
+        Ok(self.query_parser.rewrite(buffer, shards)?)
+    }
+
     /// Get current route.
     pub fn route(&self) -> Route {
         self.query_parser.route()
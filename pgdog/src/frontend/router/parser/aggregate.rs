@@ -0,0 +1,339 @@
+//! Aggregate functions that can be merged across shards.
+use std::string::String;
+
+use pg_query::{protobuf::*, NodeEnum};
+
+/// Aggregate function we know how to merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+impl AggregateFunction {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "avg" => Some(Self::Avg),
+            _ => None,
+        }
+    }
+}
+
+/// Aggregate function in the target list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateTarget {
+    column: usize,
+    function: AggregateFunction,
+    count: Option<usize>,
+}
+
+impl AggregateTarget {
+    /// Position of the column in the row.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Aggregate function.
+    pub fn function(&self) -> AggregateFunction {
+        self.function
+    }
+
+    /// Position of the `COUNT` column added to calculate `AVG` across shards.
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+}
+
+/// Aggregate functions used in a `SELECT` statement.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Aggregate {
+    targets: Vec<AggregateTarget>,
+    group_by: Vec<usize>,
+    columns: usize,
+}
+
+impl Aggregate {
+    /// Find aggregates in the statement.
+    ///
+    /// Returns `None` if the statement can't be merged, e.g. it selects columns
+    /// not in the `GROUP BY` clause, uses `DISTINCT` or window functions.
+    pub fn parse(stmt: &SelectStmt) -> Option<Self> {
+        if stmt.having_clause.is_some()
+            || stmt.group_distinct
+            || !stmt.distinct_clause.is_empty()
+            || stmt.op() != SetOperation::SetopNone
+            || stmt.target_list.is_empty()
+        {
+            return None;
+        }
+
+        let columns = stmt.target_list.len();
+        let mut targets = vec![];
+        let mut hidden = columns;
+        // Columns that are not aggregates: (column name, position in the row).
+        let mut plain = vec![];
+
+        for (column, node) in stmt.target_list.iter().enumerate() {
+            let Some(function) = Self::function(node) else {
+                plain.push((Self::column(node)?, column));
+                continue;
+            };
+            let count = if function == AggregateFunction::Avg {
+                hidden += 1;
+                Some(hidden - 1)
+            } else {
+                None
+            };
+            targets.push(AggregateTarget {
+                column,
+                function,
+                count,
+            });
+        }
+
+        if targets.is_empty() && stmt.group_clause.is_empty() {
+            return None;
+        }
+
+        // Rows can only be matched across shards if all
+        // grouping columns are returned to the client.
+        let mut group_by = vec![];
+        for node in &stmt.group_clause {
+            let position = match node.node {
+                Some(NodeEnum::ColumnRef(_)) => {
+                    let name = Self::column_ref(node)?;
+                    plain
+                        .iter()
+                        .find(|(column, _)| *column == name)
+                        .map(|(_, position)| *position)?
+                }
+                // GROUP BY 1, 2, etc. PostgreSQL rejects positions outside the target list.
+                Some(NodeEnum::AConst(ref aconst)) => match aconst.val {
+                    Some(a_const::Val::Ival(ref integer)) => {
+                        let position = usize::try_from(integer.ival).ok()?.checked_sub(1)?;
+                        Self::column(stmt.target_list.get(position)?)?;
+                        position
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            group_by.push(position);
+        }
+
+        // Columns that aren't aggregated must be grouped by.
+        if plain.len() > group_by.len() {
+            return None;
+        }
+
+        Some(Self {
+            targets,
+            group_by,
+            columns,
+        })
+    }
+
+    /// Aggregate targets.
+    pub fn targets(&self) -> &[AggregateTarget] {
+        &self.targets
+    }
+
+    /// Positions of the columns the rows are grouped by.
+    pub fn group_by(&self) -> &[usize] {
+        &self.group_by
+    }
+
+    /// Number of columns the client expects to receive.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Rewrite the statement so it can be merged across shards.
+    ///
+    /// `AVG(x)` is replaced with `SUM(x)` and `COUNT(x)` is appended to the target list.
+    /// Returns `false` if the statement didn't need to be changed.
+    pub fn rewrite(&self, stmt: &mut SelectStmt) -> bool {
+        let mut counts = vec![];
+
+        for target in &self.targets {
+            if target.count.is_none() {
+                continue;
+            }
+
+            let Some(NodeEnum::ResTarget(ref mut res)) = stmt.target_list[target.column].node
+            else {
+                continue;
+            };
+            if res.name.is_empty() {
+                res.name = "avg".into();
+            }
+            let Some(NodeEnum::FuncCall(ref mut func)) =
+                res.val.as_mut().and_then(|val| val.node.as_mut())
+            else {
+                continue;
+            };
+
+            let mut count = func.clone();
+            count.funcname = vec![Self::name("count")];
+            counts.push(Node {
+                node: Some(NodeEnum::ResTarget(Box::new(ResTarget {
+                    val: Some(Box::new(Node {
+                        node: Some(NodeEnum::FuncCall(count)),
+                    })),
+                    ..Default::default()
+                }))),
+            });
+
+            func.funcname = vec![Self::name("sum")];
+        }
+
+        let changed = !counts.is_empty();
+        stmt.target_list.extend(counts);
+
+        changed
+    }
+
+    fn function(node: &Node) -> Option<AggregateFunction> {
+        let Some(NodeEnum::ResTarget(ref res)) = node.node else {
+            return None;
+        };
+        let Some(NodeEnum::FuncCall(ref func)) = res.val.as_ref().and_then(|val| val.node.as_ref())
+        else {
+            return None;
+        };
+
+        if func.agg_distinct || func.agg_within_group || func.over.is_some() {
+            return None;
+        }
+
+        match func.funcname.last().and_then(|name| name.node.as_ref()) {
+            Some(NodeEnum::String(name)) => AggregateFunction::from_name(&name.sval),
+            _ => None,
+        }
+    }
+
+    /// Name of the column referenced in the target list.
+    fn column(node: &Node) -> Option<String> {
+        let Some(NodeEnum::ResTarget(ref res)) = node.node else {
+            return None;
+        };
+        Self::column_ref(res.val.as_ref()?)
+    }
+
+    /// Column name, without the table name.
+    fn column_ref(node: &Node) -> Option<String> {
+        let Some(NodeEnum::ColumnRef(ref column)) = node.node else {
+            return None;
+        };
+        match column.fields.last().and_then(|field| field.node.as_ref()) {
+            Some(NodeEnum::String(string)) => Some(string.sval.clone()),
+            _ => None,
+        }
+    }
+
+    fn name(name: &str) -> Node {
+        Node {
+            node: Some(NodeEnum::String(pg_query::protobuf::String {
+                sval: name.into(),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn select(query: &str) -> SelectStmt {
+        let ast = pg_query::parse(query).unwrap();
+        let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match root.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => *stmt.clone(),
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let stmt = select("SELECT COUNT(*), SUM(id), MIN(id), MAX(id) FROM sharded");
+        let aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.columns(), 4);
+        assert_eq!(
+            aggregate
+                .targets()
+                .iter()
+                .map(|target| target.function())
+                .collect::<Vec<_>>(),
+            vec![
+                AggregateFunction::Count,
+                AggregateFunction::Sum,
+                AggregateFunction::Min,
+                AggregateFunction::Max,
+            ]
+        );
+        assert!(!aggregate.rewrite(&mut stmt.clone()));
+
+        for query in [
+            "SELECT id, COUNT(*) FROM sharded",
+            "SELECT COUNT(*) FROM sharded GROUP BY id",
+            "SELECT id, email, COUNT(*) FROM sharded GROUP BY id",
+            "SELECT id, COUNT(*) FROM sharded GROUP BY id HAVING COUNT(*) > 1",
+            "SELECT COUNT(DISTINCT id) FROM sharded",
+            "SELECT COUNT(*) OVER () FROM sharded",
+            "SELECT COUNT(*) + 1 FROM sharded",
+            "SELECT id FROM sharded",
+        ] {
+            assert!(Aggregate::parse(&select(query)).is_none(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_group_by() {
+        let stmt = select(
+            "SELECT s.id AS key, email, COUNT(*) FROM sharded s GROUP BY s.id, 2 ORDER BY 3 DESC",
+        );
+        let aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.group_by(), &[0, 1]);
+        assert_eq!(aggregate.columns(), 3);
+        assert_eq!(aggregate.targets().len(), 1);
+        assert_eq!(aggregate.targets()[0].column(), 2);
+
+        let stmt = select("SELECT id FROM sharded GROUP BY id");
+        let aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.group_by(), &[0]);
+        assert!(aggregate.targets().is_empty());
+
+        for query in [
+            "SELECT id, COUNT(*) FROM sharded GROUP BY 0",
+            "SELECT id, COUNT(*) FROM sharded GROUP BY -1",
+            "SELECT id, COUNT(*) FROM sharded GROUP BY 3",
+            "SELECT id, COUNT(*) FROM sharded GROUP BY 2",
+        ] {
+            assert!(Aggregate::parse(&select(query)).is_none(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_avg() {
+        let stmt = select("SELECT AVG(id), COUNT(*), AVG(value) AS v FROM sharded WHERE id > 5");
+        let aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.columns(), 3);
+        assert_eq!(aggregate.targets()[0].count(), Some(3));
+        assert_eq!(aggregate.targets()[1].count(), None);
+        assert_eq!(aggregate.targets()[2].count(), Some(4));
+
+        let mut rewrite = stmt.clone();
+        assert!(aggregate.rewrite(&mut rewrite));
+        assert_eq!(
+            NodeEnum::SelectStmt(Box::new(rewrite)).deparse().unwrap(),
+            "SELECT sum(id) AS avg, count(*), sum(value) AS v, count(id), count(value) FROM sharded WHERE id > 5"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Binary COPY stream parser that can handle partial inputs.
+
+use std::ops::Range;
+
+use super::Error;
+
+/// Binary COPY file signature.
+static SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+/// Part of a binary COPY stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryRecord {
+    /// File header, including the header extension.
+    Header(Vec<u8>),
+    /// A row.
+    Tuple(BinaryTuple),
+    /// File trailer.
+    Trailer(Vec<u8>),
+}
+
+/// Row encoded in binary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryTuple {
+    data: Vec<u8>,
+    fields: Vec<Option<Range<usize>>>,
+}
+
+impl BinaryTuple {
+    /// Get field data. `NULL` fields have no data.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.fields
+            .get(index)
+            .cloned()
+            .flatten()
+            .map(|range| &self.data[range])
+    }
+
+    /// Tuple as it appears in the stream.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Binary COPY reader that can handle partial inputs.
+#[derive(Debug, Clone, Default)]
+pub struct BinaryStream {
+    /// Input buffer.
+    buffer: Vec<u8>,
+    /// Header was read.
+    header: bool,
+}
+
+impl BinaryStream {
+    /// Write some data to the stream.
+    pub fn write(&mut self, data: &[u8]) {
+        self.buffer.extend(data);
+    }
+
+    /// Fetch the next complete record from the stream, if any.
+    pub fn record(&mut self) -> Result<Option<BinaryRecord>, Error> {
+        if !self.header {
+            let header = SIGNATURE.len() + 8;
+            if self.buffer.len() < header {
+                return Ok(None);
+            }
+            if &self.buffer[..SIGNATURE.len()] != SIGNATURE {
+                return Err(Error::BinaryCopySignature);
+            }
+            let Some(extension) = Self::i32(&self.buffer, header - 4) else {
+                return Ok(None);
+            };
+            let len = header + usize::try_from(extension).map_err(|_| Error::BinaryCopyFormat)?;
+            if self.buffer.len() < len {
+                return Ok(None);
+            }
+
+            self.header = true;
+            return Ok(Some(BinaryRecord::Header(self.take(len))));
+        }
+
+        let Some(count) = Self::i16(&self.buffer, 0) else {
+            return Ok(None);
+        };
+
+        if count == -1 {
+            return Ok(Some(BinaryRecord::Trailer(self.take(2))));
+        }
+
+        let mut fields = vec![];
+        let mut offset = 2;
+        for _ in 0..count {
+            let Some(len) = Self::i32(&self.buffer, offset) else {
+                return Ok(None);
+            };
+            offset += 4;
+
+            if len == -1 {
+                fields.push(None);
+                continue;
+            }
+
+            let len = usize::try_from(len).map_err(|_| Error::BinaryCopyFormat)?;
+            if self.buffer.len() < offset + len {
+                return Ok(None);
+            }
+            fields.push(Some(offset..offset + len));
+            offset += len;
+        }
+
+        Ok(Some(BinaryRecord::Tuple(BinaryTuple {
+            data: self.take(offset),
+            fields,
+        })))
+    }
+
+    /// Get an iterator over all records available in the buffer.
+    pub fn records(&mut self) -> impl Iterator<Item = Result<BinaryRecord, Error>> + '_ {
+        std::iter::from_fn(move || self.record().transpose())
+    }
+
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        self.buffer.drain(..len).collect()
+    }
+
+    fn i16(buffer: &[u8], offset: usize) -> Option<i16> {
+        buffer
+            .get(offset..offset + 2)
+            .map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(buffer: &[u8], offset: usize) -> Option<i32> {
+        buffer
+            .get(offset..offset + 4)
+            .map(|bytes| i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_binary_stream() {
+        let mut data = SIGNATURE.to_vec();
+        data.extend(0_i32.to_be_bytes());
+        data.extend(0_i32.to_be_bytes());
+        data.extend(2_i16.to_be_bytes());
+        data.extend(8_i32.to_be_bytes());
+        data.extend(5_i64.to_be_bytes());
+        data.extend((-1_i32).to_be_bytes());
+        data.extend((-1_i16).to_be_bytes());
+
+        let mut stream = BinaryStream::default();
+        let mut records = vec![];
+        // Write one byte at a time to make sure partial input is handled.
+        for byte in data {
+            stream.write(&[byte]);
+            for record in stream.records() {
+                records.push(record.unwrap());
+            }
+        }
+
+        assert_eq!(records.len(), 3);
+        assert!(matches!(records[0], BinaryRecord::Header(ref header) if header.len() == 19));
+        let BinaryRecord::Tuple(ref tuple) = records[1] else {
+            panic!("not a tuple");
+        };
+        assert_eq!(tuple.get(0), Some(&5_i64.to_be_bytes()[..]));
+        assert_eq!(tuple.get(1), None);
+        assert_eq!(tuple.data().len(), 2 + 4 + 8 + 4);
+        assert_eq!(records[2], BinaryRecord::Trailer(vec![0xff, 0xff]));
+
+        let mut stream = BinaryStream::default();
+        stream.write(b"not a binary copy stream");
+        assert!(stream.record().is_err());
+    }
+}
@@ -24,14 +24,17 @@ pub struct Stats {
 pub struct CachedAst {
     pub ast: Arc<ParseResult>,
     pub hits: usize,
+    /// Query fingerprint.
+    pub fingerprint: u64,
 }
 
 impl CachedAst {
-    fn new(ast: ParseResult) -> Self {
-        Self {
-            ast: Arc::new(ast),
+    fn new(query: &str) -> Result<Self> {
+        Ok(Self {
+            ast: Arc::new(parse(query)?),
             hits: 1,
-        }
+            fingerprint: fingerprint(query)?.value,
+        })
     }
 }
 
@@ -67,15 +70,31 @@ impl Cache {
             }
         }
 
+        Ok(self.insert(query)?.ast)
+    }
+
+    /// Get the fingerprint of a statement, using the cache if the statement
+    /// was parsed already.
+    ///
+    /// Doesn't add the statement to the cache, since the router doesn't parse
+    /// all of them, and doesn't count as a cache hit.
+    pub fn fingerprint(&self, query: &str) -> Result<u64> {
+        if let Some(entry) = self.inner.lock().queries.get(query) {
+            return Ok(entry.fingerprint);
+        }
+
+        Ok(fingerprint(query)?.value)
+    }
+
+    fn insert(&mut self, query: &str) -> Result<CachedAst> {
         // Parse query without holding lock.
-        let entry = CachedAst::new(parse(query)?);
-        let ast = entry.ast.clone();
+        let entry = CachedAst::new(query)?;
 
         let mut guard = self.inner.lock();
-        guard.queries.insert(query.to_owned(), entry);
+        guard.queries.insert(query.to_owned(), entry.clone());
         guard.stats.misses += 1;
 
-        Ok(ast)
+        Ok(entry)
     }
 
     /// Get global cache instance.
@@ -88,6 +107,11 @@ impl Cache {
         Self::get().inner.lock().stats
     }
 
+    /// Number of queries stored in the cache.
+    pub fn len() -> usize {
+        Self::get().inner.lock().queries.len()
+    }
+
     /// Get a copy of all queries stored in the cache.
     pub fn queries() -> HashMap<String, CachedAst> {
         Self::get().inner.lock().queries.clone()
@@ -2,7 +2,9 @@ use once_cell::sync::Lazy;
 use pg_query::{protobuf::Token, scan, Error};
 use regex::Regex;
 
-use super::super::sharding::shard_str;
+use crate::{backend::Cluster, config::ShardedTable};
+
+use super::super::sharding::shard_value;
 
 static SHARD: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_shard: *([0-9]+)"#).unwrap());
 static SHARDING_KEY: Lazy<Regex> =
@@ -15,15 +17,20 @@ static SHARDING_KEY: Lazy<Regex> =
 ///
 /// See [`SHARD`] and [`SHARDING_KEY`] for the style of comment we expect.
 ///
-pub fn shard(query: &str, shards: usize) -> Result<Option<usize>, Error> {
+/// The sharding key is placed using the sharding function of the primary sharded table.
+///
+pub fn shard(query: &str, cluster: &Cluster) -> Result<Option<usize>, Error> {
     let tokens = scan(query)?;
+    let shards = cluster.shards().len();
 
     for token in tokens.tokens.iter() {
         if token.token == Token::CComment as i32 {
             let comment = &query[token.start as usize..token.end as usize];
             if let Some(cap) = SHARDING_KEY.captures(comment) {
                 if let Some(sharding_key) = cap.get(1) {
-                    return Ok(shard_str(sharding_key.as_str(), shards));
+                    let default = ShardedTable::default();
+                    let table = cluster.primary_sharded_table().unwrap_or(&default);
+                    return Ok(shard_value(sharding_key.as_str(), table, shards));
                 }
             }
             if let Some(cap) = SHARD.captures(comment) {
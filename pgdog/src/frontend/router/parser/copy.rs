@@ -4,11 +4,15 @@ use pg_query::{protobuf::CopyStmt, NodeEnum};
 
 use crate::{
     backend::Cluster,
-    frontend::router::{sharding::shard_str, CopyRow},
+    config::ShardedTable,
+    frontend::router::{
+        sharding::{shard_binary, shard_value},
+        CopyRow,
+    },
     net::messages::CopyData,
 };
 
-use super::{CsvStream, Error};
+use super::{BinaryRecord, BinaryStream, CsvStream, Error};
 
 /// Copy information parsed from a COPY statement.
 #[derive(Debug, Clone)]
@@ -44,12 +48,18 @@ pub struct CopyParser {
     pub shards: usize,
     /// Which column is used for sharding.
     pub sharded_column: Option<usize>,
+    /// Configuration of the sharded table.
+    pub sharded_table: Option<ShardedTable>,
     /// Number of columns
     pub columns: usize,
     /// This is a COPY coming from the server.
     pub is_from: bool,
+    /// Data is in the binary format.
+    pub binary: bool,
     /// CSV parser that can handle incomplete records.
     csv_stream: CsvStream,
+    /// Binary parser that can handle incomplete records.
+    binary_stream: BinaryStream,
 }
 
 impl Default for CopyParser {
@@ -58,10 +68,13 @@ impl Default for CopyParser {
             headers: false,
             delimiter: None,
             sharded_column: None,
+            sharded_table: None,
             shards: 1,
             columns: 0,
             is_from: false,
+            binary: false,
             csv_stream: CsvStream::new(',', false),
+            binary_stream: BinaryStream::default(),
         }
     }
 }
@@ -84,7 +97,10 @@ impl CopyParser {
                 }
             }
 
-            parser.sharded_column = cluster.sharded_column(&rel.relname, &columns);
+            if let Some(column) = cluster.sharded_column(&rel.relname, &columns) {
+                parser.sharded_column = Some(column.position);
+                parser.sharded_table = Some(column.table.clone());
+            }
             parser.columns = columns.len();
 
             for option in &stmt.options {
@@ -93,10 +109,12 @@ impl CopyParser {
                         "format" => {
                             if let Some(ref arg) = elem.arg {
                                 if let Some(NodeEnum::String(ref string)) = arg.node {
-                                    if string.sval.to_lowercase().as_str() == "csv"
-                                        && parser.delimiter.is_none()
-                                    {
-                                        parser.delimiter = Some(',');
+                                    match string.sval.to_lowercase().as_str() {
+                                        "csv" if parser.delimiter.is_none() => {
+                                            parser.delimiter = Some(',');
+                                        }
+                                        "binary" => parser.binary = true,
+                                        _ => (),
                                     }
                                 }
                             }
@@ -134,6 +152,10 @@ impl CopyParser {
     /// Split CopyData (F) messages into multiple CopyData (F) messages
     /// with shard numbers.
     pub fn shard(&mut self, data: Vec<CopyData>) -> Result<Vec<CopyRow>, Error> {
+        if self.binary {
+            return self.shard_binary(data);
+        }
+
         let mut rows = vec![];
 
         for row in data {
@@ -151,10 +173,12 @@ impl CopyParser {
                 // Totally broken.
                 let record = record?;
 
-                let shard = if let Some(sharding_column) = self.sharded_column {
+                let shard = if let (Some(sharding_column), Some(table)) =
+                    (self.sharded_column, &self.sharded_table)
+                {
                     let key = record.get(sharding_column).ok_or(Error::NoShardingColumn)?;
 
-                    shard_str(key, self.shards)
+                    shard_value(key, table, self.shards)
                 } else {
                     None
                 };
@@ -165,6 +189,39 @@ impl CopyParser {
 
         Ok(rows)
     }
+
+    /// Split binary CopyData (F) messages into rows with shard numbers.
+    /// Header and trailer are sent to all shards.
+    fn shard_binary(&mut self, data: Vec<CopyData>) -> Result<Vec<CopyRow>, Error> {
+        let mut rows = vec![];
+
+        for row in data {
+            self.binary_stream.write(row.data());
+
+            for record in self.binary_stream.records() {
+                rows.push(match record? {
+                    BinaryRecord::Header(header) => CopyRow::new(&header, None),
+                    BinaryRecord::Trailer(trailer) => CopyRow::new(&trailer, None),
+                    BinaryRecord::Tuple(tuple) => {
+                        let shard = if let (Some(sharding_column), Some(table)) =
+                            (self.sharded_column, &self.sharded_table)
+                        {
+                            // Column data types aren't known, so they are guessed from the size.
+                            tuple
+                                .get(sharding_column)
+                                .and_then(|key| shard_binary(key, 0, table, self.shards))
+                        } else {
+                            None
+                        };
+
+                        CopyRow::new(tuple.data(), shard)
+                    }
+                });
+            }
+        }
+
+        Ok(rows)
+    }
 }
 
 #[cfg(test)]
@@ -172,6 +229,7 @@ mod test {
     use pg_query::parse;
 
     use super::*;
+    use crate::frontend::router::sharding::shard_int;
 
     #[test]
     fn test_copy_text() {
@@ -235,4 +293,47 @@ mod test {
         let sharded = copy.shard(vec![partial_three]).unwrap();
         assert_eq!(sharded[0].message().data(), b"1,2\n");
     }
+
+    #[test]
+    fn test_copy_binary() {
+        let copy = "COPY sharded (id, value) FROM STDIN (FORMAT BINARY)";
+        let stmt = parse(copy).unwrap();
+        let stmt = stmt.protobuf.stmts.first().unwrap();
+        let copy = match stmt.stmt.clone().unwrap().node.unwrap() {
+            NodeEnum::CopyStmt(copy) => copy,
+            _ => panic!("not a copy"),
+        };
+
+        let cluster = Cluster::new_test();
+        let mut copy = CopyParser::new(&copy, &cluster).unwrap().unwrap();
+        assert!(copy.binary);
+
+        let mut header = b"PGCOPY\n\xff\r\n\0".to_vec();
+        header.extend(0_i32.to_be_bytes());
+        header.extend(0_i32.to_be_bytes());
+
+        let mut data = header.clone();
+        for id in [1_i64, 11] {
+            data.extend(2_i16.to_be_bytes());
+            data.extend(8_i32.to_be_bytes());
+            data.extend(id.to_be_bytes());
+            data.extend(5_i32.to_be_bytes());
+            data.extend(b"hello");
+        }
+        data.extend((-1_i16).to_be_bytes());
+
+        // Split the stream mid-row.
+        let (one, two) = data.split_at(header.len() + 10);
+        let mut sharded = copy.shard(vec![CopyData::new(one)]).unwrap();
+        assert_eq!(sharded.len(), 1);
+        sharded.extend(copy.shard(vec![CopyData::new(two)]).unwrap());
+
+        assert_eq!(sharded.len(), 4);
+        assert_eq!(sharded[0].message().data(), &header);
+        assert_eq!(sharded[0].shard(), None);
+        assert_eq!(sharded[1].shard(), Some(shard_int(1, 2)));
+        assert_eq!(sharded[2].shard(), Some(shard_int(11, 2)));
+        assert_eq!(sharded[3].message().data(), &[0xff, 0xff]);
+        assert_eq!(sharded[3].shard(), None);
+    }
 }
@@ -30,4 +30,10 @@ pub enum Error {
 
     #[error("exceeded maximum number of rows in CSV parser")]
     MaxCsvParserRows,
+
+    #[error("binary copy signature is invalid")]
+    BinaryCopySignature,
+
+    #[error("binary copy data is malformed")]
+    BinaryCopyFormat,
 }
@@ -1,7 +1,12 @@
 //! Handle INSERT statements.
+use std::collections::BTreeMap;
+use std::string::String;
+
 use pg_query::{protobuf::*, NodeEnum};
 
-use super::{Column, Table, Tuple};
+use crate::{frontend::Buffer, net::messages::Message};
+
+use super::{Column, Error, Rewrite, Table, Tuple};
 
 /// Parse an `INSERT` statement.
 #[derive(Debug)]
@@ -27,7 +32,7 @@ impl<'a> Insert<'a> {
     }
 
     /// Get table name, if specified (should always be).
-    pub fn table(&self) -> Option<Table> {
+    pub fn table(&self) -> Option<Table<'_>> {
         self.stmt.relation.as_ref().map(Table::from)
     }
 
@@ -40,12 +45,178 @@ impl<'a> Insert<'a> {
                     .iter()
                     .map(Tuple::try_from)
                     .collect::<Result<Vec<Tuple<'a>>, ()>>();
-                return tuples.unwrap();
+                return tuples.unwrap_or_default();
             }
         }
 
         vec![]
     }
+
+    /// Split a multi-row `INSERT` into one statement per shard.
+    ///
+    /// `shards` contains the shard number for each tuple returned by [`Insert::tuples`].
+    /// Placeholders are renumbered, so each statement only references its own parameters.
+    /// Returns no statements if a row has no shard or the values contain expressions
+    /// we can't renumber.
+    pub fn split(&self, shards: &[usize]) -> Result<Vec<ShardedInsert>, Error> {
+        let Some(NodeEnum::SelectStmt(ref select)) = self
+            .stmt
+            .select_stmt
+            .as_ref()
+            .and_then(|node| node.node.as_ref())
+        else {
+            return Ok(vec![]);
+        };
+
+        // Every row must go somewhere.
+        if select.values_lists.len() != shards.len() {
+            return Ok(vec![]);
+        }
+
+        let mut tuples: BTreeMap<usize, Vec<&Node>> = BTreeMap::new();
+        for (tuple, shard) in select.values_lists.iter().zip(shards) {
+            tuples.entry(*shard).or_default().push(tuple);
+        }
+
+        let mut inserts = vec![];
+
+        for (shard, tuples) in tuples {
+            let mut params = vec![];
+            let mut values_lists = vec![];
+
+            for tuple in tuples {
+                let mut tuple = tuple.clone();
+                // Parameters we can't find can't be renumbered.
+                if !renumber(&mut tuple, &mut params) {
+                    return Ok(vec![]);
+                }
+                values_lists.push(tuple);
+            }
+
+            let mut select = select.clone();
+            select.values_lists = values_lists;
+            let mut stmt = self.stmt.clone();
+            stmt.select_stmt = Some(Box::new(Node {
+                node: Some(NodeEnum::SelectStmt(select)),
+            }));
+
+            let query = NodeEnum::InsertStmt(Box::new(stmt))
+                .deparse()
+                .map_err(Error::PgQuery)?;
+
+            inserts.push(ShardedInsert {
+                shard,
+                query,
+                params,
+            });
+        }
+
+        Ok(inserts)
+    }
+}
+
+/// Renumber placeholders in an expression, recording the positions
+/// of the original parameters in `params`.
+///
+/// Returns false if the expression contains nodes we don't look inside of, e.g. subqueries.
+fn renumber(node: &mut Node, params: &mut Vec<usize>) -> bool {
+    let Some(ref mut inner) = node.node else {
+        return true;
+    };
+
+    match inner {
+        NodeEnum::ParamRef(param) => {
+            let Some(position) = usize::try_from(param.number)
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+            else {
+                return false;
+            };
+            // The same parameter can be used more than once.
+            let number = match params.iter().position(|p| *p == position) {
+                Some(index) => index + 1,
+                None => {
+                    params.push(position);
+                    params.len()
+                }
+            };
+            param.number = number as i32;
+            true
+        }
+
+        NodeEnum::AConst(_)
+        | NodeEnum::ColumnRef(_)
+        | NodeEnum::SetToDefault(_)
+        | NodeEnum::SqlvalueFunction(_)
+        | NodeEnum::String(_)
+        | NodeEnum::TypeName(_) => true,
+
+        NodeEnum::List(list) => renumber_all(&mut list.items, params),
+        NodeEnum::TypeCast(cast) => renumber_boxed(&mut cast.arg, params),
+        NodeEnum::CollateClause(collate) => renumber_boxed(&mut collate.arg, params),
+        NodeEnum::NamedArgExpr(arg) => renumber_boxed(&mut arg.arg, params),
+        NodeEnum::NullTest(test) => renumber_boxed(&mut test.arg, params),
+        NodeEnum::BooleanTest(test) => renumber_boxed(&mut test.arg, params),
+        NodeEnum::FuncCall(call) => {
+            // Aggregates and window functions aren't allowed in VALUES.
+            renumber_all(&mut call.args, params)
+        }
+        NodeEnum::AExpr(expr) => {
+            renumber_boxed(&mut expr.lexpr, params) && renumber_boxed(&mut expr.rexpr, params)
+        }
+        NodeEnum::BoolExpr(expr) => renumber_all(&mut expr.args, params),
+        NodeEnum::RowExpr(expr) => renumber_all(&mut expr.args, params),
+        NodeEnum::AArrayExpr(expr) => renumber_all(&mut expr.elements, params),
+        NodeEnum::CoalesceExpr(expr) => renumber_all(&mut expr.args, params),
+        NodeEnum::MinMaxExpr(expr) => renumber_all(&mut expr.args, params),
+        NodeEnum::CaseExpr(expr) => {
+            renumber_boxed(&mut expr.arg, params)
+                && renumber_all(&mut expr.args, params)
+                && renumber_boxed(&mut expr.defresult, params)
+        }
+        NodeEnum::CaseWhen(when) => {
+            renumber_boxed(&mut when.expr, params) && renumber_boxed(&mut when.result, params)
+        }
+        NodeEnum::AIndirection(indirection) => {
+            renumber_boxed(&mut indirection.arg, params)
+                && renumber_all(&mut indirection.indirection, params)
+        }
+        NodeEnum::AIndices(indices) => {
+            renumber_boxed(&mut indices.lidx, params) && renumber_boxed(&mut indices.uidx, params)
+        }
+
+        _ => false,
+    }
+}
+
+fn renumber_boxed(node: &mut Option<Box<Node>>, params: &mut Vec<usize>) -> bool {
+    node.as_mut()
+        .map(|node| renumber(node, params))
+        .unwrap_or(true)
+}
+
+fn renumber_all(nodes: &mut [Node], params: &mut Vec<usize>) -> bool {
+    nodes.iter_mut().all(|node| renumber(node, params))
+}
+
+/// Part of a multi-row `INSERT` that goes to one shard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardedInsert {
+    /// Shard number.
+    pub shard: usize,
+    /// Statement containing only the rows for this shard.
+    pub query: String,
+    /// Positions of the original parameters used by the statement, in order.
+    pub params: Vec<usize>,
+}
+
+impl ShardedInsert {
+    /// Rewrite client messages to execute this statement instead of the original one.
+    pub fn rewrite(&self, buffer: &Buffer) -> Result<Vec<Message>, Error> {
+        Rewrite::new(&self.query)
+            .params(&self.params)
+            .messages(buffer)
+    }
 }
 
 #[cfg(test)]
@@ -54,6 +225,7 @@ mod test {
 
     use super::super::Value;
     use super::*;
+    use crate::net::messages::{Bind, FromBytes, Parameter, Protocol, ToBytes};
 
     #[test]
     fn test_insert() {
@@ -99,4 +271,85 @@ mod test {
             _ => panic!("not an insert"),
         }
     }
+
+    #[test]
+    fn test_insert_split() {
+        let query = parse(
+            "INSERT INTO my_table (id, email) VALUES ($1, $2), ($3, $4), ($5, 'test@test.com')",
+        )
+        .unwrap();
+        let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+        match &select.node {
+            Some(NodeEnum::InsertStmt(stmt)) => {
+                let insert = Insert::new(stmt);
+                let split = insert.split(&[1, 0, 1]).unwrap();
+                assert_eq!(split.len(), 2);
+                assert_eq!(split[0].shard, 0);
+                assert_eq!(
+                    split[0].query,
+                    "INSERT INTO my_table (id, email) VALUES ($1, $2)"
+                );
+                assert_eq!(split[0].params, vec![2, 3]);
+                assert_eq!(split[1].shard, 1);
+                assert_eq!(
+                    split[1].query,
+                    "INSERT INTO my_table (id, email) VALUES ($1, $2), ($3, 'test@test.com')"
+                );
+                assert_eq!(split[1].params, vec![0, 1, 4]);
+
+                let bind = Bind {
+                    params: (1..=5)
+                        .map(|i| Parameter {
+                            len: 1,
+                            data: i.to_string().as_bytes().to_vec(),
+                        })
+                        .collect(),
+                    ..Default::default()
+                };
+                let buffer = Buffer::from(vec![bind.message().unwrap()]);
+                let messages = split[0].rewrite(&buffer).unwrap();
+                assert_eq!(messages[0].code(), 'P');
+                let bind = Bind::from_bytes(messages[1].to_bytes().unwrap()).unwrap();
+                assert_eq!(bind.params.len(), 2);
+                assert_eq!(bind.params[0].data, b"3");
+                assert_eq!(bind.params[1].data, b"4");
+            }
+
+            _ => panic!("not an insert"),
+        }
+    }
+
+    #[test]
+    fn test_insert_split_expressions() {
+        let split = |query: &str, shards: &[usize]| {
+            let query = parse(query).unwrap();
+            let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+            match &select.node {
+                Some(NodeEnum::InsertStmt(stmt)) => Insert::new(stmt).split(shards).unwrap(),
+                _ => panic!("not an insert"),
+            }
+        };
+
+        let query = "INSERT INTO my_table (id, email) VALUES ($1, lower($2)), ($3::bigint, coalesce($4, $2 || '@test.com')), ($5, upper($6))";
+        let inserts = split(query, &[0, 1, 0]);
+        assert_eq!(inserts.len(), 2);
+        assert_eq!(
+            inserts[0].query,
+            "INSERT INTO my_table (id, email) VALUES ($1, lower($2)), ($3, upper($4))"
+        );
+        assert_eq!(inserts[0].params, vec![0, 1, 4, 5]);
+        assert_eq!(
+            inserts[1].query,
+            "INSERT INTO my_table (id, email) VALUES ($1::bigint, COALESCE($2, $3 || '@test.com'))"
+        );
+        assert_eq!(inserts[1].params, vec![2, 3, 1]);
+
+        // Each row needs a shard.
+        assert!(split(query, &[0, 1]).is_empty());
+
+        // Subqueries aren't renumbered.
+        let query = "INSERT INTO my_table (id, email) VALUES ($1, $2), ($3, (SELECT $4))";
+        assert!(split(query, &[0, 1]).is_empty());
+    }
 }
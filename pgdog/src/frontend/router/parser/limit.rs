@@ -0,0 +1,286 @@
+//! LIMIT and OFFSET applied to rows merged from multiple shards.
+use pg_query::{
+    protobuf::{a_const::Val, *},
+    NodeEnum,
+};
+
+use super::Parameters;
+
+/// `LIMIT` and `OFFSET` clauses of a `SELECT` statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limit {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl Limit {
+    /// Get `LIMIT` and `OFFSET` from the statement.
+    ///
+    /// Returns `None` if neither is set or their values can't be determined.
+    pub fn parse(stmt: &SelectStmt, params: &Option<Parameters>) -> Option<Self> {
+        if stmt.limit_option() == LimitOption::WithTies {
+            return None;
+        }
+
+        let limit = match stmt.limit_count {
+            Some(ref node) => Self::value(node, params)?,
+            None => None,
+        };
+        let offset = match stmt.limit_offset {
+            Some(ref node) => Self::value(node, params)?,
+            None => None,
+        };
+
+        if limit.is_none() && offset.is_none() {
+            None
+        } else {
+            Some(Self { limit, offset })
+        }
+    }
+
+    /// Maximum number of rows to return.
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Number of rows to skip.
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    /// Should the row at this position be returned to the client?
+    pub fn includes(&self, row: usize) -> bool {
+        row >= self.offset()
+            && self
+                .limit
+                .map(|limit| row - self.offset() < limit)
+                .unwrap_or(true)
+    }
+
+    /// Number of rows returned to the client, out of the rows received from all shards.
+    pub fn rows(&self, rows: usize) -> usize {
+        let rows = rows.saturating_sub(self.offset());
+        self.limit.map(|limit| rows.min(limit)).unwrap_or(rows)
+    }
+
+    /// Rewrite the statement so the offset is applied after merging rows from all shards.
+    ///
+    /// Each shard returns up to `LIMIT + OFFSET` rows. If rows are aggregated, shards
+    /// return all of them, since partial groups can't be limited.
+    pub fn rewrite(stmt: &mut SelectStmt, aggregate: bool) -> bool {
+        if aggregate || stmt.limit_count.is_none() {
+            let changed = stmt.limit_count.is_some() || stmt.limit_offset.is_some();
+            stmt.limit_count = None;
+            stmt.limit_offset = None;
+            return changed;
+        }
+
+        let Some(offset) = stmt.limit_offset.take() else {
+            return false;
+        };
+        let Some(limit) = stmt.limit_count.take() else {
+            return false;
+        };
+
+        stmt.limit_count = Some(Box::new(
+            match (Self::constant(&limit), Self::constant(&offset)) {
+                (Some(limit), Some(offset)) => Self::integer(limit.saturating_add(offset)),
+                _ => Node {
+                    node: Some(NodeEnum::AExpr(Box::new(AExpr {
+                        kind: AExprKind::AexprOp.into(),
+                        name: vec![Self::string("+")],
+                        lexpr: Some(Self::bigint(limit)),
+                        rexpr: Some(Self::bigint(offset)),
+                        location: -1,
+                    }))),
+                },
+            },
+        ));
+
+        true
+    }
+
+    /// Get the value of a `LIMIT` or `OFFSET` clause.
+    /// `LIMIT ALL` and `LIMIT NULL` have no value.
+    fn value(node: &Node, params: &Option<Parameters>) -> Option<Option<usize>> {
+        match node.node {
+            Some(NodeEnum::AConst(ref aconst)) => {
+                if aconst.isnull {
+                    Some(None)
+                } else {
+                    Self::constant(node).map(Some)
+                }
+            }
+
+            Some(NodeEnum::ParamRef(ref param)) => {
+                let params = params.as_ref()?;
+                let param = params
+                    .parameter((param.number as usize).checked_sub(1)?)
+                    .ok()??;
+                let value = param
+                    .bigint()
+                    .or_else(|| param.decode::<i32>().map(i64::from))?;
+                usize::try_from(value).ok().map(Some)
+            }
+
+            _ => None,
+        }
+    }
+
+    fn constant(node: &Node) -> Option<usize> {
+        match node.node {
+            Some(NodeEnum::AConst(AConst {
+                val: Some(Val::Ival(ref integer)),
+                ..
+            })) => usize::try_from(integer.ival).ok(),
+            // Integers that don't fit in int4.
+            Some(NodeEnum::AConst(AConst {
+                val: Some(Val::Fval(ref float)),
+                ..
+            })) => float.fval.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Parameter types can't be inferred from an expression, so cast them explicitly.
+    fn bigint(node: Box<Node>) -> Box<Node> {
+        Box::new(Node {
+            node: Some(NodeEnum::TypeCast(Box::new(TypeCast {
+                arg: Some(node),
+                type_name: Some(TypeName {
+                    names: vec![Self::string("pg_catalog"), Self::string("int8")],
+                    typemod: -1,
+                    location: -1,
+                    ..Default::default()
+                }),
+                location: -1,
+            }))),
+        })
+    }
+
+    fn string(value: &str) -> Node {
+        Node {
+            node: Some(NodeEnum::String(pg_query::protobuf::String {
+                sval: value.into(),
+            })),
+        }
+    }
+
+    /// Integer constant. Like the parser, use a numeric constant for values
+    /// that don't fit in int4, which PostgreSQL treats as int8.
+    fn integer(value: usize) -> Node {
+        let val = match i32::try_from(value) {
+            Ok(ival) => Val::Ival(Integer { ival }),
+            Err(_) => Val::Fval(Float {
+                fval: value.min(i64::MAX as usize).to_string(),
+            }),
+        };
+
+        Node {
+            node: Some(NodeEnum::AConst(AConst {
+                val: Some(val),
+                isnull: false,
+                location: -1,
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::string::String;
+
+    use super::*;
+    use crate::net::messages::{Bind, Parameter};
+
+    fn select(query: &str) -> SelectStmt {
+        let ast = pg_query::parse(query).unwrap();
+        let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match root.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => *stmt.clone(),
+            _ => panic!("not a select"),
+        }
+    }
+
+    fn rewrite(query: &str, aggregate: bool) -> String {
+        let mut stmt = select(query);
+        assert!(Limit::rewrite(&mut stmt, aggregate));
+        NodeEnum::SelectStmt(Box::new(stmt)).deparse().unwrap()
+    }
+
+    #[test]
+    fn test_limit() {
+        let stmt = select("SELECT * FROM sharded ORDER BY id LIMIT 20 OFFSET 40");
+        let limit = Limit::parse(&stmt, &None).unwrap();
+        assert_eq!(limit.limit(), Some(20));
+        assert_eq!(limit.offset(), 40);
+        assert!(!limit.includes(39));
+        assert!(limit.includes(40));
+        assert!(limit.includes(59));
+        assert!(!limit.includes(60));
+        assert_eq!(limit.rows(45), 5);
+        assert_eq!(limit.rows(100), 20);
+        assert_eq!(limit.rows(10), 0);
+
+        assert!(Limit::parse(&select("SELECT * FROM sharded"), &None).is_none());
+        assert!(Limit::parse(&select("SELECT * FROM sharded LIMIT ALL"), &None).is_none());
+
+        let bind = Bind {
+            params: vec![Parameter {
+                len: 1,
+                data: "5".as_bytes().to_vec(),
+            }],
+            ..Default::default()
+        };
+        let stmt = select("SELECT * FROM sharded LIMIT 10 OFFSET $1");
+        let limit = Limit::parse(&stmt, &Some(Parameters::new(bind, vec![]))).unwrap();
+        assert_eq!(limit.limit(), Some(10));
+        assert_eq!(limit.offset(), 5);
+        assert!(Limit::parse(&stmt, &None).is_none());
+
+        let stmt = select("SELECT * FROM sharded LIMIT 10 OFFSET $0");
+        let params = Some(Parameters::new(Bind::default(), vec![]));
+        assert!(Limit::parse(&stmt, &params).is_none());
+    }
+
+    #[test]
+    fn test_limit_rewrite() {
+        assert_eq!(
+            rewrite(
+                "SELECT * FROM sharded ORDER BY id LIMIT 20 OFFSET 40",
+                false
+            ),
+            "SELECT * FROM sharded ORDER BY id LIMIT 60"
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM sharded LIMIT $1 OFFSET $2", false),
+            "SELECT * FROM sharded LIMIT $1::bigint + $2::bigint"
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM sharded OFFSET 5", false),
+            "SELECT * FROM sharded"
+        );
+        assert_eq!(
+            rewrite("SELECT id, count(*) FROM sharded GROUP BY id LIMIT 5", true),
+            "SELECT id, count(*) FROM sharded GROUP BY id"
+        );
+
+        assert_eq!(
+            rewrite(
+                "SELECT * FROM sharded LIMIT 2147483647 OFFSET 2147483647",
+                false
+            ),
+            "SELECT * FROM sharded LIMIT 4294967294"
+        );
+        assert_eq!(
+            rewrite(
+                "SELECT * FROM sharded LIMIT 9223372036854775807 OFFSET 10",
+                false
+            ),
+            "SELECT * FROM sharded LIMIT 9223372036854775807"
+        );
+
+        let mut stmt = select("SELECT * FROM sharded LIMIT 5");
+        assert!(!Limit::rewrite(&mut stmt, false));
+    }
+}
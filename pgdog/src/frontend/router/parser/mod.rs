@@ -1,5 +1,7 @@
 //! Query parser.
 
+pub mod aggregate;
+pub mod binary;
 pub mod cache;
 pub mod column;
 pub mod comment;
@@ -8,23 +10,31 @@ pub mod csv;
 pub mod error;
 pub mod insert;
 pub mod key;
+pub mod limit;
 pub mod order_by;
+pub mod parameters;
 pub mod query;
+pub mod rewrite;
 pub mod route;
 pub mod table;
 pub mod tuple;
 pub mod value;
 pub mod where_clause;
 
+pub use aggregate::{Aggregate, AggregateFunction, AggregateTarget};
+pub use binary::{BinaryRecord, BinaryStream, BinaryTuple};
 pub use cache::Cache;
 pub use column::Column;
 pub use copy::CopyParser;
 pub use csv::{CsvStream, Record};
 pub use error::Error;
-pub use insert::Insert;
+pub use insert::{Insert, ShardedInsert};
 pub use key::Key;
+pub use limit::Limit;
 pub use order_by::OrderBy;
+pub use parameters::Parameters;
 pub use query::{Command, QueryParser};
+pub use rewrite::{Rewrite, ShardedMessages};
 pub use route::Route;
 pub use table::Table;
 pub use tuple::Tuple;
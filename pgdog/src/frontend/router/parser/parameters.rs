@@ -0,0 +1,138 @@
+//! Parameters bound to a prepared statement.
+
+use crate::{
+    config::ShardedTable,
+    frontend::router::sharding::{shard_binary, shard_value},
+    net::messages::{Bind, Format, ParameterWithFormat},
+};
+
+use super::Error;
+
+/// Bind (F) parameters and their data types, as declared in Parse (F).
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {
+    bind: Bind,
+    data_types: Vec<i32>,
+}
+
+impl Parameters {
+    /// Create parameters from a Bind (F) message and parameter data types.
+    pub fn new(bind: Bind, data_types: Vec<i32>) -> Self {
+        Self { bind, data_types }
+    }
+
+    /// Bind (F) message.
+    pub fn bind(&self) -> &Bind {
+        &self.bind
+    }
+
+    /// Number of parameters.
+    pub fn len(&self) -> usize {
+        self.bind.params.len()
+    }
+
+    /// No parameters were bound.
+    pub fn is_empty(&self) -> bool {
+        self.bind.params.is_empty()
+    }
+
+    /// Get parameter at index.
+    pub fn parameter(&self, index: usize) -> Result<Option<ParameterWithFormat<'_>>, Error> {
+        Ok(self.bind.parameter(index)?)
+    }
+
+    /// Data type OID of the parameter at index. 0 means the type wasn't specified.
+    pub fn data_type(&self, index: usize) -> i32 {
+        self.data_types.get(index).copied().unwrap_or(0)
+    }
+
+    /// Shard on the parameter at index, decoding it using its format and data type,
+    /// with the table's sharding function.
+    pub fn shard(
+        &self,
+        index: usize,
+        table: &ShardedTable,
+        shards: usize,
+    ) -> Result<Option<usize>, Error> {
+        let Some(param) = self.parameter(index)? else {
+            return Ok(None);
+        };
+
+        Ok(match param.format() {
+            Format::Text => param
+                .text()
+                .and_then(|text| shard_value(text, table, shards)),
+            Format::Binary => shard_binary(param.data(), self.data_type(index), table, shards),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        frontend::router::sharding::{shard_int, shard_uuid},
+        net::messages::Parameter,
+    };
+
+    fn parameter(data: &[u8]) -> Parameter {
+        Parameter {
+            len: data.len() as i32,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_binary_parameters() {
+        let table = ShardedTable::default();
+        let id = Uuid::new_v4();
+        let bind = Bind {
+            codes: vec![0, 1, 1, 1],
+            params: vec![
+                parameter(b"11"),
+                parameter(&11_i64.to_be_bytes()),
+                parameter(&11_i32.to_be_bytes()),
+                parameter(id.as_bytes()),
+            ],
+            ..Default::default()
+        };
+
+        let params = Parameters::new(bind.clone(), vec![20, 20, 23, 2950]);
+        for index in 0..3 {
+            assert_eq!(
+                params.shard(index, &table, 3).unwrap(),
+                Some(shard_int(11, 3))
+            );
+        }
+        assert_eq!(params.shard(3, &table, 3).unwrap(), Some(shard_uuid(id, 3)));
+        assert_eq!(params.shard(4, &table, 3).unwrap(), None);
+
+        // Data types not specified by the client.
+        let params = Parameters::new(bind, vec![]);
+        assert_eq!(params.shard(2, &table, 3).unwrap(), Some(shard_int(11, 3)));
+        assert_eq!(params.shard(3, &table, 3).unwrap(), Some(shard_uuid(id, 3)));
+
+        // A single format code applies to all parameters.
+        let bind = Bind {
+            codes: vec![1],
+            params: vec![
+                parameter(&1_i64.to_be_bytes()),
+                parameter(&2_i64.to_be_bytes()),
+            ],
+            ..Default::default()
+        };
+        let params = Parameters::new(bind, vec![20, 20]);
+        assert_eq!(params.shard(1, &table, 3).unwrap(), Some(shard_int(2, 3)));
+
+        // Wrong size for the data type.
+        let bind = Bind {
+            codes: vec![1],
+            params: vec![parameter(&[0, 1])],
+            ..Default::default()
+        };
+        let params = Parameters::new(bind, vec![20]);
+        assert_eq!(params.shard(0, &table, 3).unwrap(), None);
+    }
+}
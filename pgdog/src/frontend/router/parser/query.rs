@@ -4,13 +4,16 @@ use std::collections::{BTreeSet, HashSet};
 use crate::{
     backend::{databases::databases, Cluster},
     frontend::{
-        router::{parser::OrderBy, round_robin, sharding::shard_str, CopyRow},
+        router::{parser::OrderBy, round_robin, sharding::shard_value, CopyRow},
         Buffer,
     },
-    net::messages::{Bind, CopyData},
+    net::messages::CopyData,
 };
 
-use super::{Cache, CopyParser, Error, Insert, Key, Route, WhereClause};
+use super::{
+    Aggregate, Cache, CopyParser, Error, Insert, Key, Limit, Parameters, Rewrite, Route,
+    ShardedInsert, ShardedMessages, Table, WhereClause,
+};
 
 use once_cell::sync::Lazy;
 use pg_query::{
@@ -33,6 +36,7 @@ static REPLICATION_REGEX: Lazy<Regex> = Lazy::new(|| {
 pub enum Command {
     Query(Route),
     Copy(Box<CopyParser>),
+    ShardedInsert(Vec<ShardedInsert>),
     StartTransaction(std::string::String),
     CommitTransaction,
     RollbackTransaction,
@@ -40,6 +44,19 @@ pub enum Command {
     ReplicationMeta,
 }
 
+impl Command {
+    /// Shards the command is sent to, if not all of them.
+    pub fn shards(&self) -> Option<Vec<usize>> {
+        match self {
+            Command::Query(route) => route.shard().map(|shard| vec![shard]),
+            Command::ShardedInsert(inserts) => {
+                Some(inserts.iter().map(|insert| insert.shard).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct QueryParser {
     command: Command,
@@ -63,7 +80,11 @@ impl QueryParser {
 
     pub fn parse(&mut self, buffer: &Buffer, cluster: &Cluster) -> Result<&Command, Error> {
         if let Some(query) = buffer.query()? {
-            self.command = self.query(&query, cluster, buffer.parameters()?)?;
+            let params = match buffer.parameters()? {
+                Some(bind) => Some(Parameters::new(bind, buffer.data_types()?)),
+                None => None,
+            };
+            self.command = self.query(&query, cluster, params)?;
         }
         Ok(&self.command)
     }
@@ -76,6 +97,33 @@ impl QueryParser {
         }
     }
 
+    /// Messages to send to each shard, if the statement had to be rewritten.
+    pub fn rewrite(
+        &self,
+        buffer: &Buffer,
+        shards: usize,
+    ) -> Result<Option<ShardedMessages>, Error> {
+        match self.command {
+            Command::ShardedInsert(ref inserts) => inserts
+                .iter()
+                .map(|insert| Ok((insert.shard, insert.rewrite(buffer)?)))
+                .collect::<Result<Vec<_>, Error>>()
+                .map(Some),
+
+            Command::Query(ref route) => match route.rewrite() {
+                Some(query) => {
+                    let messages = Rewrite::new(query).messages(buffer)?;
+                    Ok(Some(
+                        (0..shards).map(|shard| (shard, messages.clone())).collect(),
+                    ))
+                }
+                None => Ok(None),
+            },
+
+            _ => Ok(None),
+        }
+    }
+
     /// Get the route currently determined by the parser.
     pub fn route(&self) -> Route {
         match self.command {
@@ -88,7 +136,7 @@ impl QueryParser {
         &self,
         query: &str,
         cluster: &Cluster,
-        params: Option<Bind>,
+        params: Option<Parameters>,
     ) -> Result<Command, Error> {
         if self.replication_mode {
             if query.starts_with("START_REPLICATION") {
@@ -111,7 +159,7 @@ impl QueryParser {
         }
 
         // Hardcoded shard from a comment.
-        let shard = super::comment::shard(query, cluster.shards().len()).map_err(Error::PgQuery)?;
+        let shard = super::comment::shard(query, cluster).map_err(Error::PgQuery)?;
 
         // Cluster is read only or write only, traffic split isn't needed,
         // so don't parse the query further.
@@ -144,13 +192,13 @@ impl QueryParser {
                         round_robin::next() % cluster.shards().len(),
                     ))));
                 } else {
-                    Self::select(stmt, cluster, params)
+                    Self::select(stmt, cluster, &params)
                 }
             }
             Some(NodeEnum::CopyStmt(ref stmt)) => Self::copy(stmt, cluster),
             Some(NodeEnum::InsertStmt(ref stmt)) => Self::insert(stmt, cluster, &params),
-            Some(NodeEnum::UpdateStmt(ref stmt)) => Self::update(stmt),
-            Some(NodeEnum::DeleteStmt(ref stmt)) => Self::delete(stmt),
+            Some(NodeEnum::UpdateStmt(ref stmt)) => Self::update(stmt, cluster, &params),
+            Some(NodeEnum::DeleteStmt(ref stmt)) => Self::delete(stmt, cluster, &params),
             Some(NodeEnum::TransactionStmt(ref stmt)) => match stmt.kind() {
                 TransactionStmtKind::TransStmtCommit => return Ok(Command::CommitTransaction),
                 TransactionStmtKind::TransStmtRollback => return Ok(Command::RollbackTransaction),
@@ -163,6 +211,10 @@ impl QueryParser {
         }?;
 
         if let Some(shard) = shard {
+            if let Command::ShardedInsert(_) = command {
+                command = Command::Query(Route::write(Some(shard)));
+            }
+
             if let Command::Query(ref mut route) = command {
                 route.set_shard(shard);
             }
@@ -194,11 +246,9 @@ impl QueryParser {
     fn select(
         stmt: &SelectStmt,
         cluster: &Cluster,
-        params: Option<Bind>,
+        params: &Option<Parameters>,
     ) -> Result<Command, Error> {
         let order_by = Self::select_sort(&stmt.sort_clause);
-        let sharded_tables = cluster.sharded_tables();
-        let mut shards = HashSet::new();
         let table_name = stmt
             .from_clause
             .first()
@@ -213,29 +263,67 @@ impl QueryParser {
                 })
             })
             .flatten();
-        if let Some(where_clause) = WhereClause::new(table_name, &stmt.where_clause) {
+        let shards = Self::where_clause(table_name, &stmt.where_clause, cluster, params)?;
+        let shard = Self::single_shard(shards);
+
+        // Rows only need to be merged if the query goes to multiple shards.
+        if shard.is_some() || cluster.shards().len() < 2 {
+            return Ok(Command::Query(Route::select(shard, &order_by, None, None)));
+        }
+
+        let aggregate = Aggregate::parse(stmt);
+        let limit = Limit::parse(stmt, params);
+
+        let mut rewrite = stmt.clone();
+        let mut changed = aggregate
+            .as_ref()
+            .map(|aggregate| aggregate.rewrite(&mut rewrite))
+            .unwrap_or(false);
+        if limit.is_some() {
+            changed |= Limit::rewrite(&mut rewrite, aggregate.is_some());
+        }
+
+        let mut route = Route::select(shard, &order_by, aggregate, limit);
+        if changed {
+            route.set_rewrite(
+                NodeEnum::SelectStmt(Box::new(rewrite))
+                    .deparse()
+                    .map_err(Error::PgQuery)?,
+            );
+        }
+
+        Ok(Command::Query(route))
+    }
+
+    /// Find shards matching sharding keys in a `WHERE` clause.
+    fn where_clause(
+        table_name: Option<&str>,
+        where_clause: &Option<Box<Node>>,
+        cluster: &Cluster,
+        params: &Option<Parameters>,
+    ) -> Result<HashSet<usize>, Error> {
+        let mut shards = HashSet::new();
+
+        if let Some(where_clause) = WhereClause::new(table_name, where_clause) {
             // Complexity: O(number of sharded tables * number of columns in the query)
-            for table in sharded_tables {
+            for table in cluster.sharded_tables() {
                 let table_name = table.name.as_deref();
                 let keys = where_clause.keys(table_name, &table.column);
                 for key in keys {
                     match key {
                         Key::Constant(value) => {
-                            if let Some(shard) = shard_str(&value, cluster.shards().len()) {
+                            if let Some(shard) = shard_value(&value, table, cluster.shards().len())
+                            {
                                 shards.insert(shard);
                             }
                         }
 
                         Key::Parameter(param) => {
                             if let Some(ref params) = params {
-                                if let Some(param) = params.parameter(param)? {
-                                    // TODO: Handle binary encoding.
-                                    if let Some(text) = param.text() {
-                                        if let Some(shard) = shard_str(text, cluster.shards().len())
-                                        {
-                                            shards.insert(shard);
-                                        }
-                                    }
+                                if let Some(shard) =
+                                    params.shard(param, table, cluster.shards().len())?
+                                {
+                                    shards.insert(shard);
                                 }
                             }
                         }
@@ -244,13 +332,7 @@ impl QueryParser {
             }
         }
 
-        let shard = if shards.len() == 1 {
-            shards.iter().next().cloned()
-        } else {
-            None
-        };
-
-        Ok(Command::Query(Route::select(shard, &order_by)))
+        Ok(shards)
     }
 
     /// Parse the `ORDER BY` clause of a `SELECT` statement.
@@ -309,7 +391,7 @@ impl QueryParser {
     fn insert(
         stmt: &InsertStmt,
         cluster: &Cluster,
-        params: &Option<Bind>,
+        params: &Option<Parameters>,
     ) -> Result<Command, Error> {
         let insert = Insert::new(stmt);
         let columns = insert
@@ -322,38 +404,90 @@ impl QueryParser {
 
         let sharding_column = cluster.sharded_column(table, &columns);
         let mut shards = BTreeSet::new();
+        let mut tuples = vec![];
         if let Some(column) = sharding_column {
             for tuple in insert.tuples() {
-                if let Some(value) = tuple.get(column) {
-                    shards.insert(if let Some(bind) = params {
-                        value.shard_placeholder(bind, num_shards)
+                if let Some(value) = tuple.get(column.position) {
+                    let shard = if let Some(params) = params {
+                        value.shard_placeholder(params, column.table, num_shards)
                     } else {
-                        value.shard(num_shards)
-                    });
+                        value.shard(column.table, num_shards)
+                    };
+                    shards.insert(shard);
+                    tuples.push(shard);
                 }
             }
         }
 
-        // TODO: support sending inserts to multiple shards.
         if shards.len() == 1 {
-            Ok(Command::Query(Route::write(shards.pop_last().unwrap())))
-        } else {
-            Ok(Command::Query(Route::write(None)))
+            return Ok(Command::Query(Route::write(shards.pop_last().unwrap())));
+        }
+
+        // Rows go to different shards. Split the statement, making sure
+        // we know where each row goes and all parameters are accounted for.
+        if shards.len() > 1 && !shards.contains(&None) {
+            let tuples = tuples.into_iter().flatten().collect::<Vec<_>>();
+            let inserts = insert.split(&tuples)?;
+            let num_params = inserts
+                .iter()
+                .flat_map(|insert| insert.params.iter())
+                .collect::<BTreeSet<_>>()
+                .len();
+            let expected = params.as_ref().map(|params| params.len()).unwrap_or(0);
+
+            if !inserts.is_empty() && num_params == expected {
+                return Ok(Command::ShardedInsert(inserts));
+            }
         }
-    }
 
-    fn update(_stmt: &UpdateStmt) -> Result<Command, Error> {
         Ok(Command::Query(Route::write(None)))
     }
 
-    fn delete(_stmt: &DeleteStmt) -> Result<Command, Error> {
-        Ok(Command::Query(Route::write(None)))
+    fn update(
+        stmt: &UpdateStmt,
+        cluster: &Cluster,
+        params: &Option<Parameters>,
+    ) -> Result<Command, Error> {
+        let table = stmt.relation.as_ref().map(Table::from);
+        let shards = Self::where_clause(
+            table.map(|table| table.name),
+            &stmt.where_clause,
+            cluster,
+            params,
+        )?;
+
+        Ok(Command::Query(Route::write(Self::single_shard(shards))))
+    }
+
+    fn delete(
+        stmt: &DeleteStmt,
+        cluster: &Cluster,
+        params: &Option<Parameters>,
+    ) -> Result<Command, Error> {
+        let table = stmt.relation.as_ref().map(Table::from);
+        let shards = Self::where_clause(
+            table.map(|table| table.name),
+            &stmt.where_clause,
+            cluster,
+            params,
+        )?;
+
+        Ok(Command::Query(Route::write(Self::single_shard(shards))))
+    }
+
+    /// Route to a shard only if all keys point to the same one.
+    fn single_shard(shards: HashSet<usize>) -> Option<usize> {
+        if shards.len() == 1 {
+            shards.into_iter().next()
+        } else {
+            None
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::net::messages::{parse::Parse, Parameter, Protocol};
+    use crate::net::messages::{parse::Parse, Bind, Parameter, Protocol};
 
     use super::*;
     use crate::net::messages::Query;
@@ -422,4 +556,162 @@ mod test {
             panic!("not a route");
         }
     }
+
+    #[test]
+    fn test_update() {
+        let query = Parse::new_anonymous("UPDATE sharded SET email = $2 WHERE id = $1");
+        let params = Bind {
+            portal: "".into(),
+            statement: "".into(),
+            codes: vec![],
+            params: vec![
+                Parameter {
+                    len: 2,
+                    data: "11".as_bytes().to_vec(),
+                },
+                Parameter {
+                    len: "test@test.com".len() as i32,
+                    data: "test@test.com".as_bytes().to_vec(),
+                },
+            ],
+            results: vec![],
+        };
+        let mut buffer = Buffer::new();
+        buffer.push(query.message().unwrap());
+        buffer.push(params.message().unwrap());
+
+        let mut parser = QueryParser::default();
+        let cluster = Cluster::new_test();
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::Query(route) = command {
+            assert_eq!(route.shard(), Some(1));
+            assert!(route.is_write());
+        } else {
+            panic!("not a route");
+        }
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut parser = QueryParser::default();
+        let cluster = Cluster::new_test();
+
+        let mut buffer = Buffer::new();
+        buffer.push(
+            Query::new("DELETE FROM sharded WHERE id = 11")
+                .message()
+                .unwrap(),
+        );
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::Query(route) = command {
+            assert_eq!(route.shard(), Some(1));
+            assert!(route.is_write());
+        } else {
+            panic!("not a route");
+        }
+
+        let mut buffer = Buffer::new();
+        buffer.push(
+            Query::new("DELETE FROM sharded WHERE email = 'test@test.com'")
+                .message()
+                .unwrap(),
+        );
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::Query(route) = command {
+            assert!(route.is_all_shards());
+            assert!(route.is_write());
+        } else {
+            panic!("not a route");
+        }
+    }
+
+    #[test]
+    fn test_insert_multi_shard() {
+        let query = Parse::new_anonymous(
+            "INSERT INTO sharded (id, email) VALUES ($1, lower($2)), ($3::bigint, $4)",
+        );
+        let params = Bind {
+            params: ["11", "one@test.com", "1", "two@test.com"]
+                .into_iter()
+                .map(|param| Parameter {
+                    len: param.len() as i32,
+                    data: param.as_bytes().to_vec(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new();
+        buffer.push(query.message().unwrap());
+        buffer.push(params.message().unwrap());
+
+        let mut parser = QueryParser::default();
+        let cluster = Cluster::new_test();
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::ShardedInsert(inserts) = command {
+            assert_eq!(inserts.len(), 2);
+            assert_eq!(inserts[0].shard, 0);
+            assert_eq!(inserts[0].params, vec![2, 3]);
+            assert_eq!(inserts[1].shard, 1);
+            assert_eq!(inserts[1].params, vec![0, 1]);
+            assert_eq!(
+                inserts[0].query,
+                "INSERT INTO sharded (id, email) VALUES ($1::bigint, $2)"
+            );
+        } else {
+            panic!("not a sharded insert");
+        }
+        assert!(parser.route().is_all_shards());
+    }
+
+    #[test]
+    fn test_binary_parameters() {
+        let mut parse = Parse::new_anonymous("SELECT * FROM sharded WHERE id = $1");
+        parse.data_types = vec![20];
+        let bind = Bind {
+            codes: vec![1],
+            params: vec![Parameter {
+                len: 8,
+                data: 11_i64.to_be_bytes().to_vec(),
+            }],
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new();
+        buffer.push(parse.message().unwrap());
+        buffer.push(bind.message().unwrap());
+
+        let mut parser = QueryParser::default();
+        let cluster = Cluster::new_test();
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::Query(route) = command {
+            assert_eq!(route.shard(), Some(1));
+        } else {
+            panic!("not a route");
+        }
+
+        let parse = Parse::new_anonymous("INSERT INTO sharded (id, email) VALUES ($1, $2)");
+        let bind = Bind {
+            codes: vec![1, 0],
+            params: vec![
+                Parameter {
+                    len: 4,
+                    data: 11_i32.to_be_bytes().to_vec(),
+                },
+                Parameter {
+                    len: 13,
+                    data: "test@test.com".as_bytes().to_vec(),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new();
+        buffer.push(parse.message().unwrap());
+        buffer.push(bind.message().unwrap());
+
+        let command = parser.parse(&buffer, &cluster).unwrap();
+        if let Command::Query(route) = command {
+            assert_eq!(route.shard(), Some(1));
+        } else {
+            panic!("not a route");
+        }
+    }
 }
@@ -0,0 +1,151 @@
+//! Rewrite client messages to execute a different statement.
+
+use crate::{
+    frontend::Buffer,
+    net::messages::{Bind, Describe, FromBytes, Message, Parse, Protocol, Query, ToBytes},
+};
+
+use super::Error;
+
+/// Messages to send to each shard.
+pub type ShardedMessages = Vec<(usize, Vec<Message>)>;
+
+/// Replace the statement sent by the client with another one.
+#[derive(Debug)]
+pub struct Rewrite<'a> {
+    query: &'a str,
+    params: Option<&'a [usize]>,
+}
+
+impl<'a> Rewrite<'a> {
+    /// Execute this query instead, using the same parameters.
+    pub fn new(query: &'a str) -> Self {
+        Self {
+            query,
+            params: None,
+        }
+    }
+
+    /// Only pass these parameters, in this order, to the new statement.
+    pub fn params(mut self, params: &'a [usize]) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    /// Rewrite client messages to execute the new statement instead of the original one.
+    ///
+    /// Prepared statements are replaced with the unnamed statement. If the client didn't send
+    /// a Parse (F) message, because the statement was already prepared, one is added before Bind (F).
+    pub fn messages(&self, buffer: &Buffer) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![];
+        let mut parsed = false;
+
+        for message in buffer.iter() {
+            match message.code() {
+                'Q' => {
+                    messages.push(Query::new(self.query).message()?);
+                }
+
+                'P' => {
+                    let original = Parse::from_bytes(message.to_bytes()?)?;
+                    messages.push(self.parse(&original.data_types).message()?);
+                    parsed = true;
+                }
+
+                'B' => {
+                    if !parsed {
+                        messages.push(self.parse(&[]).message()?);
+                        parsed = true;
+                    }
+                    let bind = Bind::from_bytes(message.to_bytes()?)?;
+                    messages.push(self.bind(&bind).message()?);
+                }
+
+                'D' => {
+                    let mut describe = Describe::from_bytes(message.to_bytes()?)?;
+                    if describe.kind == 'S' {
+                        describe.statement = "".into();
+                    }
+                    messages.push(describe.message()?);
+                }
+
+                _ => messages.push(message.clone()),
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn parse(&self, data_types: &[i32]) -> Parse {
+        let mut parse = Parse::new_anonymous(self.query);
+        parse.data_types = match self.params {
+            Some(params) if !data_types.is_empty() => params
+                .iter()
+                .map(|param| data_types.get(*param).copied().unwrap_or(0))
+                .collect(),
+            _ => data_types.to_vec(),
+        };
+        parse
+    }
+
+    fn bind(&self, bind: &Bind) -> Bind {
+        let Some(params) = self.params else {
+            return Bind {
+                statement: "".into(),
+                ..bind.clone()
+            };
+        };
+
+        let codes = if bind.codes.len() > 1 {
+            params
+                .iter()
+                .map(|param| bind.codes.get(*param).copied().unwrap_or(0))
+                .collect()
+        } else {
+            bind.codes.clone()
+        };
+
+        Bind {
+            portal: bind.portal.clone(),
+            statement: "".into(),
+            codes,
+            params: params
+                .iter()
+                .filter_map(|param| bind.params.get(*param).cloned())
+                .collect(),
+            results: bind.results.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::Parameter;
+
+    #[test]
+    fn test_rewrite_prepared() {
+        let mut buffer = Buffer::new();
+        buffer.push(
+            Bind {
+                statement: "__pgdog_1".into(),
+                params: vec![Parameter {
+                    len: 1,
+                    data: "1".as_bytes().to_vec(),
+                }],
+                ..Default::default()
+            }
+            .message()
+            .unwrap(),
+        );
+
+        let messages = Rewrite::new("SELECT $1").messages(&buffer).unwrap();
+        assert_eq!(messages.len(), 2);
+        let parse = Parse::from_bytes(messages[0].to_bytes().unwrap()).unwrap();
+        assert_eq!(parse.query, "SELECT $1");
+        assert!(parse.anonymous());
+        let bind = Bind::from_bytes(messages[1].to_bytes().unwrap()).unwrap();
+        assert!(bind.anonymous());
+        assert_eq!(bind.params.len(), 1);
+    }
+}
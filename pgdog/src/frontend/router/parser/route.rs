@@ -1,4 +1,4 @@
-use super::OrderBy;
+use super::{Aggregate, Limit, OrderBy};
 
 /// Path a query should take and any transformations
 /// that should be applied along the way.
@@ -7,6 +7,9 @@ pub struct Route {
     shard: Option<usize>,
     read: bool,
     order_by: Vec<OrderBy>,
+    aggregate: Option<Aggregate>,
+    limit: Option<Limit>,
+    rewrite: Option<String>,
 }
 
 impl Default for Route {
@@ -17,11 +20,19 @@ impl Default for Route {
 
 impl Route {
     /// SELECT query.
-    pub fn select(shard: Option<usize>, order_by: &[OrderBy]) -> Self {
+    pub fn select(
+        shard: Option<usize>,
+        order_by: &[OrderBy],
+        aggregate: Option<Aggregate>,
+        limit: Option<Limit>,
+    ) -> Self {
         Self {
             shard,
             order_by: order_by.to_vec(),
             read: true,
+            aggregate,
+            limit,
+            rewrite: None,
         }
     }
 
@@ -31,6 +42,9 @@ impl Route {
             shard,
             read: true,
             order_by: vec![],
+            aggregate: None,
+            limit: None,
+            rewrite: None,
         }
     }
 
@@ -40,6 +54,9 @@ impl Route {
             shard,
             read: false,
             order_by: vec![],
+            aggregate: None,
+            limit: None,
+            rewrite: None,
         }
     }
 
@@ -65,7 +82,26 @@ impl Route {
         &self.order_by
     }
 
+    /// Aggregate functions to merge across shards.
+    pub fn aggregate(&self) -> Option<&Aggregate> {
+        self.aggregate.as_ref()
+    }
+
+    /// Limit and offset to apply to rows merged from all shards.
+    pub fn limit(&self) -> Option<&Limit> {
+        self.limit.as_ref()
+    }
+
+    /// Query to send to the shards instead of the one sent by the client.
+    pub fn rewrite(&self) -> Option<&str> {
+        self.rewrite.as_deref()
+    }
+
     pub fn set_shard(&mut self, shard: usize) {
         self.shard = Some(shard);
     }
+
+    pub fn set_rewrite(&mut self, query: String) {
+        self.rewrite = Some(query);
+    }
 }
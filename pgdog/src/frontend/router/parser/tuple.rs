@@ -16,12 +16,12 @@ pub struct Tuple<'a> {
 impl<'a> TryFrom<&'a List> for Tuple<'a> {
     type Error = ();
 
+    /// Values that aren't constants or placeholders are expressions.
     fn try_from(value: &'a List) -> Result<Self, Self::Error> {
         let mut values = vec![];
 
         for value in &value.items {
-            let value = value.try_into()?;
-            values.push(value);
+            values.push(value.try_into().unwrap_or(Value::Expression));
         }
 
         Ok(Self { values })
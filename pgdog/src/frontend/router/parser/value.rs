@@ -6,8 +6,11 @@ use pg_query::{
 };
 
 use crate::{
-    frontend::router::sharding::{shard_int, shard_str},
-    net::messages::Bind,
+    config::{DataType, ShardedTable},
+    frontend::router::{
+        parser::Parameters,
+        sharding::{shard, shard_value, ShardingKey},
+    },
 };
 
 /// A value extracted from a query.
@@ -18,27 +21,38 @@ pub enum Value<'a> {
     Boolean(bool),
     Null,
     Placeholder(i32),
+    /// Expression, e.g. a function call, that isn't evaluated.
+    Expression,
 }
 
 impl<'a> Value<'a> {
-    /// Extract value from a Bind (F) message and shard on it.
-    pub fn shard_placeholder(&self, bind: &'a Bind, shards: usize) -> Option<usize> {
+    /// Extract value from Bind (F) parameters and shard on it.
+    pub fn shard_placeholder(
+        &self,
+        params: &Parameters,
+        table: &ShardedTable,
+        shards: usize,
+    ) -> Option<usize> {
         match self {
-            Value::Placeholder(placeholder) => bind
-                .parameter(*placeholder as usize - 1)
+            Value::Placeholder(placeholder) => params
+                .shard(*placeholder as usize - 1, table, shards)
                 .ok()
-                .flatten()
-                .and_then(|value| value.text().map(|value| shard_str(value, shards)))
                 .flatten(),
-            _ => self.shard(shards),
+            _ => self.shard(table, shards),
         }
     }
 
-    /// Shard the value given the number of shards in the cluster.
-    pub fn shard(&self, shards: usize) -> Option<usize> {
+    /// Shard the value using the table's sharding function.
+    pub fn shard(&self, table: &ShardedTable, shards: usize) -> Option<usize> {
         match self {
-            Value::String(v) => shard_str(v, shards),
-            Value::Integer(v) => Some(shard_int(*v, shards)),
+            Value::String(v) => shard_value(v, table, shards),
+            Value::Integer(v) => {
+                if table.data_type == DataType::Varchar {
+                    shard_value(&v.to_string(), table, shards)
+                } else {
+                    shard(ShardingKey::Integer(*v), table, shards)
+                }
+            }
             _ => None,
         }
     }
@@ -51,10 +65,7 @@ impl<'a> From<&'a AConst> for Value<'a> {
         }
 
         match value.val.as_ref() {
-            Some(Val::Sval(s)) => match s.sval.parse::<i64>() {
-                Ok(i) => Value::Integer(i),
-                Err(_) => Value::String(s.sval.as_str()),
-            },
+            Some(Val::Sval(s)) => Value::String(s.sval.as_str()),
             Some(Val::Boolval(b)) => Value::Boolean(b.boolval),
             Some(Val::Ival(i)) => Value::Integer(i.ival as i64),
             _ => Value::Null,
@@ -69,6 +80,11 @@ impl<'a> TryFrom<&'a Node> for Value<'a> {
         match &value.node {
             Some(NodeEnum::AConst(a_const)) => Ok(a_const.into()),
             Some(NodeEnum::ParamRef(param_ref)) => Ok(Value::Placeholder(param_ref.number)),
+            // Shard on the value being cast, e.g. $1::bigint.
+            Some(NodeEnum::TypeCast(cast)) => match cast.arg {
+                Some(ref arg) => arg.as_ref().try_into(),
+                None => Err(()),
+            },
             _ => Err(()),
         }
     }
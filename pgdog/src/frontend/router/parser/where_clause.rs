@@ -83,18 +83,18 @@ impl WhereClause {
                 // TODO: Handle something like
                 // id = (SELECT 5) which is stupid but legal SQL.
                 (&[left], &[right]) => match (left, right) {
-                    (Output::Column(ref column), output) => {
-                        if Self::column_match(column, table_name, column_name) {
-                            if let Some(key) = Self::get_key(output) {
-                                keys.push(key);
-                            }
+                    (Output::Column(ref column), output)
+                        if Self::column_match(column, table_name, column_name) =>
+                    {
+                        if let Some(key) = Self::get_key(output) {
+                            keys.push(key);
                         }
                     }
-                    (output, Output::Column(ref column)) => {
-                        if Self::column_match(column, table_name, column_name) {
-                            if let Some(key) = Self::get_key(output) {
-                                keys.push(key);
-                            }
+                    (output, Output::Column(ref column))
+                        if Self::column_match(column, table_name, column_name) =>
+                    {
+                        if let Some(key) = Self::get_key(output) {
+                            keys.push(key);
                         }
                     }
                     _ => (),
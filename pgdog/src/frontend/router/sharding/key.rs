@@ -0,0 +1,61 @@
+//! Sharding key extracted from queries, parameters and rows.
+
+use std::str::from_utf8;
+
+use uuid::Uuid;
+
+use crate::{
+    config::{DataType, ShardListValue},
+    net::messages::{Format, FromDataType},
+};
+
+/// Sharding key value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShardingKey<'a> {
+    Integer(i64),
+    Uuid(Uuid),
+    Text(&'a str),
+}
+
+impl<'a> ShardingKey<'a> {
+    /// Parse a value encoded as text, parsing out a BIGINT or UUID,
+    /// unless the column is text.
+    pub fn from_text(value: &'a str, data_type: DataType) -> Self {
+        if data_type == DataType::Varchar {
+            Self::Text(value)
+        } else if let Ok(value) = value.parse::<i64>() {
+            Self::Integer(value)
+        } else if let Ok(value) = value.parse::<Uuid>() {
+            Self::Uuid(value)
+        } else {
+            Self::Text(value)
+        }
+    }
+
+    /// Decode a value encoded in binary, using its data type OID.
+    /// If the type isn't known, it's guessed from the column data type or the size of the value.
+    pub fn from_binary(data: &'a [u8], oid: i32, data_type: DataType) -> Option<Self> {
+        match (oid, data.len()) {
+            (25 | 1043, _) | (0, _) if data_type == DataType::Varchar => {
+                from_utf8(data).ok().map(Self::Text)
+            }
+            (20, _) | (0, 8) => i64::decode(data, Format::Binary).ok().map(Self::Integer),
+            (23, _) | (0, 4) => i32::decode(data, Format::Binary)
+                .ok()
+                .map(|value| Self::Integer(value as i64)),
+            (2950, _) | (0, 16) => Uuid::decode(data, Format::Binary).ok().map(Self::Uuid),
+            _ => None,
+        }
+    }
+
+    /// Key is equal to a value in a sharding list.
+    pub fn matches(&self, value: &ShardListValue) -> bool {
+        match (self, value) {
+            (Self::Integer(key), ShardListValue::Integer(value)) => key == value,
+            (Self::Integer(key), ShardListValue::String(value)) => value.parse() == Ok(*key),
+            (Self::Uuid(key), ShardListValue::String(value)) => value.parse() == Ok(*key),
+            (Self::Text(key), ShardListValue::String(value)) => key == value,
+            _ => false,
+        }
+    }
+}
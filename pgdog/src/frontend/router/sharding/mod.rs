@@ -1,6 +1,11 @@
 use uuid::Uuid;
 
+use crate::config::{DataType, ShardedTable, ShardingFunction};
+
 pub mod ffi;
+pub mod key;
+
+pub use key::ShardingKey;
 
 /// Hash `BIGINT`.
 pub fn bigint(id: i64) -> u64 {
@@ -17,18 +22,177 @@ pub fn uuid(uuid: Uuid) -> u64 {
     }
 }
 
+/// Hash `VARCHAR` or `TEXT`, same as `hashtext`.
+pub fn varchar(value: &[u8]) -> u64 {
+    unsafe {
+        ffi::hash_combine64(
+            0,
+            ffi::hash_bytes_extended(value.as_ptr(), value.len() as i64),
+        )
+    }
+}
+
 /// Shard an integer.
 pub fn shard_int(value: i64, shards: usize) -> usize {
     bigint(value) as usize % shards
 }
 
-/// Shard a string value, parsing out a BIGINT or UUID.
-pub fn shard_str(value: &str, shards: usize) -> Option<usize> {
-    Some(match value.parse::<i64>() {
-        Ok(value) => bigint(value) as usize % shards,
-        Err(_) => match value.parse::<Uuid>() {
-            Ok(value) => uuid(value) as usize % shards,
-            Err(_) => return None,
+/// Shard a UUID.
+pub fn shard_uuid(value: Uuid, shards: usize) -> usize {
+    uuid(value) as usize % shards
+}
+
+/// Shard a value encoded in binary, using its data type OID.
+pub fn shard_binary(data: &[u8], oid: i32, table: &ShardedTable, shards: usize) -> Option<usize> {
+    ShardingKey::from_binary(data, oid, table.data_type).and_then(|key| shard(key, table, shards))
+}
+
+/// Shard a value encoded as text.
+pub fn shard_value(value: &str, table: &ShardedTable, shards: usize) -> Option<usize> {
+    shard(
+        ShardingKey::from_text(value, table.data_type),
+        table,
+        shards,
+    )
+}
+
+/// Shard a key using the table's sharding function.
+///
+/// Returns `None` if the function can't place the key on any of the shards.
+pub fn shard(key: ShardingKey, table: &ShardedTable, shards: usize) -> Option<usize> {
+    if shards == 0 {
+        return None;
+    }
+
+    let shard = match table.sharding_function {
+        ShardingFunction::Hash => match key {
+            ShardingKey::Integer(value) => Some(shard_int(value, shards)),
+            ShardingKey::Uuid(value) => Some(shard_uuid(value, shards)),
+            ShardingKey::Text(value) if table.data_type == DataType::Varchar => {
+                Some(varchar(value.as_bytes()) as usize % shards)
+            }
+            ShardingKey::Text(_) => None,
         },
-    })
+
+        ShardingFunction::Modulo => match key {
+            ShardingKey::Integer(value) => Some(value.rem_euclid(shards as i64) as usize),
+            ShardingKey::Uuid(value) => Some((value.as_u128() % shards as u128) as usize),
+            ShardingKey::Text(_) => None,
+        },
+
+        ShardingFunction::Range => match key {
+            ShardingKey::Integer(value) => table
+                .ranges
+                .iter()
+                .find(|range| range.contains(value))
+                .map(|range| range.shard),
+            _ => None,
+        },
+
+        ShardingFunction::List => table
+            .lists
+            .iter()
+            .find(|list| list.values.iter().any(|value| key.matches(value)))
+            .map(|list| list.shard),
+    };
+
+    shard.filter(|shard| *shard < shards)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ShardList, ShardListValue, ShardRange};
+
+    #[test]
+    fn test_sharding_functions() {
+        let table = ShardedTable {
+            sharding_function: ShardingFunction::Modulo,
+            ..Default::default()
+        };
+        assert_eq!(shard_value("7", &table, 3), Some(1));
+        assert_eq!(shard_value("-1", &table, 3), Some(2));
+        assert_eq!(shard_value("acme", &table, 3), None);
+
+        let table = ShardedTable {
+            sharding_function: ShardingFunction::Range,
+            ranges: vec![
+                ShardRange {
+                    start: None,
+                    end: Some(100),
+                    shard: 0,
+                },
+                ShardRange {
+                    start: Some(100),
+                    end: Some(200),
+                    shard: 1,
+                },
+                ShardRange {
+                    start: Some(200),
+                    end: None,
+                    shard: 5,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(shard_value("99", &table, 2), Some(0));
+        assert_eq!(shard_value("100", &table, 2), Some(1));
+        // Shard doesn't exist.
+        assert_eq!(shard_value("200", &table, 2), None);
+        assert_eq!(shard_binary(&150_i64.to_be_bytes(), 20, &table, 2), Some(1));
+
+        let id = Uuid::new_v4();
+        let table = ShardedTable {
+            sharding_function: ShardingFunction::List,
+            lists: vec![
+                ShardList {
+                    values: vec![
+                        ShardListValue::Integer(1),
+                        ShardListValue::String("acme".into()),
+                    ],
+                    shard: 1,
+                },
+                ShardList {
+                    values: vec![ShardListValue::String(id.to_string())],
+                    shard: 0,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(shard_value("1", &table, 2), Some(1));
+        assert_eq!(shard_value("acme", &table, 2), Some(1));
+        assert_eq!(shard_value(&id.to_string(), &table, 2), Some(0));
+        assert_eq!(shard_binary(id.as_bytes(), 2950, &table, 2), Some(0));
+        assert_eq!(shard_value("2", &table, 2), None);
+
+        let table = ShardedTable::default();
+        assert_eq!(shard_value("1", &table, 2), Some(shard_int(1, 2)));
+        assert_eq!(shard_value("acme", &table, 2), None);
+    }
+
+    #[test]
+    fn test_varchar() {
+        let table = ShardedTable {
+            data_type: DataType::Varchar,
+            ..Default::default()
+        };
+
+        // Same partitions as `PARTITION BY HASH (text)` with modulus 3.
+        for (value, shard) in [
+            ("acme", 2),
+            ("test@test.com", 0),
+            ("1", 0),
+            ("", 2),
+            ("héllo wörld", 1),
+            ("a somewhat longer tenant slug value", 2),
+        ] {
+            assert_eq!(shard_value(value, &table, 3), Some(shard), "{}", value);
+            assert_eq!(
+                shard_binary(value.as_bytes(), 25, &table, 3),
+                Some(shard),
+                "{}",
+                value
+            );
+        }
+    }
 }
@@ -23,6 +23,8 @@ pub struct Stats {
     pub last_transaction_time: Duration,
     /// Total query time.
     pub query_time: Duration,
+    /// Last query time.
+    pub last_query_time: Duration,
     /// Total wait time.
     pub wait_time: Duration,
     /// Current client state.
@@ -44,6 +46,7 @@ impl Stats {
             transaction_time: Duration::from_secs(0),
             last_transaction_time: Duration::from_secs(0),
             query_time: Duration::from_secs(0),
+            last_query_time: Duration::from_secs(0),
             wait_time: Duration::from_secs(0),
             state: State::Idle,
             transaction_timer: now,
@@ -69,7 +72,8 @@ impl Stats {
     pub(super) fn query(&mut self) -> Self {
         let now = Instant::now();
         self.queries += 1;
-        self.query_time += now.duration_since(self.query_timer);
+        self.last_query_time = now.duration_since(self.query_timer);
+        self.query_time += self.last_query_time;
         self.query_timer = now;
         *self
     }
@@ -0,0 +1,66 @@
+//! Logging setup.
+
+use std::io::IsTerminal;
+
+use once_cell::sync::OnceCell;
+use tracing::{error, level_filters::LevelFilter};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Layer, Registry};
+
+use crate::config::LogFormat;
+
+/// Output layer, swapped when the log format changes.
+type Format = Box<dyn Layer<Registry> + Send + Sync>;
+
+static FORMAT: OnceCell<reload::Handle<Format, Registry>> = OnceCell::new();
+
+fn layer(format: LogFormat) -> Format {
+    match format {
+        LogFormat::Text => {
+            let layer = fmt::layer()
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_file(false);
+            #[cfg(not(debug_assertions))]
+            let layer = layer.with_target(false);
+            layer.boxed()
+        }
+
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_file(false)
+            .boxed(),
+    }
+}
+
+/// Setup the logger, so `info!`, `debug!`
+/// and other macros actually output something.
+///
+/// Using try_init and ignoring errors to allow
+/// for use in tests (setting up multiple times).
+pub fn init() {
+    let (format, handle) = reload::Layer::new(layer(LogFormat::default()));
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+
+    if tracing_subscriber::registry()
+        .with(format)
+        .with(filter)
+        .try_init()
+        .is_ok()
+    {
+        let _ = FORMAT.set(handle);
+    }
+}
+
+/// Change the log output format.
+pub fn format(format: LogFormat) {
+    if let Some(handle) = FORMAT.get() {
+        if let Err(err) = handle.reload(layer(format)) {
+            error!("failed to change log format: {}", err);
+        }
+    }
+}
@@ -1,15 +1,14 @@
 //! pgDog, modern PostgreSQL proxy, pooler and query router.
 
-use backend::databases;
+use backend::{databases, two_pc::two_pc};
 use clap::Parser;
 use cli::Commands;
-use config::config;
+use config::{config, watcher::Watcher};
 use frontend::listener::Listener;
-use tokio::runtime::Builder;
-use tracing::{info, level_filters::LevelFilter};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use tokio::{runtime::Builder, spawn};
+use tracing::{error, info, warn};
 
-use std::{io::IsTerminal, process::exit};
+use std::process::exit;
 
 pub mod admin;
 pub mod auth;
@@ -17,6 +16,7 @@ pub mod backend;
 pub mod cli;
 pub mod config;
 pub mod frontend;
+pub mod logger;
 pub mod net;
 pub mod plugin;
 pub mod state;
@@ -25,32 +25,10 @@ pub mod stats;
 pub mod tui;
 pub mod util;
 
-/// Setup the logger, so `info!`, `debug!`
-/// and other macros actually output something.
-///
-/// Using try_init and ignoring errors to allow
-/// for use in tests (setting up multiple times).
-fn logger() {
-    let format = fmt::layer()
-        .with_ansi(std::io::stderr().is_terminal())
-        .with_file(false);
-    #[cfg(not(debug_assertions))]
-    let format = format.with_target(false);
-
-    let filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env_lossy();
-
-    let _ = tracing_subscriber::registry()
-        .with(format)
-        .with(filter)
-        .try_init();
-}
-
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = cli::Cli::parse();
 
-    logger();
+    logger::init();
 
     let mut overrides = config::Overrides::default();
 
@@ -87,6 +65,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     config::overrides(overrides);
 
+    // Plugins set up their own logger, using the same format.
+    let log_format = config.config.general.log_format;
+    logger::format(log_format);
+    std::env::set_var(pgdog_plugin::logging::LOG_FORMAT, log_format.to_string());
+
     plugin::load_from_config()?;
 
     let runtime = match config.config.general.workers {
@@ -114,10 +97,52 @@ async fn pgdog() -> Result<(), Box<dyn std::error::Error>> {
     // are async, so doing this after Tokio launched seems prudent.
     net::tls::load()?;
 
+    let general = &config().config.general;
+
+    // Load the two-phase commit log before pools start recovery.
+    if general.two_phase_commit {
+        two_pc().init(&general.two_phase_commit_log).await?;
+    }
+
     // Load databases and connect if needed.
     databases::init();
 
-    let general = &config().config.general;
+    // Always watch, so watch_config can be enabled on reload.
+    let watcher = {
+        let config = config();
+        Watcher::new(&[&config.config_path, &config.users_path])
+    };
+    match watcher {
+        Ok(mut watcher) => {
+            spawn(async move {
+                while watcher
+                    .changed(config().config.general.watch_config_delay())
+                    .await
+                {
+                    if !config().config.general.watch_config {
+                        continue;
+                    }
+                    info!("config changed, reloading");
+                    if let Err(err) = databases::reload() {
+                        error!("reload failed: {}", err);
+                    }
+                }
+            });
+        }
+        Err(err) if general.watch_config => return Err(err.into()),
+        Err(err) => warn!("config watcher failed to start: {}", err),
+    }
+
+    if let Some(port) = config().config.stats.openmetrics_port {
+        let host = config()
+            .config
+            .stats
+            .openmetrics_host
+            .clone()
+            .unwrap_or(general.host.clone());
+        let http = stats::http::Http::bind(&format!("{}:{}", host, port)).await?;
+        spawn(http.serve());
+    }
 
     if let Some(broadcast_addr) = general.broadcast_address {
         net::discovery::Listener::get().run(broadcast_addr, general.broadcast_port);
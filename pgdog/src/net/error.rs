@@ -37,6 +37,12 @@ pub enum Error {
     #[error("{0}")]
     Rustls(#[from] rustls::Error),
 
+    #[error("TLS verifier: {0}")]
+    TlsVerifier(String),
+
+    #[error("system root certificates not loaded: {0}")]
+    NativeCerts(String),
+
     #[error("\"{0}\" parameter is missing")]
     MissingParameter(String),
 
@@ -64,6 +70,15 @@ pub enum Error {
     #[error("not a uuid")]
     NotUuid(#[from] uuid::Error),
 
+    #[error("not a numeric")]
+    NotNumeric,
+
+    #[error("{0} out of range")]
+    OutOfRange(&'static str),
+
+    #[error("values have different data types")]
+    MismatchedDataTypes,
+
     #[error("not a timestamptz")]
     NotTimestampTz,
 
@@ -22,6 +22,13 @@ impl Password {
             response: response.to_owned(),
         }
     }
+
+    /// Create new PasswordMessage (F) with a clear text or MD5-encrypted password.
+    pub fn cleartext(password: &str) -> Self {
+        Self::PasswordMessage {
+            response: format!("{}\0", password),
+        }
+    }
 }
 
 impl FromBytes for Password {
@@ -8,7 +8,6 @@ use super::prelude::*;
 use super::Error;
 use super::FromDataType;
 
-use std::cmp::max;
 use std::str::from_utf8;
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -58,6 +57,16 @@ impl ParameterWithFormat<'_> {
         Self::decode(self)
     }
 
+    /// Format the parameter is encoded with.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Raw parameter data.
+    pub fn data(&self) -> &[u8] {
+        &self.parameter.data
+    }
+
     /// Get decoded value.
     pub fn decode<T: FromDataType>(&self) -> Option<T> {
         T::decode(&self.parameter.data, self.format).ok()
@@ -81,9 +90,15 @@ pub struct Bind {
 
 impl Bind {
     /// Format a parameter is using.
+    ///
+    /// A single format code applies to all parameters.
     pub fn parameter_format(&self, index: usize) -> Result<Format, Error> {
-        let index = max(self.codes.len() as isize - 1, index as isize) as usize;
-        if let Some(code) = self.codes.get(index) {
+        let code = if self.codes.len() == 1 {
+            self.codes.first()
+        } else {
+            self.codes.get(index)
+        };
+        if let Some(code) = code {
             match code {
                 0 => Ok(Format::Text),
                 1 => Ok(Format::Binary),
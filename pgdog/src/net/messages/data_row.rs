@@ -1,13 +1,14 @@
 //! DataRow (B) message.
 
-use super::{code, prelude::*, Datum, Format, FromDataType, Numeric, RowDescription};
+use super::{code, prelude::*, Datum, Field, Format, FromDataType, Numeric, RowDescription};
 use bytes::BytesMut;
 use std::ops::Deref;
 
 /// DataRow message.
 #[derive(Debug, Clone)]
 pub struct DataRow {
-    columns: Vec<Bytes>,
+    /// Column values, `None` is NULL.
+    columns: Vec<Option<Bytes>>,
 }
 
 /// Convert value to data row column
@@ -65,6 +66,12 @@ impl ToDataRowColumn for f64 {
     }
 }
 
+impl ToDataRowColumn for Bytes {
+    fn to_data_row_column(&self) -> Bytes {
+        self.clone()
+    }
+}
+
 impl ToDataRowColumn for u128 {
     fn to_data_row_column(&self) -> Bytes {
         Bytes::copy_from_slice(self.to_string().as_bytes())
@@ -85,7 +92,13 @@ impl DataRow {
 
     /// Add a column to the data row.
     pub fn add(&mut self, value: impl ToDataRowColumn) -> &mut Self {
-        self.columns.push(value.to_data_row_column());
+        self.columns.push(Some(value.to_data_row_column()));
+        self
+    }
+
+    /// Add a NULL column to the data row.
+    pub fn add_null(&mut self) -> &mut Self {
+        self.columns.push(None);
         self
     }
 
@@ -98,10 +111,10 @@ impl DataRow {
         dr
     }
 
-    /// Get data for column at index.
+    /// Get data for column at index. NULL columns have no data.
     #[inline]
     pub fn column(&self, index: usize) -> Option<Bytes> {
-        self.columns.get(index).cloned()
+        self.columns.get(index).cloned().flatten()
     }
 
     /// Get integer at index with text/binary encoding.
@@ -132,13 +145,11 @@ impl DataRow {
         index: usize,
         rd: &'a RowDescription,
     ) -> Result<Option<Column<'a>>, Error> {
-        if let Some(field) = rd.field(index) {
-            if let Some(data) = self.column(index) {
-                return Ok(Some(Column {
-                    name: field.name.as_str(),
-                    value: Datum::new(&data, field.data_type(), field.format())?,
-                }));
-            }
+        if let (Some(field), Some(data)) = (rd.field(index), self.columns.get(index)) {
+            return Ok(Some(Column {
+                name: field.name.as_str(),
+                value: Self::datum(data, field)?,
+            }));
         }
 
         Ok(None)
@@ -148,17 +159,22 @@ impl DataRow {
     pub fn into_row<'a>(&self, rd: &'a RowDescription) -> Result<Vec<Column<'a>>, Error> {
         let mut row = vec![];
 
-        for (index, field) in rd.fields.iter().enumerate() {
-            if let Some(data) = self.column(index) {
-                row.push(Column {
-                    name: field.name.as_str(),
-                    value: Datum::new(&data, field.data_type(), field.format())?,
-                });
-            }
+        for (field, data) in rd.fields.iter().zip(self.columns.iter()) {
+            row.push(Column {
+                name: field.name.as_str(),
+                value: Self::datum(data, field)?,
+            });
         }
 
         Ok(row)
     }
+
+    fn datum(data: &Option<Bytes>, field: &Field) -> Result<Datum, Error> {
+        match data {
+            Some(data) => Datum::new(data, field.data_type(), field.format()),
+            None => Ok(Datum::Null),
+        }
+    }
 }
 
 /// Column with data type mapped to a Rust type.
@@ -180,14 +196,14 @@ impl FromBytes for DataRow {
                 let mut column = BytesMut::new();
 
                 if len < 0 {
-                    return column.freeze();
+                    return None;
                 }
 
                 for _ in 0..len {
                     column.put_u8(bytes.get_u8());
                 }
 
-                column.freeze()
+                Some(column.freeze())
             })
             .collect();
 
@@ -201,8 +217,13 @@ impl ToBytes for DataRow {
         payload.put_i16(self.columns.len() as i16);
 
         for column in &self.columns {
-            payload.put_i32(column.len() as i32);
-            payload.put(&column[..]);
+            match column {
+                Some(column) => {
+                    payload.put_i32(column.len() as i32);
+                    payload.put(&column[..]);
+                }
+                None => payload.put_i32(-1),
+            }
         }
 
         Ok(payload.freeze())
@@ -6,7 +6,10 @@ use bytes::{Buf, Bytes};
 impl FromDataType for i64 {
     fn decode(mut bytes: &[u8], encoding: Format) -> Result<Self, Error> {
         match encoding {
-            Format::Binary => Ok(bytes.get_i64()),
+            Format::Binary => match bytes.len() {
+                8 => Ok(bytes.get_i64()),
+                n => Err(Error::WrongSizeBinary(n)),
+            },
 
             Format::Text => {
                 let s = String::decode(bytes, Format::Text)?;
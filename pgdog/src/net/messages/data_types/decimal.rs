@@ -0,0 +1,270 @@
+use std::ops::Deref;
+use std::str::FromStr;
+
+use bigdecimal::num_bigint::{BigInt, Sign};
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::*;
+
+/// Base of the digits in the binary encoding.
+const NBASE: i64 = 10_000;
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+/// Significant digits in the result of a division.
+const NUMERIC_MIN_SIG_DIGITS: i64 = 16;
+const NUMERIC_MAX_DISPLAY_SCALE: i64 = 1000;
+
+/// NUMERIC, with exact arithmetic.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal {
+    data: BigDecimal,
+}
+
+impl Deref for Decimal {
+    type Target = BigDecimal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl From<BigDecimal> for Decimal {
+    fn from(data: BigDecimal) -> Self {
+        Self { data }
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self {
+            data: BigDecimal::from(value),
+        }
+    }
+}
+
+impl Decimal {
+    /// Approximate value.
+    pub fn to_f64(&self) -> Option<f64> {
+        self.data.to_f64()
+    }
+
+    /// Divide, rounding the result to the same number of fractional digits
+    /// as PostgreSQL does. Returns `None` when dividing by zero.
+    pub fn div(&self, rhs: &Decimal) -> Option<Self> {
+        if rhs.data.is_zero() {
+            return None;
+        }
+
+        let scale = Self::div_scale(&self.data, &rhs.data);
+        let (mut numerator, lhs_scale) = self.data.as_bigint_and_exponent();
+        let (mut denominator, rhs_scale) = rhs.data.as_bigint_and_exponent();
+
+        // numerator / denominator * 10^shift, as an integer.
+        let shift = scale - lhs_scale + rhs_scale;
+        if shift >= 0 {
+            numerator *= BigInt::from(10).pow(shift as u32);
+        } else {
+            denominator *= BigInt::from(10).pow(-shift as u32);
+        }
+
+        // Round half away from zero.
+        let mut quotient = &numerator / &denominator;
+        let remainder = &numerator - &quotient * &denominator;
+        if remainder.abs() * 2 >= denominator.abs() {
+            if numerator.is_negative() == denominator.is_negative() {
+                quotient += 1;
+            } else {
+                quotient -= 1;
+            }
+        }
+
+        Some(Self {
+            data: BigDecimal::new(quotient, scale),
+        })
+    }
+
+    /// Number of fractional digits in the result of a division, see `select_div_scale`
+    /// in PostgreSQL.
+    fn div_scale(lhs: &BigDecimal, rhs: &BigDecimal) -> i64 {
+        let (lhs_weight, lhs_digit) = Self::weight(lhs);
+        let (rhs_weight, rhs_digit) = Self::weight(rhs);
+
+        let mut weight = lhs_weight - rhs_weight;
+        if lhs_digit <= rhs_digit {
+            weight -= 1;
+        }
+
+        (NUMERIC_MIN_SIG_DIGITS - weight * 4)
+            .max(lhs.fractional_digit_count())
+            .max(rhs.fractional_digit_count())
+            .clamp(0, NUMERIC_MAX_DISPLAY_SCALE)
+    }
+
+    /// Weight and value of the first base 10000 digit.
+    fn weight(value: &BigDecimal) -> (i64, i64) {
+        if value.is_zero() {
+            return (0, 0);
+        }
+
+        let (digits, scale) = value.abs().into_bigint_and_exponent();
+        // Position of the most significant decimal digit.
+        let exponent = value.digits() as i64 - 1 - scale;
+        let weight = exponent.div_euclid(4);
+
+        let shift = -scale - weight * 4;
+        let first = if shift >= 0 {
+            digits * BigInt::from(10).pow(shift as u32)
+        } else {
+            digits / BigInt::from(10).pow(-shift as u32)
+        };
+
+        (weight, first.to_i64().unwrap_or_default())
+    }
+}
+
+impl FromDataType for Decimal {
+    fn decode(mut bytes: &[u8], encoding: Format) -> Result<Self, Error> {
+        match encoding {
+            Format::Text => {
+                let s = String::decode(bytes, encoding)?;
+                let data = BigDecimal::from_str(s.trim()).map_err(|_| Error::NotNumeric)?;
+                Ok(Self { data })
+            }
+
+            // ndigits, weight, sign, dscale, followed by base 10000 digits.
+            Format::Binary => {
+                if bytes.len() < 8 {
+                    return Err(Error::WrongSizeBinary(bytes.len()));
+                }
+                let ndigits = bytes.get_i16() as i64;
+                let weight = bytes.get_i16() as i64;
+                let sign = bytes.get_u16();
+                let dscale = bytes.get_u16() as i64;
+                if ndigits < 0 || bytes.len() != ndigits as usize * 2 {
+                    return Err(Error::WrongSizeBinary(bytes.len()));
+                }
+
+                let mut digits = BigInt::zero();
+                for _ in 0..ndigits {
+                    digits = digits * NBASE + bytes.get_i16();
+                }
+                let digits = match sign {
+                    NUMERIC_POS => digits,
+                    NUMERIC_NEG => -digits,
+                    // NaN and infinity.
+                    _ => return Err(Error::NotNumeric),
+                };
+
+                // Value of the last digit is 10000^(weight - ndigits + 1).
+                let scale = -4 * (weight - ndigits + 1);
+                Ok(Self {
+                    data: BigDecimal::new(digits, scale).with_scale(dscale),
+                })
+            }
+        }
+    }
+
+    fn encode(&self, encoding: Format) -> Result<Bytes, Error> {
+        match encoding {
+            Format::Text => Ok(Bytes::copy_from_slice(
+                self.data.to_plain_string().as_bytes(),
+            )),
+
+            Format::Binary => {
+                let dscale = self.data.fractional_digit_count().max(0);
+                let (digits, scale) = self.data.with_scale(dscale).into_bigint_and_scale();
+                let (sign, mut digits) = digits.into_parts();
+
+                // Align the decimal point with a base 10000 digit.
+                let pad = (4 - scale % 4) % 4;
+                digits *= 10_u32.pow(pad as u32);
+
+                // Least significant first.
+                let mut nbase_digits = vec![];
+                while !digits.is_zero() {
+                    let digit = (&digits % NBASE as u32).to_i16().unwrap_or_default();
+                    nbase_digits.push(digit);
+                    digits /= NBASE as u32;
+                }
+                let mut weight = nbase_digits.len() as i64 - 1 - (scale + pad) / 4;
+
+                let trailing_zeros = nbase_digits.iter().take_while(|d| **d == 0).count();
+                nbase_digits.drain(..trailing_zeros);
+                nbase_digits.reverse();
+                if nbase_digits.is_empty() {
+                    weight = 0;
+                }
+
+                let mut payload = BytesMut::new();
+                payload.put_i16(nbase_digits.len() as i16);
+                payload.put_i16(weight as i16);
+                payload.put_u16(if sign == Sign::Minus {
+                    NUMERIC_NEG
+                } else {
+                    NUMERIC_POS
+                });
+                payload.put_u16(dscale as u16);
+                for digit in nbase_digits {
+                    payload.put_i16(digit);
+                }
+
+                Ok(payload.freeze())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decimal() {
+        for value in [
+            "0",
+            "1",
+            "-1",
+            "10000",
+            "12345678901234567890.123456789",
+            "-0.00012",
+            "0.5",
+            "100.10",
+        ] {
+            let decimal = Decimal::decode(value.as_bytes(), Format::Text).unwrap();
+            let text = decimal.encode(Format::Text).unwrap();
+            assert_eq!(text, value.as_bytes());
+
+            let binary = decimal.encode(Format::Binary).unwrap();
+            let decoded = Decimal::decode(&binary, Format::Binary).unwrap();
+            assert_eq!(decoded.encode(Format::Text).unwrap(), value.as_bytes());
+        }
+
+        // SELECT 1234.5::numeric, binary encoding.
+        let binary = [0, 2, 0, 0, 0, 0, 0, 1, 0x04, 0xd2, 0x13, 0x88];
+        let decimal = Decimal::decode(&binary, Format::Binary).unwrap();
+        assert_eq!(decimal.encode(Format::Text).unwrap(), "1234.5".as_bytes());
+        assert_eq!(decimal.encode(Format::Binary).unwrap(), binary.as_slice());
+    }
+
+    #[test]
+    fn test_decimal_div() {
+        // Results from PostgreSQL.
+        for (lhs, rhs, result) in [
+            ("3", "2", "1.5000000000000000"),
+            ("1", "3", "0.33333333333333333333"),
+            ("-2", "3", "-0.66666666666666666667"),
+            ("0.3", "2", "0.15000000000000000000"),
+            ("18446744073709551614", "3", "6148914691236517205"),
+            ("12345.678", "7", "1763.6682857142857143"),
+            ("0", "5", "0.00000000000000000000"),
+        ] {
+            let lhs = Decimal::decode(lhs.as_bytes(), Format::Text).unwrap();
+            let rhs = Decimal::decode(rhs.as_bytes(), Format::Text).unwrap();
+            let quotient = lhs.div(&rhs).unwrap();
+            assert_eq!(quotient.encode(Format::Text).unwrap(), result.as_bytes());
+        }
+
+        assert!(Decimal::from(1).div(&Decimal::from(0)).is_none());
+    }
+}
@@ -6,7 +6,33 @@ use bytes::{Buf, Bytes};
 impl FromDataType for i32 {
     fn decode(mut bytes: &[u8], encoding: Format) -> Result<Self, Error> {
         match encoding {
-            Format::Binary => Ok(bytes.get_i32()),
+            Format::Binary => match bytes.len() {
+                4 => Ok(bytes.get_i32()),
+                n => Err(Error::WrongSizeBinary(n)),
+            },
+
+            Format::Text => {
+                let s = String::decode(bytes, Format::Text)?;
+                Ok(s.parse()?)
+            }
+        }
+    }
+
+    fn encode(&self, encoding: Format) -> Result<Bytes, Error> {
+        match encoding {
+            Format::Text => Ok(Bytes::copy_from_slice(self.to_string().as_bytes())),
+            Format::Binary => Ok(Bytes::copy_from_slice(&self.to_be_bytes())),
+        }
+    }
+}
+
+impl FromDataType for i16 {
+    fn decode(mut bytes: &[u8], encoding: Format) -> Result<Self, Error> {
+        match encoding {
+            Format::Binary => match bytes.len() {
+                2 => Ok(bytes.get_i16()),
+                n => Err(Error::WrongSizeBinary(n)),
+            },
 
             Format::Text => {
                 let s = String::decode(bytes, Format::Text)?;
@@ -3,6 +3,7 @@ use ::uuid::Uuid;
 use bytes::Bytes;
 
 pub mod bigint;
+pub mod decimal;
 pub mod integer;
 pub mod interval;
 pub mod numeric;
@@ -11,6 +12,7 @@ pub mod timestamp;
 pub mod timestamptz;
 pub mod uuid;
 
+pub use decimal::Decimal;
 pub use interval::Interval;
 pub use numeric::Numeric;
 pub use timestamp::Timestamp;
@@ -39,32 +41,80 @@ pub enum Datum {
     TimestampTz(TimestampTz),
     /// UUID.
     Uuid(Uuid),
-    /// NUMERIC, REAL, DOUBLE PRECISION.
+    /// REAL, DOUBLE PRECISION.
     Numeric(Numeric),
+    /// NUMERIC.
+    Decimal(Decimal),
     /// NULL.
     Null,
 }
 
 impl Datum {
+    /// Decode a non-NULL value. Types we don't decode are returned as NULL.
     pub fn new(bytes: &[u8], data_type: DataType, encoding: Format) -> Result<Self, Error> {
-        if bytes.is_empty() {
-            return Ok(Datum::Null);
-        }
-
         match data_type {
             DataType::Bigint => Ok(Datum::Bigint(i64::decode(bytes, encoding)?)),
             DataType::Integer => Ok(Datum::Integer(i32::decode(bytes, encoding)?)),
+            DataType::SmallInt => Ok(Datum::SmallInt(i16::decode(bytes, encoding)?)),
             DataType::Text => Ok(Datum::Text(String::decode(bytes, encoding)?)),
             DataType::Interval => Ok(Datum::Interval(Interval::decode(bytes, encoding)?)),
-            DataType::Numeric | DataType::DoublePrecision | DataType::Real => {
+            DataType::DoublePrecision | DataType::Real => {
                 Ok(Datum::Numeric(Numeric::decode(bytes, encoding)?))
             }
+            DataType::Numeric => Ok(Datum::Decimal(Decimal::decode(bytes, encoding)?)),
             DataType::Uuid => Ok(Datum::Uuid(Uuid::decode(bytes, encoding)?)),
             DataType::Timestamp => Ok(Datum::Timestamp(Timestamp::decode(bytes, encoding)?)),
             DataType::TimestampTz => Ok(Datum::TimestampTz(TimestampTz::decode(bytes, encoding)?)),
             _ => Ok(Datum::Null),
         }
     }
+
+    /// Encode the value for sending it to the client. NULL has no data.
+    pub fn encode(&self, encoding: Format) -> Result<Option<Bytes>, Error> {
+        let data = match self {
+            Datum::Bigint(value) => value.encode(encoding),
+            Datum::Integer(value) => value.encode(encoding),
+            Datum::SmallInt(value) => value.encode(encoding),
+            Datum::Interval(value) => value.encode(encoding),
+            Datum::Text(value) => value.encode(encoding),
+            Datum::Timestamp(value) => value.encode(encoding),
+            Datum::TimestampTz(value) => value.encode(encoding),
+            Datum::Uuid(value) => value.encode(encoding),
+            Datum::Numeric(value) => value.encode(encoding),
+            Datum::Decimal(value) => value.encode(encoding),
+            Datum::Null => return Ok(None),
+        };
+
+        data.map(Some)
+    }
+
+    /// The value is NULL.
+    pub fn is_null(&self) -> bool {
+        matches!(self, Datum::Null)
+    }
+
+    /// Add two numbers together, e.g. partial sums from multiple shards.
+    /// NULLs are ignored, like they are by `SUM`.
+    pub fn sum(self, rhs: Self) -> Result<Self, Error> {
+        match (self, rhs) {
+            (Datum::Bigint(a), Datum::Bigint(b)) => a
+                .checked_add(b)
+                .map(Datum::Bigint)
+                .ok_or(Error::OutOfRange("bigint")),
+            (Datum::Integer(a), Datum::Integer(b)) => a
+                .checked_add(b)
+                .map(Datum::Integer)
+                .ok_or(Error::OutOfRange("integer")),
+            (Datum::SmallInt(a), Datum::SmallInt(b)) => a
+                .checked_add(b)
+                .map(Datum::SmallInt)
+                .ok_or(Error::OutOfRange("smallint")),
+            (Datum::Numeric(a), Datum::Numeric(b)) => Ok(Datum::Numeric(Numeric::from(*a + *b))),
+            (Datum::Decimal(a), Datum::Decimal(b)) => Ok(Datum::Decimal(Decimal::from(&*a + &*b))),
+            (Datum::Null, value) | (value, Datum::Null) => Ok(value),
+            _ => Err(Error::MismatchedDataTypes),
+        }
+    }
 }
 
 /// PostgreSQL data types.
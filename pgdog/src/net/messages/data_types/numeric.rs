@@ -35,7 +35,7 @@ impl DerefMut for Numeric {
 
 impl Ord for Numeric {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.partial_cmp(other) {
+        match self.data.partial_cmp(&other.data) {
             Some(ordering) => ordering,
             None => Ordering::Equal, // We don't expect Postgres to send us NaNs.
         }
@@ -44,6 +44,12 @@ impl Ord for Numeric {
 
 impl Eq for Numeric {}
 
+impl From<f64> for Numeric {
+    fn from(data: f64) -> Self {
+        Self { data }
+    }
+}
+
 impl FromDataType for Numeric {
     fn decode(mut bytes: &[u8], encoding: Format) -> Result<Self, Error> {
         match encoding {
@@ -69,3 +75,16 @@ impl FromDataType for Numeric {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_numeric_ord() {
+        let (a, b) = (Numeric::from(1.5), Numeric::from(2.0));
+        assert_eq!(a.cmp(&b), Ordering::Less);
+        assert_eq!(b.partial_cmp(&a), Some(Ordering::Greater));
+        assert_eq!(a.cmp(&a), Ordering::Equal);
+    }
+}
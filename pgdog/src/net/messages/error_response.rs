@@ -39,6 +39,16 @@ impl ErrorResponse {
         }
     }
 
+    /// Client must connect with TLS and present a certificate.
+    pub fn tls_required() -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "28000".into(),
+            message: "TLS connection with a client certificate is required".into(),
+            detail: None,
+        }
+    }
+
     /// Connection error.
     pub fn connection() -> ErrorResponse {
         ErrorResponse {
@@ -59,6 +69,46 @@ impl ErrorResponse {
         }
     }
 
+    /// Too many clients are connected.
+    pub fn too_many_connections(message: &str) -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "53300".into(),
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    /// Client is sending queries too fast.
+    pub fn rate_limit(message: &str) -> ErrorResponse {
+        ErrorResponse {
+            severity: "ERROR".into(),
+            code: "53400".into(),
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    /// Client was idle in transaction for too long.
+    pub fn idle_in_transaction_timeout() -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "25P03".into(),
+            message: "terminating connection due to idle-in-transaction timeout".into(),
+            detail: None,
+        }
+    }
+
+    /// Client was idle for too long.
+    pub fn client_idle_timeout() -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "57P05".into(),
+            message: "terminating connection due to idle-session timeout".into(),
+            detail: None,
+        }
+    }
+
     pub fn syntax(err: &str) -> ErrorResponse {
         Self {
             severity: "ERROR".into(),
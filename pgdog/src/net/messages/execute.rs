@@ -0,0 +1,41 @@
+//! Execute (F) message.
+use crate::net::c_string_buf;
+
+use super::code;
+use super::prelude::*;
+
+/// Execute (F) message.
+#[derive(Debug, Clone, Default)]
+pub struct Execute {
+    /// Portal name.
+    pub portal: String,
+    /// Maximum number of rows to return, zero for no limit.
+    pub max_rows: i32,
+}
+
+impl FromBytes for Execute {
+    fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        code!(bytes, 'E');
+        let _len = bytes.get_i32();
+        let portal = c_string_buf(&mut bytes);
+        let max_rows = bytes.get_i32();
+
+        Ok(Self { portal, max_rows })
+    }
+}
+
+impl ToBytes for Execute {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        let mut payload = Payload::named(self.code());
+        payload.put_string(&self.portal);
+        payload.put_i32(self.max_rows);
+
+        Ok(payload.freeze())
+    }
+}
+
+impl Protocol for Execute {
+    fn code(&self) -> char {
+        'E'
+    }
+}
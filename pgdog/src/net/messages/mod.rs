@@ -2,6 +2,7 @@
 pub mod auth;
 pub mod backend_key;
 pub mod bind;
+pub mod bind_complete;
 pub mod close;
 pub mod command_complete;
 pub mod copy_data;
@@ -9,9 +10,12 @@ pub mod data_row;
 pub mod data_types;
 pub mod describe;
 pub mod error_response;
+pub mod execute;
 pub mod flush;
 pub mod hello;
+pub mod no_data;
 pub mod notice_response;
+pub mod parameter_description;
 pub mod parameter_status;
 pub mod parse;
 pub mod parse_complete;
@@ -21,11 +25,13 @@ pub mod query;
 pub mod replication;
 pub mod rfq;
 pub mod row_description;
+pub mod sync;
 pub mod terminate;
 
 pub use auth::{Authentication, Password};
 pub use backend_key::BackendKeyData;
 pub use bind::{Bind, Format, Parameter, ParameterWithFormat};
+pub use bind_complete::BindComplete;
 pub use close::Close;
 pub use command_complete::CommandComplete;
 pub use copy_data::CopyData;
@@ -33,9 +39,12 @@ pub use data_row::{DataRow, ToDataRowColumn};
 pub use data_types::*;
 pub use describe::Describe;
 pub use error_response::ErrorResponse;
+pub use execute::Execute;
 pub use flush::Flush;
 pub use hello::Startup;
+pub use no_data::NoData;
 pub use notice_response::NoticeResponse;
+pub use parameter_description::ParameterDescription;
 pub use parameter_status::ParameterStatus;
 pub use parse::Parse;
 pub use parse_complete::ParseComplete;
@@ -43,6 +52,7 @@ pub use payload::Payload;
 pub use query::Query;
 pub use rfq::ReadyForQuery;
 pub use row_description::{Field, RowDescription};
+pub use sync::Sync;
 pub use terminate::Terminate;
 
 use crate::net::Error;
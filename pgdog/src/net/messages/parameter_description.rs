@@ -0,0 +1,36 @@
+//! ParameterDescription (B) message.
+use super::code;
+use super::prelude::*;
+
+/// ParameterDescription (B) message.
+#[derive(Debug, Clone, Default)]
+pub struct ParameterDescription {
+    /// Data type of each parameter.
+    pub data_types: Vec<i32>,
+}
+
+impl FromBytes for ParameterDescription {
+    fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        code!(bytes, 't');
+        let _len = bytes.get_i32();
+        let data_types = (0..bytes.get_i16()).map(|_| bytes.get_i32()).collect();
+        Ok(Self { data_types })
+    }
+}
+
+impl ToBytes for ParameterDescription {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        let mut payload = Payload::named(self.code());
+        payload.put_i16(self.data_types.len() as i16);
+        for data_type in &self.data_types {
+            payload.put_i32(*data_type);
+        }
+        Ok(payload.freeze())
+    }
+}
+
+impl Protocol for ParameterDescription {
+    fn code(&self) -> char {
+        't'
+    }
+}
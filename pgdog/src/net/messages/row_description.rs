@@ -91,6 +91,7 @@ impl Field {
             1114 => DataType::Timestamp,
             1184 => DataType::TimestampTz,
             1186 => DataType::Interval,
+            1700 => DataType::Numeric,
             2950 => DataType::Uuid,
             _ => DataType::Other(self.type_oid),
         }
@@ -17,6 +17,7 @@ use super::messages::{ErrorResponse, Message, Protocol, ReadyForQuery, Terminate
 /// A network socket.
 #[pin_project(project = StreamProjection)]
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Stream {
     Plain(#[pin] BufStream<TcpStream>),
     Tls(#[pin] BufStream<tokio_rustls::TlsStream<TcpStream>>),
@@ -92,6 +93,25 @@ impl Stream {
         }
     }
 
+    /// Is the stream encrypted.
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Self::Tls(_))
+    }
+
+    /// Common name (CN) of the certificate presented by the peer, if any.
+    pub fn peer_common_name(&self) -> Option<String> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Tls(stream) => stream
+                .get_ref()
+                .get_ref()
+                .1
+                .peer_certificates()?
+                .first()
+                .and_then(super::tls::common_name),
+        }
+    }
+
     /// Send data via the stream.
     ///
     /// # Performance
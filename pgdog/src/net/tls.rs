@@ -1,119 +1,286 @@
 //! TLS configuration.
 
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use once_cell::sync::OnceCell;
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::{
     self,
-    client::danger::{ServerCertVerified, ServerCertVerifier},
+    client::{
+        danger::{ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
     pki_types::pem::PemObject,
-    server::danger::ClientCertVerifier,
-    ClientConfig,
+    server::WebPkiClientVerifier,
+    CertificateError, ClientConfig, RootCertStore,
 };
 use tokio_rustls::{TlsAcceptor, TlsConnector};
-use tracing::info;
+use tracing::{info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
 
-use crate::config::config;
+use crate::config::{config, Config, General, TlsMode};
 
 use super::Error;
 
-static ACCEPTOR: OnceCell<Option<TlsAcceptor>> = OnceCell::new();
-static CONNECTOR: OnceCell<TlsConnector> = OnceCell::new();
+/// TLS connectors by mode and CA bundle.
+type Connectors = HashMap<(TlsMode, Option<PathBuf>), TlsConnector>;
 
-/// Get preloaded TLS acceptor.
-pub fn acceptor() -> Option<&'static TlsAcceptor> {
-    if let Some(Some(acceptor)) = ACCEPTOR.get() {
-        return Some(acceptor);
-    }
+static ACCEPTOR: Lazy<ArcSwap<Option<TlsAcceptor>>> = Lazy::new(|| ArcSwap::from_pointee(None));
+static CONNECTORS: Lazy<ArcSwap<Connectors>> = Lazy::new(|| ArcSwap::from_pointee(HashMap::new()));
 
-    None
+/// Get current TLS acceptor.
+///
+/// Certificates can be reloaded at any time, so get the acceptor
+/// for each new connection.
+pub fn acceptor() -> Option<TlsAcceptor> {
+    ACCEPTOR.load().as_ref().clone()
 }
 
 /// Create a new TLS acceptor from the cert and key.
 ///
-/// This is not atomic, so call it on startup only.
-pub fn load_acceptor(cert: &PathBuf, key: &PathBuf) -> Result<Option<TlsAcceptor>, Error> {
-    if let Some(acceptor) = ACCEPTOR.get() {
-        return Ok(acceptor.clone());
-    }
-
-    let pem = if let Ok(pem) = CertificateDer::from_pem_file(cert) {
-        pem
-    } else {
-        let _ = ACCEPTOR.set(None);
+/// Client certificates are verified if a CA bundle is configured.
+fn new_acceptor(general: &General) -> Result<Option<TlsAcceptor>, Error> {
+    let Some((cert, key)) = general.tls() else {
         return Ok(None);
     };
 
-    let key = if let Ok(key) = PrivateKeyDer::from_pem_file(key) {
-        key
+    let pem = CertificateDer::from_pem_file(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+
+    let config = rustls::ServerConfig::builder();
+    let config = if let Some(ref ca) = general.tls_client_ca_certificate {
+        let verifier = WebPkiClientVerifier::builder(roots(Some(ca))?.into());
+        let verifier = if general.tls_client_required {
+            verifier.build()
+        } else {
+            verifier.allow_unauthenticated().build()
+        }
+        .map_err(|err| Error::TlsVerifier(err.to_string()))?;
+
+        info!(
+            "🔑 TLS client certificates verified with \"{}\"",
+            ca.display()
+        );
+
+        config.with_client_cert_verifier(verifier)
     } else {
-        let _ = ACCEPTOR.set(None);
-        return Ok(None);
+        config.with_no_client_auth()
     };
-
-    let config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(vec![pem], key)?;
-
-    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let config = config.with_single_cert(vec![pem], key)?;
 
     info!("🔑 TLS on");
 
-    // A bit of a race, but it's not a big deal unless this is called
-    // with different certificate/secret key.
-    let _ = ACCEPTOR.set(Some(acceptor.clone()));
-
-    Ok(Some(acceptor))
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
 }
 
-/// Create new TLS connector.
-pub fn connector() -> Result<TlsConnector, Error> {
-    if let Some(connector) = CONNECTOR.get() {
+/// Get TLS connector for the TLS mode, verifying server certificates
+/// with the CA bundle, or the system's root certificates if none is provided.
+pub fn connector(mode: TlsMode, ca: Option<&PathBuf>) -> Result<TlsConnector, Error> {
+    let key = (mode, ca.cloned());
+    if let Some(connector) = CONNECTORS.load().get(&key) {
         return Ok(connector.clone());
     }
 
-    let mut roots = rustls::RootCertStore::empty();
-    for cert in rustls_native_certs::load_native_certs().expect("load native certs") {
-        roots.add(cert)?;
-    }
+    let connector = new_connector(mode, ca)?;
 
-    let verifier = rustls::server::WebPkiClientVerifier::builder(roots.clone().into())
-        .build()
-        .unwrap();
-    let verifier = CertificateVerifyer { verifier };
+    CONNECTORS.rcu(|connectors| {
+        let mut connectors = HashMap::clone(connectors);
+        connectors.insert(key.clone(), connector.clone());
+        connectors
+    });
 
-    let mut config = ClientConfig::builder()
-        .with_root_certificates(roots)
-        .with_no_client_auth();
+    Ok(connector)
+}
 
-    config
-        .dangerous()
-        .set_certificate_verifier(Arc::new(verifier));
+/// Create new TLS connector.
+fn new_connector(mode: TlsMode, ca: Option<&PathBuf>) -> Result<TlsConnector, Error> {
+    let config = ClientConfig::builder();
+
+    let config = match mode {
+        TlsMode::VerifyFull => config
+            .with_root_certificates(roots(ca)?)
+            .with_no_client_auth(),
+
+        TlsMode::VerifyCa => {
+            let verifier = WebPkiServerVerifier::builder(roots(ca)?.into())
+                .build()
+                .map_err(|err| Error::TlsVerifier(err.to_string()))?;
+            config
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(VerifyCa { verifier }))
+                .with_no_client_auth()
+        }
+
+        // Server certificates aren't verified, so root certificates aren't needed.
+        TlsMode::Disable | TlsMode::Prefer | TlsMode::Require => {
+            let verifier = CertificateVerifyer {
+                provider: config.crypto_provider().clone(),
+            };
+
+            config
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+                .with_no_client_auth()
+        }
+    };
 
-    let connector = TlsConnector::from(Arc::new(config));
+    Ok(TlsConnector::from(Arc::new(config)))
+}
 
-    let _ = CONNECTOR.set(connector.clone());
+/// Create connectors for all configured databases.
+fn new_connectors(config: &Config) -> Result<Connectors, Error> {
+    let mut connectors = HashMap::new();
+    connectors.insert(
+        (TlsMode::default(), None),
+        new_connector(TlsMode::default(), None)?,
+    );
+
+    for database in &config.databases {
+        let key = (
+            database.server_tls_mode,
+            database.server_tls_ca_certificate.clone(),
+        );
+        if database.server_tls_mode != TlsMode::Disable && !connectors.contains_key(&key) {
+            let connector = new_connector(key.0, key.1.as_ref())?;
+            connectors.insert(key, connector);
+        }
+    }
 
-    Ok(connector)
+    Ok(connectors)
+}
+
+/// Load CA certificates from the file, or the system's root certificates.
+fn roots(ca: Option<&PathBuf>) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+
+    if let Some(ca) = ca {
+        for cert in CertificateDer::pem_file_iter(ca)? {
+            roots.add(cert?)?;
+        }
+    } else {
+        let native = rustls_native_certs::load_native_certs();
+        if native.certs.is_empty() && !native.errors.is_empty() {
+            let errors = native
+                .errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>();
+            return Err(Error::NativeCerts(errors.join(", ")));
+        }
+
+        for err in native.errors {
+            warn!("system root certificate not loaded: {}", err);
+        }
+
+        // Skip certificates we can't parse, like the system's own TLS libraries do.
+        let (_, ignored) = roots.add_parsable_certificates(native.certs);
+        if ignored > 0 {
+            warn!("{} system root certificates ignored", ignored);
+        }
+    }
+
+    Ok(roots)
 }
 
 /// Preload TLS at startup.
+///
+/// TLS is disabled for clients if the certificate can't be loaded.
 pub fn load() -> Result<(), Error> {
     let config = config();
 
-    if let Some((cert, key)) = config.config.general.tls() {
-        load_acceptor(cert, key)?;
-    }
+    let acceptor = new_acceptor(&config.config.general).unwrap_or_else(|err| {
+        warn!("TLS certificate not loaded: {}", err);
+        None
+    });
+    let connectors = new_connectors(&config.config)?;
+
+    ACCEPTOR.store(Arc::new(acceptor));
+    CONNECTORS.store(Arc::new(connectors));
 
-    connector()?;
+    Ok(())
+}
+
+/// Reload certificates from disk.
+///
+/// New connections will use them, existing connections are not affected.
+/// Current certificates are kept if the new ones can't be loaded.
+pub fn reload(config: &Config) -> Result<(), Error> {
+    let acceptor = new_acceptor(&config.general)?;
+    let connectors = new_connectors(config)?;
+
+    ACCEPTOR.store(Arc::new(acceptor));
+    CONNECTORS.store(Arc::new(connectors));
+
+    info!("TLS certificates reloaded");
 
     Ok(())
 }
 
+/// Get the common name (CN) from the subject of a DER-encoded certificate.
+pub fn common_name(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+    let common_name = cert.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(String::from)
+}
+
+/// Verify the server certificate chain, but not the host name.
+#[derive(Debug)]
+struct VerifyCa {
+    verifier: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for VerifyCa {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+/// Accept any server certificate, checking only that the server has its private key.
 #[derive(Debug)]
 struct CertificateVerifyer {
-    verifier: Arc<dyn ClientCertVerifier>,
+    provider: Arc<CryptoProvider>,
 }
 
 impl ServerCertVerifier for CertificateVerifyer {
@@ -136,7 +303,12 @@ impl ServerCertVerifier for CertificateVerifyer {
         cert: &CertificateDer<'_>,
         dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        self.verifier.verify_tls12_signature(message, cert, dss)
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
     }
 
     fn verify_tls13_signature(
@@ -145,10 +317,53 @@ impl ServerCertVerifier for CertificateVerifyer {
         cert: &CertificateDer<'_>,
         dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        self.verifier.verify_tls13_signature(message, cert, dss)
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
     }
 
     fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        self.verifier.supported_verify_schemes()
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_common_name() {
+        // Subject: C=US, O=PgDog, CN=pgdog
+        let pem = "-----BEGIN CERTIFICATE-----
+MIIBijCCAS+gAwIBAgIUIa9zcIdlOIYx+uxWUyGo7WMTsXgwCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNcGdkb2cgdGVzdCBDQTAeFw0yNjEwMTcwMjM1MzFaFw0zNjEw
+MTQwMjM1MzFaMC0xCzAJBgNVBAYTAlVTMQ4wDAYDVQQKDAVQZ0RvZzEOMAwGA1UE
+AwwFcGdkb2cwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASnEUgTC44gkkBBAnRY
+yqDjVg+vBaDXcF/I6qJv4WxVvt0e+OoGt6UMqBag8GsmksznrK3Bf/YId2cAhtWy
+Zn+1o0IwQDAdBgNVHQ4EFgQU9s1GrSTbaohnPuqhSyjxW1BZmXEwHwYDVR0jBBgw
+FoAU7gt4YNWcKC38oKlIwn3mHmq6m1IwCgYIKoZIzj0EAwIDSQAwRgIhAKsGxQae
+XFD45pGWS+q9dRXx8l++psPK0XsuEn5KibzdAiEApPe9jCvZraGD2KSJc588Ccze
+wNlZw3HK3l6/ztGc+ac=
+-----END CERTIFICATE-----";
+        let cert = CertificateDer::from_pem_slice(pem.as_bytes()).unwrap();
+        assert_eq!(common_name(&cert), Some("pgdog".into()));
+
+        assert_eq!(common_name(&CertificateDer::from(vec![0x30, 0x82])), None);
+
+        // Used as the CA bundle.
+        let ca = std::env::temp_dir().join(format!("pgdog_ca_{}.pem", std::process::id()));
+        std::fs::write(&ca, pem).unwrap();
+        for mode in [TlsMode::Prefer, TlsMode::Require, TlsMode::VerifyCa] {
+            assert!(new_connector(mode, Some(&ca)).is_ok());
+        }
+        std::fs::remove_file(&ca).unwrap();
+
+        // Root certificates aren't needed without verification.
+        assert!(new_connector(TlsMode::Require, None).is_ok());
     }
 }
@@ -0,0 +1,178 @@
+//! Latency histograms.
+
+use std::ops::Add;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the buckets, in seconds.
+pub const BUCKETS: [f64; 16] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    5.0, 10.0,
+];
+
+/// Histogram with fixed buckets.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Histogram {
+    /// Observations in each bucket, the last one being `+Inf`.
+    buckets: [u64; BUCKETS.len() + 1],
+    /// Sum of all observations.
+    sum: Duration,
+}
+
+impl Histogram {
+    /// Record an observation.
+    pub fn observe(&mut self, value: Duration) {
+        self.buckets[bucket(value)] += 1;
+        self.sum += value;
+    }
+
+    /// Number of observations.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Sum of all observations.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Cumulative counts for each bucket in [`BUCKETS`], followed by `+Inf`.
+    pub fn cumulative(&self) -> impl Iterator<Item = u64> + '_ {
+        self.buckets.iter().scan(0, |total, count| {
+            *total += count;
+            Some(*total)
+        })
+    }
+
+    /// Estimate the quantile (0.0 to 1.0), interpolating
+    /// within the bucket it falls in.
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+
+        let rank = quantile.clamp(0.0, 1.0) * count as f64;
+        let mut total = 0;
+
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let previous = total;
+            total += bucket;
+
+            if *bucket > 0 && total as f64 >= rank {
+                // Observations above the last bucket are reported as its bound.
+                let Some(upper) = BUCKETS.get(i) else {
+                    break;
+                };
+                let lower = if i == 0 { 0.0 } else { BUCKETS[i - 1] };
+                let position = (rank - previous as f64) / *bucket as f64;
+                return Duration::from_secs_f64(lower + (upper - lower) * position);
+            }
+        }
+
+        Duration::from_secs_f64(BUCKETS[BUCKETS.len() - 1])
+    }
+}
+
+impl Add for Histogram {
+    type Output = Histogram;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        for (bucket, count) in self.buckets.iter_mut().zip(rhs.buckets) {
+            *bucket = bucket.saturating_add(count);
+        }
+        self.sum += rhs.sum;
+        self
+    }
+}
+
+/// Histogram shared between threads, updated without locking.
+#[derive(Debug, Default)]
+pub struct AtomicHistogram {
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    /// Sum of all observations, in microseconds.
+    sum: AtomicU64,
+}
+
+impl AtomicHistogram {
+    /// Record an observation.
+    pub fn observe(&self, value: Duration) {
+        self.buckets[bucket(value)].fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Get current values.
+    pub fn snapshot(&self) -> Histogram {
+        let mut histogram = Histogram::default();
+        for (bucket, count) in histogram.buckets.iter_mut().zip(self.buckets.iter()) {
+            *bucket = count.load(Ordering::Relaxed);
+        }
+        histogram.sum = Duration::from_micros(self.sum.load(Ordering::Relaxed));
+        histogram
+    }
+}
+
+/// Find the bucket for a value.
+fn bucket(value: Duration) -> usize {
+    let seconds = value.as_secs_f64();
+    BUCKETS
+        .iter()
+        .position(|bound| seconds <= *bound)
+        .unwrap_or(BUCKETS.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = AtomicHistogram::default();
+        histogram.observe(Duration::from_micros(50));
+        histogram.observe(Duration::from_millis(1));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(60));
+
+        let snapshot = histogram.snapshot();
+        let cumulative = snapshot.cumulative().collect::<Vec<_>>();
+        assert_eq!(snapshot.count(), 4);
+        assert_eq!(cumulative[0], 1); // <= 100us
+        assert_eq!(cumulative[3], 2); // <= 1ms
+        assert_eq!(cumulative[4], 2); // <= 2.5ms
+        assert_eq!(cumulative[5], 3); // <= 5ms
+        assert_eq!(cumulative[BUCKETS.len() - 1], 3); // <= 10s
+        assert_eq!(cumulative[BUCKETS.len()], 4); // +Inf
+        assert_eq!(
+            snapshot.sum(),
+            Duration::from_micros(50 + 1_000 + 3_000 + 60_000_000)
+        );
+
+        let total = snapshot + snapshot;
+        assert_eq!(total.count(), 8);
+        assert_eq!(total.sum(), snapshot.sum() * 2);
+    }
+
+    #[test]
+    fn test_quantile() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.99), Duration::ZERO);
+
+        // 90 fast queries, 10 slow ones.
+        for _ in 0..90 {
+            histogram.observe(Duration::from_micros(800));
+        }
+        for _ in 0..10 {
+            histogram.observe(Duration::from_millis(40));
+        }
+
+        let p50 = histogram.quantile(0.5);
+        assert!(p50 > Duration::from_micros(500) && p50 <= Duration::from_millis(1));
+        let p99 = histogram.quantile(0.99);
+        assert!(p99 > Duration::from_millis(25) && p99 <= Duration::from_millis(50));
+        assert_eq!(histogram.quantile(1.0), Duration::from_millis(50));
+
+        histogram.observe(Duration::from_secs(60));
+        assert_eq!(histogram.quantile(1.0), Duration::from_secs(10));
+    }
+}
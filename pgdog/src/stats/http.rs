@@ -0,0 +1,139 @@
+//! HTTP endpoint serving metrics in OpenMetrics format.
+
+use std::io::Result;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::spawn;
+use tokio::time::timeout;
+use tracing::{debug, error, info};
+
+use super::open_metrics::{render, CONTENT_TYPE};
+
+/// Maximum size of the request head.
+const MAX_REQUEST: usize = 8192;
+/// Time the client has to send the whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics HTTP server.
+pub struct Http {
+    listener: TcpListener,
+}
+
+impl Http {
+    /// Bind to the address.
+    pub async fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("openmetrics endpoint running on http://{}/metrics", addr);
+        Ok(Self { listener })
+    }
+
+    /// Serve requests until the process exits.
+    pub async fn serve(self) {
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    error!("openmetrics accept error: {}", err);
+                    continue;
+                }
+            };
+
+            spawn(async move {
+                if let Err(err) = Self::handle(stream).await {
+                    debug!("openmetrics request failed: {} [{}]", err, peer);
+                }
+            });
+        }
+    }
+
+    /// Read one request and send the response.
+    async fn handle(mut stream: TcpStream) -> Result<()> {
+        let Ok(request) = timeout(REQUEST_TIMEOUT, Self::read(&mut stream)).await else {
+            return Ok(());
+        };
+        let Some(request) = request? else {
+            return Ok(());
+        };
+
+        let request = String::from_utf8_lossy(&request);
+        let request_line = request.lines().next().unwrap_or_default();
+        stream.write_all(&response(request_line)).await?;
+        stream.shutdown().await?;
+
+        Ok(())
+    }
+
+    /// Read the request head. Returns `None` if the client disconnected.
+    async fn read(stream: &mut TcpStream) -> Result<Option<Vec<u8>>> {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            request.extend_from_slice(&buf[..read]);
+            if request.len() > MAX_REQUEST {
+                break;
+            }
+        }
+
+        Ok(Some(request))
+    }
+}
+
+/// Build the response to a request.
+fn response(request_line: &str) -> Vec<u8> {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET" | "HEAD", "/metrics") => ("200 OK", CONTENT_TYPE, render()),
+        ("GET" | "HEAD", _) => ("404 Not Found", "text/plain", "not found\n".into()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".into(),
+        ),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+
+    response.into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_response() {
+        let ok = String::from_utf8(response("GET /metrics HTTP/1.1")).unwrap();
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(ok.contains(CONTENT_TYPE));
+        assert!(ok.ends_with("# EOF\n"));
+
+        let head = String::from_utf8(response("HEAD /metrics?x=1 HTTP/1.1")).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+
+        let missing = String::from_utf8(response("GET / HTTP/1.1")).unwrap();
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let post = String::from_utf8(response("POST /metrics HTTP/1.1")).unwrap();
+        assert!(post.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
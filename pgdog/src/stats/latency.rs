@@ -0,0 +1,42 @@
+//! Query, transaction and wait time histograms for each user/database pair.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::backend::databases::{ToUser, User};
+
+use super::AtomicHistogram;
+
+static LATENCY: Lazy<Mutex<HashMap<User, Arc<Latency>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Client latency histograms.
+#[derive(Debug, Default)]
+pub struct Latency {
+    /// Time to execute a query.
+    pub query: AtomicHistogram,
+    /// Time to execute a transaction.
+    pub transaction: AtomicHistogram,
+    /// Time spent waiting for a server connection.
+    pub wait: AtomicHistogram,
+}
+
+/// Get histograms for the user/database, creating them if needed.
+///
+/// They are kept across configuration reloads.
+pub fn latency(user: impl ToUser) -> Arc<Latency> {
+    LATENCY.lock().entry(user.to_user()).or_default().clone()
+}
+
+/// Get histograms for all users and databases.
+pub fn all() -> Vec<(User, Arc<Latency>)> {
+    let mut all = LATENCY
+        .lock()
+        .iter()
+        .map(|(user, latency)| (user.clone(), latency.clone()))
+        .collect::<Vec<_>>();
+    all.sort_by(|a, b| (&a.0.database, &a.0.user).cmp(&(&b.0.database, &b.0.user)));
+    all
+}
@@ -1,5 +1,14 @@
 //! Statistics.
 
+pub mod histogram;
+pub mod http;
+pub mod latency;
+pub mod open_metrics;
+pub mod queries;
+
+pub use histogram::{AtomicHistogram, Histogram, BUCKETS};
+pub use latency::{latency, Latency};
+
 /// Connection statistics.
 #[derive(Debug, Default)]
 pub struct ConnStats {
@@ -0,0 +1,377 @@
+//! Metrics in OpenMetrics text format.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+
+use crate::{
+    backend::{databases::databases, pool, stats::stats},
+    config::Role,
+    frontend::{
+        client::limit::rejected_total, comms::comms, router::parser::Cache, PreparedStatements,
+    },
+    state::State,
+};
+
+use super::{latency, Histogram, BUCKETS};
+
+/// Content type of the rendered metrics.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// All client and server states, so every state is reported even if unused.
+const STATES: [State; 9] = [
+    State::Idle,
+    State::Active,
+    State::IdleInTransaction,
+    State::TransactionError,
+    State::Waiting,
+    State::Disconnected,
+    State::Error,
+    State::ParseComplete,
+    State::PreparedStatementError,
+];
+
+/// Get a pool metric from its state.
+type PoolValue = fn(&pool::State) -> String;
+
+/// Metric type.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Counter => write!(f, "counter"),
+            Kind::Gauge => write!(f, "gauge"),
+            Kind::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+/// Metrics being rendered.
+#[derive(Default)]
+struct Metrics {
+    out: String,
+}
+
+impl Metrics {
+    /// Start a metric family.
+    fn family(&mut self, name: &str, kind: Kind, help: &str) {
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+    }
+
+    /// Add a sample to the current family.
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                let _ = write!(self.out, "{}=\"{}\"", label, escape(value));
+            }
+            self.out.push('}');
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    /// Add histogram samples to the current family.
+    fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+        let bounds = BUCKETS
+            .iter()
+            .map(|bound| format!("{:?}", bound))
+            .chain(["+Inf".to_string()]);
+
+        for (le, count) in bounds.zip(histogram.cumulative()) {
+            let mut labels = labels.to_vec();
+            labels.push(("le", &le));
+            self.sample(&format!("{}_bucket", name), &labels, count);
+        }
+        self.sample(
+            &format!("{}_sum", name),
+            labels,
+            histogram.sum().as_secs_f64(),
+        );
+        self.sample(&format!("{}_count", name), labels, histogram.count());
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render all metrics.
+pub fn render() -> String {
+    let mut metrics = Metrics::default();
+    pools(&mut metrics);
+    connections(&mut metrics);
+    caches(&mut metrics);
+    histograms(&mut metrics);
+    metrics.finish()
+}
+
+/// Connection pool metrics.
+fn pools(metrics: &mut Metrics) {
+    let databases = databases();
+    let mut pools = vec![];
+    for (user, cluster) in databases.all() {
+        for (shard_num, shard) in cluster.shards().iter().enumerate() {
+            for (role, pool) in shard.pools_with_roles() {
+                let addr = pool.addr().clone();
+                let role = match role {
+                    Role::Primary => "primary",
+                    Role::Replica => "replica",
+                };
+                pools.push((
+                    user.clone(),
+                    shard_num.to_string(),
+                    role,
+                    addr,
+                    pool.state(),
+                ));
+            }
+        }
+    }
+    pools.sort_by(|a, b| (&a.0.database, &a.0.user).cmp(&(&b.0.database, &b.0.user)));
+
+    let gauges: [(&str, &str, PoolValue); 7] = [
+        ("idle_connections", "Idle server connections.", |s| {
+            s.idle.to_string()
+        }),
+        (
+            "active_connections",
+            "Server connections checked out by clients.",
+            |s| s.checked_out.to_string(),
+        ),
+        ("total_connections", "All server connections.", |s| {
+            s.total.to_string()
+        }),
+        (
+            "clients_waiting",
+            "Clients waiting for a connection.",
+            |s| s.waiting.to_string(),
+        ),
+        ("online", "Pool is online.", |s| {
+            (s.online as u8).to_string()
+        }),
+        ("paused", "Pool is paused.", |s| {
+            (s.paused as u8).to_string()
+        }),
+        ("banned", "Pool is banned.", |s| {
+            (s.banned as u8).to_string()
+        }),
+    ];
+
+    let counters: [(&str, &str, PoolValue); 8] = [
+        ("errors", "Server connection errors.", |s| {
+            s.errors.to_string()
+        }),
+        (
+            "out_of_sync",
+            "Server connections closed out of sync.",
+            |s| s.out_of_sync.to_string(),
+        ),
+        ("transactions", "Transactions executed.", |s| {
+            s.stats.counts.xact_count.to_string()
+        }),
+        ("queries", "Queries executed.", |s| {
+            s.stats.counts.query_count.to_string()
+        }),
+        (
+            "server_assignments",
+            "Server connections assigned to clients.",
+            |s| s.stats.counts.server_assignment_count.to_string(),
+        ),
+        ("received_bytes", "Bytes received from servers.", |s| {
+            s.stats.counts.received.to_string()
+        }),
+        ("sent_bytes", "Bytes sent to servers.", |s| {
+            s.stats.counts.sent.to_string()
+        }),
+        (
+            "wait_seconds",
+            "Time clients spent waiting for a connection.",
+            |s| (s.stats.counts.wait_time as f64 / 1_000_000.0).to_string(),
+        ),
+    ];
+
+    let families = gauges
+        .iter()
+        .map(|gauge| (Kind::Gauge, gauge))
+        .chain(counters.iter().map(|counter| (Kind::Counter, counter)));
+
+    for (kind, (name, help, value)) in families {
+        let name = format!("pgdog_pool_{}", name);
+        metrics.family(&name, kind, help);
+        let sample = match kind {
+            Kind::Counter => format!("{}_total", name),
+            _ => name,
+        };
+        for (user, shard, role, addr, state) in &pools {
+            let port = addr.port.to_string();
+            metrics.sample(
+                &sample,
+                &[
+                    ("database", &user.database),
+                    ("user", &user.user),
+                    ("shard", shard),
+                    ("role", role),
+                    ("host", &addr.host),
+                    ("port", &port),
+                    ("database_name", &addr.database_name),
+                ],
+                value(state),
+            );
+        }
+    }
+}
+
+/// Client and server connections by state.
+fn connections(metrics: &mut Metrics) {
+    let mut clients = HashMap::new();
+    for client in comms().clients().values() {
+        *clients.entry(client.stats.state).or_insert(0) += 1;
+    }
+
+    let mut servers = HashMap::new();
+    for server in stats().values() {
+        *servers.entry(server.stats.state).or_insert(0) += 1;
+    }
+
+    for (name, help, counts) in [
+        ("pgdog_clients", "Connected clients by state.", clients),
+        ("pgdog_servers", "Server connections by state.", servers),
+    ] {
+        metrics.family(name, Kind::Gauge, help);
+        for state in STATES {
+            let label = state.to_string();
+            let count = counts.get(&state).copied().unwrap_or(0);
+            metrics.sample(name, &[("state", &label)], count);
+        }
+    }
+
+    metrics.family(
+        "pgdog_clients_rejected",
+        Kind::Counter,
+        "Clients rejected because of connection limits.",
+    );
+    metrics.sample("pgdog_clients_rejected_total", &[], rejected_total());
+}
+
+/// Query and prepared statement caches.
+fn caches(metrics: &mut Metrics) {
+    let stats = Cache::stats();
+
+    metrics.family("pgdog_query_cache_hits", Kind::Counter, "Query cache hits.");
+    metrics.sample("pgdog_query_cache_hits_total", &[], stats.hits);
+    metrics.family(
+        "pgdog_query_cache_misses",
+        Kind::Counter,
+        "Query cache misses.",
+    );
+    metrics.sample("pgdog_query_cache_misses_total", &[], stats.misses);
+    metrics.family(
+        "pgdog_query_cache_size",
+        Kind::Gauge,
+        "Queries in the query cache.",
+    );
+    metrics.sample("pgdog_query_cache_size", &[], Cache::len());
+    metrics.family(
+        "pgdog_prepared_statements",
+        Kind::Gauge,
+        "Prepared statements in the global cache.",
+    );
+    metrics.sample(
+        "pgdog_prepared_statements",
+        &[],
+        PreparedStatements::global().lock().len(),
+    );
+}
+
+/// Query, transaction and wait time histograms.
+fn histograms(metrics: &mut Metrics) {
+    let all = latency::all();
+
+    for (name, help) in [
+        ("pgdog_query_duration_seconds", "Query time."),
+        ("pgdog_transaction_duration_seconds", "Transaction time."),
+        (
+            "pgdog_wait_duration_seconds",
+            "Time spent waiting for a server connection.",
+        ),
+    ] {
+        metrics.family(name, Kind::Histogram, help);
+        for (user, latency) in &all {
+            let histogram = match name {
+                "pgdog_query_duration_seconds" => &latency.query,
+                "pgdog_transaction_duration_seconds" => &latency.transaction,
+                _ => &latency.wait,
+            };
+            metrics.histogram(
+                name,
+                &[("database", &user.database), ("user", &user.user)],
+                &histogram.snapshot(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::stats::AtomicHistogram;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("pgdog"), "pgdog");
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = AtomicHistogram::default();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(20));
+
+        let mut metrics = Metrics::default();
+        metrics.family("test_seconds", Kind::Histogram, "Test.");
+        metrics.histogram("test_seconds", &[("user", "pgdog")], &histogram.snapshot());
+        let out = metrics.finish();
+
+        assert!(out.starts_with("# TYPE test_seconds histogram\n# HELP test_seconds Test.\n"));
+        assert!(out.contains("test_seconds_bucket{user=\"pgdog\",le=\"0.001\"} 0\n"));
+        assert!(out.contains("test_seconds_bucket{user=\"pgdog\",le=\"0.005\"} 1\n"));
+        assert!(out.contains("test_seconds_bucket{user=\"pgdog\",le=\"0.025\"} 2\n"));
+        assert!(out.contains("test_seconds_bucket{user=\"pgdog\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("test_seconds_sum{user=\"pgdog\"} 0.023\n"));
+        assert!(out.contains("test_seconds_count{user=\"pgdog\"} 2\n"));
+        assert!(out.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let out = render();
+        assert!(out.contains("# TYPE pgdog_pool_banned gauge\n"));
+        assert!(out.contains("# TYPE pgdog_pool_queries counter\n"));
+        assert!(out.contains("pgdog_clients{state=\"idle in transaction\"}"));
+        assert!(out.contains("pgdog_query_cache_hits_total "));
+        assert!(out.contains("# TYPE pgdog_wait_duration_seconds histogram\n"));
+        assert!(out.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Query statistics by fingerprint, aggregated across all shards.
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+static QUERIES: Lazy<Mutex<HashMap<u64, QueryStats>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Fraction of the entries removed when the statistics are full, like `pg_stat_statements`.
+const EVICT: f64 = 0.05;
+
+/// Statistics of all queries with the same fingerprint.
+#[derive(Debug, Clone, Default)]
+pub struct QueryStats {
+    /// First query seen with this fingerprint, with constants replaced by parameters.
+    pub query: String,
+    /// Number of times the query was executed.
+    pub calls: usize,
+    /// Total execution time.
+    pub total_time: Duration,
+    /// Rows returned or affected.
+    pub rows: usize,
+    /// Number of executions that returned an error.
+    pub errors: usize,
+    /// Shards the query was sent to.
+    pub shards: BTreeSet<usize>,
+}
+
+impl QueryStats {
+    /// Mean execution time.
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total_time / self.calls as u32
+        }
+    }
+}
+
+/// Query executed by a client.
+#[derive(Debug)]
+pub struct Query {
+    fingerprint: u64,
+    rows: usize,
+    error: bool,
+}
+
+impl Query {
+    /// Start tracking a query sent to the shards.
+    ///
+    /// Statistics are kept for up to `max` fingerprints. If there is no room,
+    /// the least executed ones are removed.
+    pub fn start(
+        fingerprint: u64,
+        query: &str,
+        shards: impl IntoIterator<Item = usize>,
+        max: usize,
+    ) -> Self {
+        let mut guard = QUERIES.lock();
+        if !guard.contains_key(&fingerprint) {
+            // Normalize without holding the lock.
+            drop(guard);
+            let query = pg_query::normalize(query).unwrap_or_default();
+            guard = QUERIES.lock();
+
+            if guard.len() >= max {
+                evict(&mut guard, max);
+            }
+            guard.entry(fingerprint).or_insert_with(|| QueryStats {
+                query,
+                ..Default::default()
+            });
+        }
+        if let Some(entry) = guard.get_mut(&fingerprint) {
+            entry.shards.extend(shards);
+        }
+
+        Self {
+            fingerprint,
+            rows: 0,
+            error: false,
+        }
+    }
+
+    /// Rows returned or affected by the query.
+    pub fn rows(&mut self, rows: usize) {
+        self.rows += rows;
+    }
+
+    /// Query returned an error, possibly from more than one shard.
+    pub fn error(&mut self) {
+        self.error = true;
+    }
+
+    /// Query finished executing.
+    pub fn finish(self, time: Duration) {
+        // The entry is gone if the stats were reset.
+        if let Some(entry) = QUERIES.lock().get_mut(&self.fingerprint) {
+            entry.calls += 1;
+            entry.total_time += time;
+            entry.rows += self.rows;
+            entry.errors += self.error as usize;
+        }
+    }
+}
+
+/// Remove the least executed queries to make room for new ones.
+fn evict(queries: &mut HashMap<u64, QueryStats>, max: usize) {
+    let remove = (queries.len() + 1)
+        .saturating_sub(max)
+        .max((max as f64 * EVICT) as usize)
+        .max(1);
+    let mut calls = queries
+        .iter()
+        .map(|(fingerprint, stats)| (stats.calls, *fingerprint))
+        .collect::<Vec<_>>();
+    calls.sort_unstable();
+    for (_, fingerprint) in calls.into_iter().take(remove) {
+        queries.remove(&fingerprint);
+    }
+}
+
+/// Get statistics for all queries, slowest first.
+pub fn queries() -> Vec<(u64, QueryStats)> {
+    let mut queries = QUERIES
+        .lock()
+        .iter()
+        .map(|(fingerprint, stats)| (*fingerprint, stats.clone()))
+        .collect::<Vec<_>>();
+    queries.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total_time));
+    queries
+}
+
+/// Reset statistics.
+pub fn reset() {
+    let mut guard = QUERIES.lock();
+    guard.clear();
+    guard.shrink_to_fit();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_query_stats() {
+        let fingerprint = 0xdeadbeef;
+
+        let mut query = Query::start(fingerprint, "SELECT * FROM users WHERE id = 1", [0], 10);
+        query.rows(1);
+        query.finish(Duration::from_millis(4));
+
+        let mut query = Query::start(fingerprint, "SELECT * FROM users WHERE id = 2", [1], 10);
+        query.error();
+        query.error();
+        query.finish(Duration::from_millis(2));
+
+        let (_, stats) = queries()
+            .into_iter()
+            .find(|(f, _)| *f == fingerprint)
+            .unwrap();
+        assert_eq!(stats.query, "SELECT * FROM users WHERE id = $1");
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.rows, 1);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.total_time, Duration::from_millis(6));
+        assert_eq!(stats.mean_time(), Duration::from_millis(3));
+        assert_eq!(stats.shards, BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut queries = HashMap::new();
+        for fingerprint in 0..100 {
+            queries.insert(
+                fingerprint,
+                QueryStats {
+                    calls: fingerprint as usize,
+                    ..Default::default()
+                },
+            );
+        }
+
+        evict(&mut queries, 100);
+        assert_eq!(queries.len(), 95);
+        assert!(queries.keys().all(|fingerprint| *fingerprint >= 5));
+
+        // Limit lowered on reload.
+        evict(&mut queries, 10);
+        assert_eq!(queries.len(), 9);
+        assert!(queries.keys().all(|fingerprint| *fingerprint >= 91));
+    }
+}
@@ -4,7 +4,7 @@
 use once_cell::sync::Lazy;
 use pg_query::{parse, NodeEnum};
 use pgdog_plugin::bindings::{Config, Input, Output};
-use pgdog_plugin::Route;
+use pgdog_plugin::{logging, Route};
 
 use tracing::{debug, level_filters::LevelFilter};
 use tracing::{error, trace};
@@ -22,22 +22,26 @@ pub mod sharding_function;
 
 #[no_mangle]
 pub extern "C" fn pgdog_init() {
-    let format = fmt::layer()
-        .with_ansi(std::io::stderr().is_terminal())
-        .with_file(false);
+    // Use the same output format as pgDog.
+    let json = logging::json();
+
+    let text = (!json).then(|| {
+        fmt::layer()
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_file(false)
+    });
+    let json = json.then(|| fmt::layer().json().flatten_event(true).with_file(false));
 
     let filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
 
     tracing_subscriber::registry()
-        .with(format)
+        .with(text)
+        .with(json)
         .with(filter)
         .init();
 
-    // TODO: This is more for fun/demo, but in prod, we want
-    // this logger to respect options passed to pgDog proper, e.g.
-    // use JSON output.
     debug!("🐕 pgDog routing plugin v{}", env!("CARGO_PKG_VERSION"));
 }
 
@@ -24,7 +24,7 @@ fn hash_slice(k: &[u8]) -> u64 {
 /// Calculate shard for a BIGINT value.
 pub fn bigint(value: i64, shards: usize) -> usize {
     let hash = unsafe { hashint8extended(value) };
-    let combined = unsafe { hash_combine64(0, hash as u64) };
+    let combined = unsafe { hash_combine64(0, hash) };
 
     combined as usize % shards
 }